@@ -1,11 +1,21 @@
-use std::{future::Future, ops::Deref, pin::Pin, sync::Arc, task::Poll};
+use std::{
+    future::Future,
+    ops::Deref,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::Poll,
+    time::{Duration, Instant},
+};
 
 use crate::{
     ext::{AsyncReadExt, AsyncWriteExt},
-    AsyncRead, AsyncWrite, NetSocket,
+    time, AsyncRead, AsyncWrite, Kind, NetSocket,
 };
 
-type BoxedFuture = Pin<Box<dyn Future<Output = crate::Result<()>> + Send + 'static>>;
+type BoxedFuture<T> = Pin<Box<dyn Future<Output = crate::Result<T>> + Send + 'static>>;
 
 macro_rules! unwrap {
     ($r: expr) => {
@@ -16,8 +26,41 @@ macro_rules! unwrap {
     };
 }
 
+/// 半关闭后另一个方向默认允许空闲的时间
+const DEFAULT_LINGER: Duration = Duration::from_secs(30);
+
+/// 双向转发, 一个方向读到eof后关闭对端的写(半关闭), 另一个方向继续转发直到也结束
 pub struct Forward {
-    futures: Vec<BoxedFuture>,
+    /// s1 -> s2 与 s2 -> s1, 结束后为None
+    copies: [Option<BoxedFuture<u64>>; 2],
+    transferred: Transferred,
+    watchdog: Option<BoxedFuture<()>>,
+    activity: Arc<Activity>,
+    /// 没有空闲超时时, 半关闭后另一个方向没有数据超过linger则结束转发
+    linger: Duration,
+    lingering: Option<BoxedFuture<()>>,
+}
+
+/// 每个方向转发的字节数
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Transferred {
+    /// s1 -> s2
+    pub forward: u64,
+    /// s2 -> s1
+    pub backward: u64,
+}
+
+/// 读写时记录活动的流, 用于不经过forward转发的连接, 例如udp转发
+pub struct Watched<S> {
+    stream: S,
+    activity: Arc<Activity>,
+}
+
+/// 最后一次转发数据的时间
+struct Activity {
+    start: Instant,
+    /// 距离start的毫秒数
+    last: AtomicU64,
 }
 
 pub struct Inner<S>(std::sync::Mutex<S>);
@@ -64,30 +107,132 @@ where
     }
 }
 
+impl Activity {
+    fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            last: AtomicU64::new(0),
+        }
+    }
+
+    fn touch(&self) {
+        let elapsed = self.start.elapsed().as_millis() as u64;
+        self.last.store(elapsed, Ordering::Relaxed);
+    }
+
+    fn idle(&self) -> Duration {
+        let last = Duration::from_millis(self.last.load(Ordering::Relaxed));
+        self.start.elapsed().saturating_sub(last)
+    }
+
+    /// 等待没有活动超过timeout, 返回实际空闲的时间
+    async fn quiet(&self, timeout: Duration) -> Duration {
+        loop {
+            let idle = self.idle();
+
+            if idle >= timeout {
+                break idle;
+            }
+
+            time::sleep(timeout - idle).await;
+        }
+    }
+
+    /// 没有活动超过timeout时返回 Kind::Idle
+    fn watchdog(self: Arc<Self>, timeout: Duration) -> BoxedFuture<()> {
+        Box::pin(async move {
+            let idle = self.quiet(timeout).await;
+            log::debug!("forward idle for {:?}, close it", idle);
+            Err(Kind::Idle(timeout).into())
+        })
+    }
+}
+
+impl Forward {
+    /// 半关闭后另一个方向没有数据超过linger时结束转发, 两端随之关闭
+    pub fn linger(mut self, linger: Duration) -> Self {
+        self.linger = linger;
+        self
+    }
+
+    /// 两个方向都没有数据超过timeout时结束转发并返回 Kind::Idle, 两端随之关闭
+    pub fn idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        if let Some(timeout) = timeout.filter(|timeout| !timeout.is_zero()) {
+            self.watchdog = Some(self.activity.clone().watchdog(timeout));
+        }
+
+        self
+    }
+}
+
+/// 记录stream的读写, 返回的future在两个方向都没有数据超过timeout时返回 Kind::Idle,
+/// 没有空闲超时时返回None
+pub fn watch<S>(stream: S, timeout: Option<Duration>) -> (Watched<S>, Option<BoxedFuture<()>>) {
+    let activity = Arc::new(Activity::new());
+    let watchdog = timeout
+        .filter(|timeout| !timeout.is_zero())
+        .map(|timeout| activity.clone().watchdog(timeout));
+
+    (Watched { stream, activity }, watchdog)
+}
+
 impl Future for Forward {
-    type Output = crate::Result<()>;
+    type Output = crate::Result<Transferred>;
 
     fn poll(
         mut self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Self::Output> {
-        let mut futures = Vec::new();
-        while let Some(mut future) = self.futures.pop() {
-            match Pin::new(&mut future).poll(cx) {
-                Poll::Pending => futures.push(future),
-                Poll::Ready(r) => {
-                    return Poll::Ready(r);
+        for direction in 0..2 {
+            if let Some(copy) = self.copies[direction].as_mut() {
+                if let Poll::Ready(n) = Pin::new(copy).poll(cx) {
+                    let n = n?;
+
+                    if direction == 0 {
+                        self.transferred.forward = n;
+                    } else {
+                        self.transferred.backward = n;
+                    }
+
+                    drop(self.copies[direction].take());
                 }
             }
         }
 
-        drop(std::mem::replace(&mut self.futures, futures));
+        if self.copies.iter().all(Option::is_none) {
+            log::debug!(
+                "forward finished, {}bytes forward, {}bytes backward",
+                self.transferred.forward,
+                self.transferred.backward
+            );
+            return Poll::Ready(Ok(self.transferred));
+        }
 
-        if self.futures.is_empty() {
-            Poll::Ready(Ok(()))
-        } else {
-            Poll::Pending
+        if let Some(watchdog) = self.watchdog.as_mut() {
+            return match Pin::new(watchdog).poll(cx) {
+                Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+                _ => Poll::Pending,
+            };
         }
+
+        // 没有空闲超时时, 避免半关闭的连接一直等待对端关闭, 另一个方向仍在转发时不会结束
+        if self.copies.iter().any(Option::is_none) {
+            let linger = self.linger;
+            let activity = self.activity.clone();
+            let lingering = self.lingering.get_or_insert_with(|| {
+                Box::pin(async move {
+                    activity.quiet(linger).await;
+                    Ok(())
+                })
+            });
+
+            if Pin::new(lingering).poll(cx).is_ready() {
+                log::debug!("forward half closed for {:?}, close it", linger);
+                return Poll::Ready(Ok(self.transferred));
+            }
+        }
+
+        Poll::Pending
     }
 }
 
@@ -99,7 +244,7 @@ where
     let (s1_reader, s1_writer) = split(s1);
     let (s2_reader, s2_writer) = split(s2);
 
-    fn copy<R, W>(mut reader: R, mut writer: W) -> BoxedFuture
+    fn copy<R, W>(mut reader: R, mut writer: W, activity: Arc<Activity>) -> BoxedFuture<u64>
     where
         R: AsyncRead + Unpin + Send + 'static,
         W: AsyncWrite + Unpin + Send + 'static,
@@ -111,6 +256,8 @@ where
                 buf
             };
 
+            let mut total = 0;
+
             loop {
                 let r = reader.read(&mut buf).await;
 
@@ -121,12 +268,20 @@ where
                 let n = unsafe { r.unwrap_unchecked() };
 
                 if n == 0 {
+                    // 半关闭时重新开始计算linger
+                    activity.touch();
+                    // 只关闭对端的写, 对端仍然可以继续响应
                     let _ = writer.flush().await;
-                    return writer.close().await;
+                    if let Err(e) = writer.close().await {
+                        log::trace!("failed to close writer {}", e);
+                    }
+                    return Ok(total);
                 }
 
                 log::trace!("forward {}bytes data", n);
 
+                activity.touch();
+
                 let r = writer.write_all(&buf[..n]).await;
 
                 if r.is_err() {
@@ -136,12 +291,96 @@ where
                         err
                     });
                 }
+
+                // 写入可能因为对端较慢而等待, 完成后同样算作活动
+                activity.touch();
+
+                total += n as u64;
             }
         })
     }
 
+    let activity = Arc::new(Activity::new());
+
     Forward {
-        futures: vec![copy(s1_reader, s2_writer), copy(s2_reader, s1_writer)],
+        copies: [
+            Some(copy(s1_reader, s2_writer, activity.clone())),
+            Some(copy(s2_reader, s1_writer, activity.clone())),
+        ],
+        transferred: Transferred::default(),
+        watchdog: None,
+        activity,
+        linger: DEFAULT_LINGER,
+        lingering: None,
+    }
+}
+
+impl<S> NetSocket for Watched<S>
+where
+    S: NetSocket,
+{
+    fn peer_addr(&self) -> crate::Result<crate::Address> {
+        self.stream.peer_addr()
+    }
+
+    fn local_addr(&self) -> crate::Result<crate::Address> {
+        self.stream.local_addr()
+    }
+}
+
+impl<S> AsyncRead for Watched<S>
+where
+    S: AsyncRead + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut crate::ReadBuf<'_>,
+    ) -> Poll<crate::Result<usize>> {
+        let poll = Pin::new(&mut self.stream).poll_read(cx, buf);
+
+        if let Poll::Ready(Ok(n)) = poll {
+            if n > 0 {
+                self.activity.touch();
+            }
+        }
+
+        poll
+    }
+}
+
+impl<S> AsyncWrite for Watched<S>
+where
+    S: AsyncWrite + Unpin,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> Poll<crate::Result<usize>> {
+        let poll = Pin::new(&mut self.stream).poll_write(cx, buf);
+
+        if let Poll::Ready(Ok(n)) = poll {
+            if n > 0 {
+                self.activity.touch();
+            }
+        }
+
+        poll
+    }
+
+    fn poll_close(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<crate::Result<()>> {
+        Pin::new(&mut self.stream).poll_close(cx)
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<crate::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
     }
 }
 
@@ -217,3 +456,169 @@ impl<T> Clone for WriteHalf<T> {
         Self(self.0.clone())
     }
 }
+
+#[cfg(test)]
+#[cfg(feature = "fuso-rt-tokio")]
+mod tests {
+    use std::time::Duration;
+
+    use super::Transferred;
+    use crate::ext::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn test_forward_half_close() {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let (mut visitor, s1) = tokio::io::duplex(64);
+                let (s2, mut target) = tokio::io::duplex(64);
+
+                let forward = tokio::spawn(super::forward(s1, s2));
+
+                visitor.write_all(b"request").await.unwrap();
+                visitor.close().await.unwrap();
+
+                let mut buf = [0u8; 7];
+                target.read_exact(&mut buf).await.unwrap();
+                assert_eq!(target.read(&mut buf).await.unwrap(), 0);
+
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                target.write_all(b"response").await.unwrap();
+                target.close().await.unwrap();
+
+                let mut buf = [0u8; 8];
+                visitor.read_exact(&mut buf).await.unwrap();
+                assert_eq!(&buf, b"response");
+                assert_eq!(visitor.read(&mut buf).await.unwrap(), 0);
+
+                let transferred = forward.await.unwrap().unwrap();
+                assert_eq!(
+                    transferred,
+                    Transferred {
+                        forward: 7,
+                        backward: 8
+                    }
+                );
+            });
+    }
+
+    #[test]
+    fn test_forward_linger() {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let (mut visitor, s1) = tokio::io::duplex(64);
+                let (s2, mut target) = tokio::io::duplex(64);
+
+                let linger = Duration::from_millis(200);
+                let forward = tokio::spawn(super::forward(s1, s2).linger(linger));
+
+                visitor.write_all(b"request").await.unwrap();
+                visitor.close().await.unwrap();
+
+                let mut buf = [0u8; 7];
+                target.read_exact(&mut buf).await.unwrap();
+                assert_eq!(target.read(&mut buf).await.unwrap(), 0);
+
+                // 对端一直不关闭, linger之后结束转发
+                let transferred = tokio::time::timeout(linger * 5, forward)
+                    .await
+                    .unwrap()
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(transferred.forward, 7);
+                assert_eq!(visitor.read(&mut buf).await.unwrap(), 0);
+            });
+    }
+
+    #[test]
+    fn test_forward_linger_streaming() {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let (mut visitor, s1) = tokio::io::duplex(64);
+                let (s2, mut target) = tokio::io::duplex(64);
+
+                let linger = Duration::from_millis(200);
+                let forward = tokio::spawn(super::forward(s1, s2).linger(linger));
+
+                visitor.write_all(b"request").await.unwrap();
+                visitor.close().await.unwrap();
+
+                let mut buf = [0u8; 7];
+                target.read_exact(&mut buf).await.unwrap();
+                assert_eq!(target.read(&mut buf).await.unwrap(), 0);
+
+                // 另一个方向持续有数据时, 超过linger也不会结束
+                for _ in 0..6 {
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    target.write_all(b"chunk").await.unwrap();
+                    let mut buf = [0u8; 5];
+                    visitor.read_exact(&mut buf).await.unwrap();
+                    assert!(!forward.is_finished());
+                }
+
+                // 停止后空闲linger才结束
+                let transferred = tokio::time::timeout(linger * 5, forward)
+                    .await
+                    .unwrap()
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(transferred.forward, 7);
+                assert_eq!(visitor.read(&mut buf).await.unwrap(), 0);
+            });
+    }
+
+    #[test]
+    fn test_forward_idle_timeout() {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let (mut visitor, s1) = tokio::io::duplex(64);
+                let (s2, mut target) = tokio::io::duplex(64);
+
+                let timeout = Duration::from_millis(200);
+                let forward = tokio::spawn(super::forward(s1, s2).idle_timeout(Some(timeout)));
+
+                for _ in 0..3 {
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    visitor.write_all(b"ping").await.unwrap();
+                    let mut buf = [0u8; 4];
+                    target.read_exact(&mut buf).await.unwrap();
+                }
+
+                let err = forward.await.unwrap().unwrap_err();
+                assert!(err.is_idle());
+
+                let mut buf = [0u8; 4];
+                assert_eq!(visitor.read(&mut buf).await.unwrap(), 0);
+                assert_eq!(target.read(&mut buf).await.unwrap(), 0);
+            });
+    }
+
+    #[test]
+    fn test_watch_idle_timeout() {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let (mut peer, stream) = tokio::io::duplex(64);
+
+                let timeout = Duration::from_millis(200);
+                let (mut stream, watchdog) = super::watch(stream, Some(timeout));
+                let watchdog = tokio::spawn(watchdog.unwrap());
+
+                for _ in 0..3 {
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    peer.write_all(b"ping").await.unwrap();
+                    let mut buf = [0u8; 4];
+                    stream.read_exact(&mut buf).await.unwrap();
+                    assert!(!watchdog.is_finished());
+                }
+
+                let err = watchdog.await.unwrap().unwrap_err();
+                assert!(err.is_idle());
+
+                assert!(super::watch(peer, None).1.is_none());
+            });
+    }
+}
@@ -23,6 +23,20 @@ impl Join {
         }
     }
 
+    /// 同时等待所有的future
+    pub fn all<I, F>(futures: I) -> Self
+    where
+        I: IntoIterator<Item = F>,
+        F: Future<Output = ()> + Send + 'static,
+    {
+        Join {
+            futures: futures
+                .into_iter()
+                .map(|fut| Box::pin(fut) as BoxedFuture<()>)
+                .collect(),
+        }
+    }
+
     pub fn add<F>(mut self, fut: F) -> Self
     where
         F: Future<Output = ()> + Send + 'static,
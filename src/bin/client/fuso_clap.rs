@@ -1,15 +1,32 @@
 use std::net::IpAddr;
+use std::sync::Arc;
 use std::time::Duration;
 
 use clap::ArgAction;
 use clap::Parser;
-use fuso::penetrate::PenetrateRsaAndAesHandshake;
+#[cfg(feature = "fuso-toml")]
+use clap::{ArgMatches, CommandFactory, FromArgMatches};
+use fuso::client::{Failover, ReconnectPolicy};
+use fuso::guard::Bandwidth;
+use fuso::penetrate::client::Mapping;
+use fuso::penetrate::{
+    AccessList, Balance, Cidr, ClientHandshakeConfig, PenetrateRsaAndAesHandshake, ProxyProtocol,
+    ServerPinning,
+};
+use fuso::Addr;
+use fuso::Executor;
+use fuso::FusoExecutor;
 use fuso::FusoPenetrateConnector;
 use fuso::Socket;
+use fuso::WrappedProvider;
 
 #[derive(Parser)]
 #[clap(author, version, about)]
 struct FusoArgs {
+    /// 配置文件, 命令行参数优先于配置文件
+    #[cfg(feature = "fuso-toml")]
+    #[clap(short, long, display_order = 1)]
+    config: Option<String>,
     /// 是否启用 kcp, 默认不启用
     #[clap(long, default_value = "false", action = ArgAction::SetTrue, display_order=1)]
     kcp: bool,
@@ -39,16 +56,18 @@ struct FusoArgs {
     /// 桥接监听端口
     #[clap(long, visible_alias = "bp", display_order = 6)]
     bridge_port: Option<u16>,
-    /// 服务端地址
-    #[cfg(debug_assertions)]
-    #[clap(default_value = "127.0.0.1")]
-    server_host: String,
-    /// 服务端地址
-    #[cfg(not(debug_assertions))]
-    server_host: String,
+    /// 服务端地址, 调试模式下默认为 127.0.0.1
+    #[cfg_attr(not(debug_assertions), clap(required_unless_present = "config"))]
+    server_host: Option<String>,
     /// 服务端端口
     #[clap(default_value = "6722")]
     server_port: u16,
+    /// 备用的服务端地址, 可以指定多次, 例如: 10.0.0.2:6722, 需要与主服务端使用相同的身份
+    #[clap(long, display_order = 4)]
+    backup_server: Vec<Addr>,
+    /// 服务端地址的切换方式, priority: 每轮从主服务端开始尝试, round-robin: 依次轮流尝试
+    #[clap(long, default_value = "priority", display_order = 4, possible_values = ["priority", "round-robin"])]
+    failover: Failover,
     /// 转发地址
     #[clap(
         long,
@@ -60,6 +79,49 @@ struct FusoArgs {
     /// 转发端口
     #[clap(long, default_value = "80", visible_alias = "fp", display_order = 8)]
     forward_port: u16,
+    /// 其他的转发地址, 可以指定多次, 例如: 127.0.0.1:8081, 与转发地址一起负载均衡
+    #[clap(long, display_order = 8)]
+    forward_replica: Vec<Addr>,
+    /// 多个转发地址之间的负载均衡方式
+    #[clap(long, default_value = "round-robin", display_order = 8, possible_values = ["round-robin", "least-conn", "ip-hash"])]
+    balance: Balance,
+    /// 转发地址健康检查的间隔(秒), 所有转发地址都不可用时暂停映射, 0表示不检查,
+    /// 不指定时只每10秒检查有多个转发地址的映射
+    #[clap(long, display_order = 8)]
+    health_check: Option<u64>,
+    /// 映射暂停时返回给http访问者的页面文件, 不指定时直接关闭访问者
+    #[clap(long, display_order = 8)]
+    suspend_page: Option<String>,
+    /// 绑定的域名, 通过服务端的共享http端口访问
+    #[clap(long, display_order = 8)]
+    domain: Option<String>,
+    /// 转发前向服务写入PROXY protocol头, 使服务获取访问者的真实ip
+    #[clap(long, display_order = 8, possible_values = ["v1", "v2"])]
+    proxy_protocol: Option<ProxyProtocol>,
+    /// 额外的端口映射, 可以指定多次, 例如: name=ssh,visit=2222,target=127.0.0.1:22
+    #[clap(long, display_order = 8)]
+    mapping: Vec<Mapping>,
+    /// 允许的访问者ip, 可以指定多次, 例如: 192.168.0.0/16, 由服务端检查
+    #[clap(long, display_order = 8)]
+    allow_ip: Vec<Cidr>,
+    /// 禁止的访问者ip, 可以指定多次, 优先于allow-ip
+    #[clap(long, display_order = 8)]
+    deny_ip: Vec<Cidr>,
+    /// 所有映射共享的带宽限制, 单位为字节每秒, 如 10M 或者 上传:下载 1M:10M
+    #[clap(long, display_order = 8)]
+    bandwidth: Option<Bandwidth>,
+    /// 同时访问的数量, 0表示不限制, 由服务端检查
+    #[clap(long, default_value = "0", display_order = 8)]
+    max_visitors: usize,
+    /// 访问者空闲超时(秒), 两个方向都没有数据超过该时间时关闭, 由服务端检查
+    #[clap(long, display_order = 8)]
+    idle_timeout: Option<u64>,
+    /// 访问者半关闭后另一个方向没有数据超过该时间(秒)时关闭
+    #[clap(long, default_value = "30", display_order = 8)]
+    linger: u64,
+    /// 是否启用多路复用, 所有映射共用一个连接, 默认不启用, 只在x25519握手时生效
+    #[clap(long, default_value = "false", action = ArgAction::SetTrue, display_order=2)]
+    mux: bool,
     /// 是否启用socks5 udp转发, 默认不启用
     #[clap(long, default_value = "false", visible_alias = "su", action = ArgAction::SetTrue, display_order=2)]
     socks_udp: bool,
@@ -69,6 +131,45 @@ struct FusoArgs {
     /// socks5密码
     #[clap(long, visible_alias = "s5p", display_order = 4)]
     socks_password: Option<String>,
+    /// 服务端验证密钥
+    #[clap(long, display_order = 4)]
+    secret: Option<String>,
+    /// 服务端身份指纹, 不指定时首次连接信任服务端并记录到 known-hosts
+    #[clap(long, display_order = 4)]
+    server_fingerprint: Option<String>,
+    /// 记录服务端指纹的文件
+    #[clap(long, default_value = "fuc_known_hosts", display_order = 4)]
+    known_hosts: String,
+    /// 加密套件, 按优先级指定多个: chacha20-poly1305, aes-256-gcm, aes-128-cbc(旧版本)
+    #[clap(long, display_order = 4)]
+    cipher: Vec<fuso::encryption::CipherSuite>,
+    /// 握手方式, 需要与服务端一致
+    #[clap(long, default_value = "rsa", display_order = 4, possible_values = ["rsa", "x25519"])]
+    handshake: String,
+    /// 使用tls连接服务端
+    #[cfg(feature = "fuso-tls")]
+    #[clap(long, default_value = "false", action = ArgAction::SetTrue, display_order = 4)]
+    tls: bool,
+    /// 校验服务端证书的ca, 不指定时使用内置的根证书
+    #[cfg(feature = "fuso-tls")]
+    #[clap(long, display_order = 4)]
+    tls_ca: Option<String>,
+    /// tls握手时使用的服务名, 默认使用服务端地址
+    #[cfg(feature = "fuso-tls")]
+    #[clap(long, display_order = 4)]
+    tls_sni: Option<String>,
+    /// 客户端证书, 服务端要求客户端证书时指定
+    #[cfg(feature = "fuso-tls")]
+    #[clap(long, requires = "tls-key", display_order = 4)]
+    tls_cert: Option<String>,
+    /// 客户端私钥
+    #[cfg(feature = "fuso-tls")]
+    #[clap(long, requires = "tls-cert", display_order = 4)]
+    tls_key: Option<String>,
+    /// prometheus指标监听地址, 如 127.0.0.1:9101, 不指定时不启用
+    #[cfg(feature = "fuso-metrics")]
+    #[clap(long, display_order = 4)]
+    metrics_listen: Option<std::net::SocketAddr>,
     /// 最大等待读取时间
     #[clap(long, default_value = "5", display_order = 11)]
     maximum_rtime: u64,
@@ -81,6 +182,21 @@ struct FusoArgs {
     /// 发送心跳延时
     #[clap(long, default_value = "30", display_order = 14)]
     heartbeat_delay: u64,
+    /// 第一次重连的延时(秒), 之后每次翻倍
+    #[clap(long, display_order = 14)]
+    reconnect_delay: Option<u64>,
+    /// 重连延时的上限(秒)
+    #[clap(long, default_value = "60", display_order = 14)]
+    reconnect_max_delay: u64,
+    /// 重连延时的随机抖动比例, 0到1之间, 避免大量客户端同时重连
+    #[clap(long, default_value = "0.2", display_order = 14)]
+    reconnect_jitter: f64,
+    /// 最大重连次数, 不指定时永不停止
+    #[clap(long, display_order = 14)]
+    maximum_retries: Option<usize>,
+    /// 收到SIGTERM或者Ctrl-C后等待正在转发的连接结束的最长时间(秒), 再次收到时立即退出
+    #[clap(long, default_value = "30", display_order = 14)]
+    shutdown_timeout: u64,
     /// 通信端口
     #[clap(long, default_value = "0", display_order = 15)]
     channel_port: u16,
@@ -96,8 +212,97 @@ struct FusoArgs {
     log_level: log::LevelFilter,
 }
 
+#[cfg(feature = "fuso-toml")]
+fn parse_args() -> fuso::Result<FusoArgs> {
+    use crate::fuso_toml::FusoConfig;
+
+    let matches: ArgMatches = FusoArgs::command().get_matches();
+    let mut args = FusoArgs::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    let config = match args.config.as_ref() {
+        None => return Ok(args),
+        Some(path) => FusoConfig::load(path)?,
+    };
+
+    merge!(
+        args,
+        matches,
+        config,
+        server_host,
+        server_port,
+        backup_server,
+        failover,
+        name,
+        kcp,
+        mux,
+        visit_bind_port,
+        forward_host,
+        forward_port,
+        forward_replica,
+        balance,
+        health_check,
+        suspend_page,
+        domain,
+        proxy_protocol,
+        mapping,
+        allow_ip,
+        deny_ip,
+        bandwidth,
+        max_visitors,
+        idle_timeout,
+        linger,
+        socks,
+        socks_udp,
+        socks_username,
+        socks_password,
+        bridge_listen,
+        bridge_port,
+        secret,
+        server_fingerprint,
+        known_hosts,
+        cipher,
+        handshake,
+        maximum_rtime,
+        maximum_wtime,
+        maximum_wctime,
+        heartbeat_delay,
+        reconnect_delay,
+        reconnect_max_delay,
+        reconnect_jitter,
+        maximum_retries,
+        shutdown_timeout,
+        channel_port,
+    );
+
+    #[cfg(feature = "fuso-tls")]
+    merge!(args, matches, config, tls, tls_ca, tls_sni, tls_cert, tls_key);
+
+    #[cfg(feature = "fuso-metrics")]
+    merge!(args, matches, config, metrics_listen);
+
+    #[cfg(feature = "fuc-log")]
+    merge!(args, matches, config, log_level);
+
+    Ok(args)
+}
+
+#[cfg(not(feature = "fuso-toml"))]
+fn parse_args() -> fuso::Result<FusoArgs> {
+    Ok(FusoArgs::parse())
+}
+
 pub async fn fuso_main() -> fuso::Result<()> {
-    let args = FusoArgs::parse();
+    let args = parse_args()?;
+
+    #[cfg(debug_assertions)]
+    let server_host = args
+        .server_host
+        .unwrap_or_else(|| String::from("127.0.0.1"));
+
+    #[cfg(not(debug_assertions))]
+    let server_host = args
+        .server_host
+        .ok_or_else(|| fuso::Kind::Message("the server host is not specified".into()))?;
 
     #[cfg(feature = "fuc-log")]
     env_logger::builder()
@@ -106,27 +311,127 @@ pub async fn fuso_main() -> fuso::Result<()> {
         .format_module_path(false)
         .init();
 
-    let fuso = fuso::builder_client()
-        .await?
-        .using_handshake(PenetrateRsaAndAesHandshake::Client)
+    let pinning = ServerPinning::new(format!("{}:{}", server_host, args.server_port))
+        .fingerprint(args.server_fingerprint)
+        .known_hosts(Some(args.known_hosts));
+
+    let config = Arc::new(
+        ClientHandshakeConfig::default()
+            .pinning(Some(pinning))
+            .ciphers(args.cipher),
+    );
+
+    let (handshake, bridge_handshake) = match args.handshake.as_str() {
+        #[cfg(feature = "fuso-crypt-x25519")]
+        "x25519" => (
+            WrappedProvider::wrap(fuso::penetrate::PenetrateX25519Handshake::ConfiguredClient(
+                config,
+            )),
+            WrappedProvider::wrap(fuso::penetrate::PenetrateX25519Handshake::Server),
+        ),
+        #[cfg(not(feature = "fuso-crypt-x25519"))]
+        "x25519" => {
+            return Err(fuso::Kind::Message(String::from(
+                "the x25519 handshake requires the fuso-crypt-x25519 feature",
+            ))
+            .into())
+        }
+        _ => (
+            WrappedProvider::wrap(PenetrateRsaAndAesHandshake::ConfiguredClient(config)),
+            WrappedProvider::wrap(PenetrateRsaAndAesHandshake::Server),
+        ),
+    };
+
+    // 多路复用的数据经过控制通道, rsa加密的控制通道吞吐量太低
+    let mux = args.mux && args.handshake == "x25519";
+
+    if args.mux && !mux {
+        log::warn!("mux requires the x25519 handshake, forward through new connections instead");
+    }
+
+    #[cfg(feature = "fuso-metrics")]
+    if let Some(listen) = args.metrics_listen {
+        let _ = FusoExecutor.spawn(fuso::metrics::Metrics::serve(listen));
+    }
+
+    #[cfg(feature = "fuso-tls")]
+    let tls = if args.tls {
+        let identity = args.tls_cert.zip(args.tls_key);
+        Some(fuso::tls::TlsClientConfig::load(
+            args.tls_ca,
+            identity,
+            args.tls_sni,
+        )?)
+    } else {
+        None
+    };
+
+    let suspend_page = args
+        .suspend_page
+        .map(|path| {
+            std::fs::read_to_string(&path).map_err(|e| {
+                fuso::Kind::Message(format!("failed to read suspend page {}: {}", path, e))
+            })
+        })
+        .transpose()?;
+
+    let builder = fuso::builder_client().await?;
+
+    #[cfg(feature = "fuso-tls")]
+    let builder = builder.using_tls(tls);
+
+    let builder = builder
+        .using_handshake(handshake)
         .using_penetrate(
             Socket::tcp(args.visit_bind_port),
             Socket::tcp((args.forward_host, args.forward_port)),
         )
-        .maximum_retries(None)
         .heartbeat_delay(Duration::from_secs(args.heartbeat_delay))
         .maximum_wait(Duration::from_secs(args.maximum_wctime))
         .set_name(args.name)
         .enable_kcp(args.kcp)
         .enable_socks5(args.socks)
         .enable_socks5_udp(args.socks_udp)
+        .enable_mux(mux)
         .channel_port(args.channel_port)
         .set_socks5_password(args.socks_password)
         .set_socks5_username(args.socks_username)
-        .build(
-            Socket::tcp((args.server_host, args.server_port)),
-            FusoPenetrateConnector::new().await?,
-        );
+        .set_secret(args.secret)
+        .set_domain(args.domain)
+        .set_proxy_protocol(args.proxy_protocol)
+        .set_mappings(args.mapping)
+        .set_access_list(
+            AccessList::default()
+                .allow(args.allow_ip)
+                .deny(args.deny_ip),
+        )
+        .set_bandwidth(args.bandwidth.unwrap_or_default())
+        .maximum_visitors(args.max_visitors)
+        .idle_timeout(args.idle_timeout.map(Duration::from_secs))
+        .linger(Duration::from_secs(args.linger))
+        .set_replicas(args.forward_replica.into_iter().map(Socket::tcp).collect())
+        .set_balance(args.balance)
+        .health_check(args.health_check.map(Duration::from_secs))
+        .set_suspend_page(suspend_page)
+        .backup_servers(args.backup_server.into_iter().map(Socket::tcp).collect())
+        .failover(args.failover);
+
+    let reconnect = ReconnectPolicy::default()
+        .maximum_delay(Duration::from_secs(args.reconnect_max_delay))
+        .jitter(args.reconnect_jitter)
+        .maximum_retries(args.maximum_retries);
+
+    let reconnect = match args.reconnect_delay {
+        None => reconnect,
+        Some(delay) => reconnect.initial_delay(Duration::from_secs(delay)),
+    };
+
+    let builder = builder.reconnect_policy(reconnect);
+
+    let fuso = builder.build(
+        Socket::tcp((server_host, args.server_port)),
+        FusoPenetrateConnector::new().await?,
+    );
 
     let fuso = match args.bridge_port {
         None => fuso.run(),
@@ -134,10 +439,15 @@ pub async fn fuso_main() -> fuso::Result<()> {
             .using_bridge(
                 Socket::tcp((args.bridge_listen, port)),
                 fuso::FusoAccepter,
-                PenetrateRsaAndAesHandshake::Server,
+                bridge_handshake,
             )
             .run(),
     };
 
+    let _ = FusoExecutor.spawn(
+        fuso.shutdown()
+            .on_signal(Duration::from_secs(args.shutdown_timeout)),
+    );
+
     fuso.await
 }
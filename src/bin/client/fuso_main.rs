@@ -1,4 +1,10 @@
+#[cfg(feature = "fuso-toml")]
+#[macro_use]
+#[path = "../common/mod.rs"]
+mod common;
 mod fuso_clap;
+#[cfg(feature = "fuso-toml")]
+mod fuso_toml;
 
 #[cfg(feature = "fuso-clap")]
 use fuso_clap as fuc;
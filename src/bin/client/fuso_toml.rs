@@ -0,0 +1,230 @@
+use std::{net::IpAddr, time::Duration};
+
+use fuso::{
+    client::Failover,
+    encryption::CipherSuite,
+    guard::Bandwidth,
+    penetrate::{client::Mapping, AccessList, Balance, Cidr, ProxyProtocol},
+    Addr, Socket,
+};
+use serde::{Deserialize, Deserializer};
+
+use crate::common::{parse, parse_vec};
+
+/// 客户端配置文件, 字段名与命令行参数相同(`-` 换成 `_`), 命令行参数优先
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FusoConfig {
+    pub server_host: Option<String>,
+    pub server_port: Option<u16>,
+    #[serde(deserialize_with = "parse_vec")]
+    pub backup_server: Option<Vec<Addr>>,
+    #[serde(deserialize_with = "parse")]
+    pub failover: Option<Failover>,
+    pub name: Option<String>,
+    pub kcp: Option<bool>,
+    pub mux: Option<bool>,
+    pub visit_bind_port: Option<u16>,
+    pub forward_host: Option<String>,
+    pub forward_port: Option<u16>,
+    #[serde(deserialize_with = "parse_vec")]
+    pub forward_replica: Option<Vec<Addr>>,
+    #[serde(deserialize_with = "parse")]
+    pub balance: Option<Balance>,
+    pub health_check: Option<u64>,
+    pub suspend_page: Option<String>,
+    pub domain: Option<String>,
+    #[serde(deserialize_with = "parse")]
+    pub proxy_protocol: Option<ProxyProtocol>,
+    #[serde(deserialize_with = "mappings")]
+    pub mapping: Option<Vec<Mapping>>,
+    #[serde(deserialize_with = "parse_vec")]
+    pub allow_ip: Option<Vec<Cidr>>,
+    #[serde(deserialize_with = "parse_vec")]
+    pub deny_ip: Option<Vec<Cidr>>,
+    #[serde(deserialize_with = "parse")]
+    pub bandwidth: Option<Bandwidth>,
+    pub max_visitors: Option<usize>,
+    pub idle_timeout: Option<u64>,
+    pub linger: Option<u64>,
+    pub shutdown_timeout: Option<u64>,
+    pub socks: Option<bool>,
+    pub socks_udp: Option<bool>,
+    pub socks_username: Option<String>,
+    pub socks_password: Option<String>,
+    pub bridge_listen: Option<IpAddr>,
+    pub bridge_port: Option<u16>,
+    pub secret: Option<String>,
+    pub server_fingerprint: Option<String>,
+    pub known_hosts: Option<String>,
+    #[serde(deserialize_with = "parse_vec")]
+    pub cipher: Option<Vec<CipherSuite>>,
+    pub handshake: Option<String>,
+    #[cfg(feature = "fuso-tls")]
+    pub tls: Option<bool>,
+    #[cfg(feature = "fuso-tls")]
+    pub tls_ca: Option<String>,
+    #[cfg(feature = "fuso-tls")]
+    pub tls_sni: Option<String>,
+    #[cfg(feature = "fuso-tls")]
+    pub tls_cert: Option<String>,
+    #[cfg(feature = "fuso-tls")]
+    pub tls_key: Option<String>,
+    #[cfg(feature = "fuso-metrics")]
+    pub metrics_listen: Option<std::net::SocketAddr>,
+    pub maximum_rtime: Option<u64>,
+    pub maximum_wtime: Option<u64>,
+    pub maximum_wctime: Option<u64>,
+    pub heartbeat_delay: Option<u64>,
+    pub reconnect_delay: Option<u64>,
+    pub reconnect_max_delay: Option<u64>,
+    pub reconnect_jitter: Option<f64>,
+    pub maximum_retries: Option<usize>,
+    pub channel_port: Option<u16>,
+    #[serde(deserialize_with = "parse")]
+    pub log_level: Option<log::LevelFilter>,
+}
+
+/// 额外的端口映射, 对应配置文件中的 [[mapping]]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MappingConfig {
+    name: String,
+    target: Targets,
+    #[serde(default)]
+    visit: u16,
+    #[serde(default, deserialize_with = "parse")]
+    proxy_protocol: Option<ProxyProtocol>,
+    #[serde(default)]
+    socks: bool,
+    #[serde(default)]
+    socks_udp: bool,
+    socks_username: Option<String>,
+    socks_password: Option<String>,
+    #[serde(default, deserialize_with = "parse_vec")]
+    allow_ip: Option<Vec<Cidr>>,
+    #[serde(default, deserialize_with = "parse_vec")]
+    deny_ip: Option<Vec<Cidr>>,
+    #[serde(default, deserialize_with = "parse")]
+    bandwidth: Option<Bandwidth>,
+    #[serde(default)]
+    max_visitors: usize,
+    idle_timeout: Option<u64>,
+    #[serde(default, deserialize_with = "parse")]
+    balance: Option<Balance>,
+    suspend_page: Option<String>,
+}
+
+/// 单个转发目标, 或者负载均衡的多个转发目标
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Targets {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl FusoConfig {
+    pub fn load(path: &str) -> fuso::Result<Self> {
+        crate::common::load(path)
+    }
+}
+
+fn mappings<'de, D>(deserializer: D) -> Result<Option<Vec<Mapping>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<Vec<MappingConfig>>::deserialize(deserializer)?
+        .map(|mappings| {
+            mappings
+                .into_iter()
+                .map(|mapping| {
+                    let targets = match mapping.target {
+                        Targets::One(target) => vec![target],
+                        Targets::Many(targets) => targets,
+                    };
+
+                    let mut targets = targets
+                        .iter()
+                        .map(|target| target.parse::<Addr>().map(Socket::tcp))
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(serde::de::Error::custom)?
+                        .into_iter();
+
+                    let target = targets.next().ok_or_else(|| {
+                        serde::de::Error::custom(format!(
+                            "the mapping {} requires a target",
+                            mapping.name
+                        ))
+                    })?;
+
+                    let suspend_page = mapping
+                        .suspend_page
+                        .map(|path| {
+                            std::fs::read_to_string(&path).map_err(|e| {
+                                serde::de::Error::custom(format!(
+                                    "failed to read suspend page {}: {}",
+                                    path, e
+                                ))
+                            })
+                        })
+                        .transpose()?;
+
+                    Ok(targets
+                        .fold(
+                            Mapping::new(&mapping.name, Socket::tcp(mapping.visit), target),
+                            Mapping::add_replica,
+                        )
+                        .enable_socks5(mapping.socks)
+                        .enable_socks5_udp(mapping.socks_udp)
+                        .set_socks5_username(mapping.socks_username)
+                        .set_socks5_password(mapping.socks_password)
+                        .set_proxy_protocol(mapping.proxy_protocol)
+                        .set_access_list(
+                            AccessList::default()
+                                .allow(mapping.allow_ip.unwrap_or_default())
+                                .deny(mapping.deny_ip.unwrap_or_default()),
+                        )
+                        .set_bandwidth(mapping.bandwidth.unwrap_or_default())
+                        .maximum_visitors(mapping.max_visitors)
+                        .idle_timeout(mapping.idle_timeout.map(Duration::from_secs))
+                        .set_balance(mapping.balance.unwrap_or_default())
+                        .set_suspend_page(suspend_page))
+                })
+                .collect()
+        })
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FusoConfig;
+
+    #[test]
+    fn test_mappings() {
+        let config: FusoConfig = toml::from_str(
+            r#"
+            server_host = "example.com"
+            forward_port = 8080
+
+            [[mapping]]
+            name = "ssh"
+            target = "127.0.0.1:22"
+            visit = 10022
+
+            [[mapping]]
+            name = "web"
+            target = ["127.0.0.1:8080", "127.0.0.1:8081"]
+            balance = "round-robin"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.server_host.as_deref(), Some("example.com"));
+        assert_eq!(config.mapping.map(|mappings| mappings.len()), Some(2));
+
+        let e = toml::from_str::<FusoConfig>("[[mapping]]\nname = \"ssh\"\ntarget = []")
+            .err()
+            .unwrap();
+        assert!(e.to_string().contains("the mapping ssh requires a target"));
+    }
+}
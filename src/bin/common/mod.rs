@@ -0,0 +1,149 @@
+//! 客户端与服务端共用的配置文件解析
+
+use std::{fmt::Display, str::FromStr};
+
+use fuso::Kind;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer};
+
+/// 命令行未指定的参数使用配置文件中的值
+macro_rules! merge {
+    ($args: expr, $matches: expr, $config: expr, $($field: ident),* $(,)?) => {
+        $(
+            let id = stringify!($field).replace('_', "-");
+            if let Some(value) = $config.$field {
+                if $matches.value_source(id.as_str()) != Some(clap::ValueSource::CommandLine) {
+                    $args.$field = value.into();
+                }
+            }
+        )*
+    };
+}
+
+/// 读取toml格式的配置文件, 错误中包含出错的字段与行号
+pub fn load<T: DeserializeOwned>(path: &str) -> fuso::Result<T> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| Kind::Message(format!("failed to read {}: {}", path, e)))?;
+
+    from_str(&content)
+        .map_err(|e| Kind::Message(format!("invalid configuration {}: {}", path, e)).into())
+}
+
+fn from_str<T: DeserializeOwned>(content: &str) -> Result<T, String> {
+    toml::from_str(content).map_err(|e| locate(content, e))
+}
+
+/// 字段值解析失败或者未知字段时toml只能定位到表的开始(第1行第1列), 按照字段名找到所在的行
+fn locate(content: &str, e: toml::de::Error) -> String {
+    let message = e.to_string();
+
+    if e.line_col() != Some((0, 0)) {
+        return message;
+    }
+
+    let key = ["for key `", "unknown field `"]
+        .iter()
+        .find_map(|prefix| message.split_once(prefix))
+        .and_then(|(_, rest)| rest.split_once('`'))
+        .and_then(|(key, _)| key.rsplit('.').next());
+
+    let line = key.and_then(|key| {
+        content.lines().position(|line| {
+            line.trim_start()
+                .strip_prefix(key)
+                .map_or(false, |rest| rest.trim_start().starts_with('='))
+        })
+    });
+
+    match (line, message.rsplit_once(" at line ")) {
+        (Some(line), Some((message, _))) => format!("{} at line {}", message, line + 1),
+        _ => message,
+    }
+}
+
+/// 使用FromStr解析字符串字段, 与命令行参数的格式相同
+pub fn parse<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|value| value.parse().map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+/// 使用FromStr解析字符串数组字段, 对应可以指定多次的命令行参数
+pub fn parse_vec<'de, D, T>(deserializer: D) -> Result<Option<Vec<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    Option::<Vec<String>>::deserialize(deserializer)?
+        .map(|values| {
+            values
+                .iter()
+                .map(|value| value.parse().map_err(serde::de::Error::custom))
+                .collect()
+        })
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use fuso::penetrate::{Cidr, PortRange};
+    use serde::Deserialize;
+
+    use super::{from_str, parse, parse_vec};
+
+    #[derive(Debug, Default, Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    struct Config {
+        port: Option<u16>,
+        #[serde(deserialize_with = "parse")]
+        port_range: Option<PortRange>,
+        #[serde(deserialize_with = "parse_vec")]
+        allow_ip: Option<Vec<Cidr>>,
+    }
+
+    #[test]
+    fn test_parse() {
+        let config: Config = toml::from_str(
+            r#"
+            port = 6722
+            port_range = "20000-20999"
+            allow_ip = ["10.0.0.0/8", "192.168.1.1"]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.port, Some(6722));
+        assert_eq!(config.port_range, "20000-20999".parse().ok());
+        assert_eq!(config.allow_ip.map(|ips| ips.len()), Some(2));
+
+        let config: Config = toml::from_str("").unwrap();
+        assert!(config.port.is_none() && config.allow_ip.is_none());
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let error = |content: &str| from_str::<Config>(content).unwrap_err();
+
+        assert_eq!(
+            error("port = 6722\nport_range = \"20999-20000\""),
+            "invalid port range 20999-20000 for key `port_range` at line 2"
+        );
+        assert_eq!(
+            error("port = 6722\n\n  allow_ip = [\"10.0.0.0/8\", \"x\"]"),
+            "invalid cidr x for key `allow_ip` at line 3"
+        );
+        assert!(error("port = 6722\nports = 1").ends_with("at line 2"));
+
+        // toml能够定位的错误保持不变
+        assert_eq!(
+            error("port = 6722\nport_range = 1"),
+            "invalid type: integer `1`, expected a string for key `port_range` at line 2 column 14"
+        );
+        assert!(error("port\n").contains("at line 1 column 5"));
+    }
+}
@@ -1,9 +1,22 @@
 use std::net::IpAddr;
 
-use clap::Parser;
+use clap::{ArgAction, ArgMatches, CommandFactory, FromArgMatches, Parser};
+use fuso::guard::{Bandwidth, Quota};
+use fuso::penetrate::{Cidr, PortRange, Reservation};
+
+#[cfg(feature = "fuso-toml")]
+#[macro_use]
+#[path = "../common/mod.rs"]
+mod common;
+#[cfg(feature = "fuso-toml")]
+mod fuso_toml;
 
 #[derive(Parser)]
 pub struct FusoArgs {
+    /// 配置文件, 命令行参数优先于配置文件
+    #[cfg(feature = "fuso-toml")]
+    #[clap(short, long)]
+    config: Option<String>,
     /// 监听的端口
     #[clap(short, long, default_value = "6722")]
     port: u16,
@@ -32,6 +45,175 @@ pub struct FusoArgs {
     /// 发送心跳延时
     #[clap(long, default_value = "30")]
     heartbeat_delay: u64,
+    /// 等待客户端建立连接的最大时间
+    #[clap(long, default_value = "10")]
+    maximum_wait: u64,
+    /// 访问者的数据无法识别时是否直接关闭连接
+    #[clap(long, default_value = "true", action = ArgAction::Set)]
+    fallback_strict_mode: bool,
+    /// 客户端验证密钥, 可以指定多个, 不指定时不进行验证
+    #[clap(long)]
+    secret: Vec<String>,
+    /// 允许客户端使用的访问端口范围, 如 10000-20000, 不指定时不限制
+    #[clap(long)]
+    port_range: Option<PortRange>,
+    /// 禁止使用的访问端口, 可以指定多个, 如 22 或者 6000-6100
+    #[clap(long)]
+    deny_port: Vec<PortRange>,
+    /// 固定分配给客户端的访问端口, 可以指定多个, 如 name=10022, name可以是客户端名称或者密钥
+    #[clap(long)]
+    reserve: Vec<Reservation>,
+    /// 允许的访问者ip, 可以指定多个, 如 192.168.0.0/16, 不指定时不限制
+    #[clap(long)]
+    allow_ip: Vec<Cidr>,
+    /// 禁止的访问者ip, 可以指定多个, 优先于allow-ip
+    #[clap(long)]
+    deny_ip: Vec<Cidr>,
+    /// 所有客户端共享的带宽限制, 单位为字节每秒, 如 10M 或者 上传:下载 1M:10M
+    #[clap(long)]
+    bandwidth: Option<Bandwidth>,
+    /// 客户端或映射的带宽限制, 可以指定多个, 如 name=1M:2M 或者 name/mapping=512K
+    #[clap(long)]
+    client_bandwidth: Vec<Quota>,
+    /// 同时在线的客户端数量, 0表示不限制
+    #[clap(long, default_value = "0")]
+    max_clients: usize,
+    /// 每个映射同时访问的数量, 0表示不限制, 客户端也指定时取较小的值
+    #[clap(long, default_value = "0")]
+    max_visitors: usize,
+    /// 每个访问者ip每秒新建的连接数, 0表示不限制
+    #[clap(long, default_value = "0")]
+    max_visitor_rate: u32,
+    /// 访问者两个方向都没有数据超过该时间(秒)时关闭, 客户端也指定时取较小的值, 不指定时不限制
+    #[clap(long)]
+    idle_timeout: Option<u64>,
+    /// 没有空闲超时时, 访问者半关闭后另一个方向没有数据超过该时间(秒)则关闭
+    #[clap(long, default_value = "30")]
+    linger: u64,
+    /// 收到SIGTERM或者Ctrl-C后等待正在转发的连接结束的最长时间(秒), 再次收到时立即退出
+    #[clap(long, default_value = "30")]
+    shutdown_timeout: u64,
+    /// 服务端身份密钥文件, 不存在时自动生成, 不指定时不对握手签名
+    #[clap(long)]
+    identity: Option<String>,
+    /// 允许使用的加密套件, 可以指定多个: chacha20-poly1305, aes-256-gcm, aes-128-cbc(旧版本)
+    #[clap(long)]
+    cipher: Vec<fuso::encryption::CipherSuite>,
+    /// 握手方式, 需要与客户端一致
+    #[clap(long, default_value = "rsa", possible_values = ["rsa", "x25519"])]
+    handshake: String,
+    /// tls监听端口, 指定证书后启用
+    #[cfg(feature = "fuso-tls")]
+    #[clap(long, default_value = "6723")]
+    tls_port: u16,
+    /// tls证书文件
+    #[cfg(feature = "fuso-tls")]
+    #[clap(long, requires = "tls-key")]
+    tls_cert: Option<String>,
+    /// tls私钥文件
+    #[cfg(feature = "fuso-tls")]
+    #[clap(long, requires = "tls-cert")]
+    tls_key: Option<String>,
+    /// 签发客户端证书的ca, 指定后要求客户端提供证书
+    #[cfg(feature = "fuso-tls")]
+    #[clap(long, requires = "tls-cert")]
+    tls_client_ca: Option<String>,
+    /// 管理接口监听地址, 如 127.0.0.1:6780, 不指定时不启用
+    #[cfg(feature = "fuso-api")]
+    #[clap(long)]
+    api_listen: Option<std::net::SocketAddr>,
+    /// 管理接口的访问令牌, 不指定时只能监听本地回环地址
+    #[cfg(feature = "fuso-api")]
+    #[clap(long)]
+    api_token: Option<String>,
+    /// prometheus指标监听地址, 如 127.0.0.1:9100, 不指定时不启用
+    #[cfg(feature = "fuso-metrics")]
+    #[clap(long)]
+    metrics_listen: Option<std::net::SocketAddr>,
+    /// 共享的http端口, 客户端通过 --domain 按域名访问, 不指定时不启用
+    #[clap(long)]
+    http_port: Option<u16>,
+    /// 共享的https端口, 按照tls的SNI转发给对应的客户端, 不指定时不启用
+    #[clap(long)]
+    https_port: Option<u16>,
+}
+
+/// 使用启动时的命令行参数, 热更新时重新读取配置文件, 失败时返回错误
+fn args_from(matches: &ArgMatches) -> fuso::Result<FusoArgs> {
+    FusoArgs::from_arg_matches(matches)
+        .map_err(|e| fuso::Kind::Message(format!("invalid arguments {}", e)).into())
+}
+
+#[cfg(feature = "fuso-toml")]
+fn parse_args(matches: &ArgMatches) -> fuso::Result<FusoArgs> {
+    use fuso_toml::FusoConfig;
+
+    let mut args = args_from(matches)?;
+
+    let config = match args.config.as_ref() {
+        None => return Ok(args),
+        Some(path) => FusoConfig::load(path)?,
+    };
+
+    merge!(
+        args,
+        matches,
+        config,
+        listen,
+        port,
+        enable_ufd,
+        enable_socks,
+        observer,
+        heartbeat_delay,
+        maximum_wait,
+        fallback_strict_mode,
+        secret,
+        port_range,
+        deny_port,
+        reserve,
+        allow_ip,
+        deny_ip,
+        bandwidth,
+        client_bandwidth,
+        max_clients,
+        max_visitors,
+        max_visitor_rate,
+        idle_timeout,
+        linger,
+        shutdown_timeout,
+        identity,
+        cipher,
+        handshake,
+        http_port,
+        https_port,
+    );
+
+    #[cfg(feature = "fuso-tls")]
+    merge!(
+        args,
+        matches,
+        config,
+        tls_port,
+        tls_cert,
+        tls_key,
+        tls_client_ca
+    );
+
+    #[cfg(feature = "fuso-api")]
+    merge!(args, matches, config, api_listen, api_token);
+
+    #[cfg(feature = "fuso-metrics")]
+    merge!(args, matches, config, metrics_listen);
+
+    #[cfg(feature = "fus-log")]
+    merge!(args, matches, config, log_level);
+
+    Ok(args)
+}
+
+#[cfg(not(feature = "fuso-toml"))]
+fn parse_args(matches: &ArgMatches) -> fuso::Result<FusoArgs> {
+    args_from(matches)
 }
 
 #[cfg(feature = "fus-log")]
@@ -39,37 +221,203 @@ fn init_logger(log_level: log::LevelFilter) {
     let is_info_log = log_level.eq(&log::LevelFilter::Info);
     env_logger::builder()
         .filter_module("fuso", log_level)
+        .filter_module(module_path!(), log_level)
         .default_format()
         .format_timestamp_millis()
         .format_target(!is_info_log)
         .init();
 }
 
+/// 可以热更新的配置
+fn settings(args: &FusoArgs) -> fuso::penetrate::Settings {
+    fuso::penetrate::Settings::default()
+        .max_wait_time(std::time::Duration::from_secs(args.maximum_wait))
+        .fallback_strict_mode(args.fallback_strict_mode)
+        .set_secrets(args.secret.clone())
+        .set_port_policy(
+            fuso::penetrate::PortPolicy::default()
+                .allow(args.port_range)
+                .deny(args.deny_port.clone())
+                .reserve(args.reserve.clone()),
+        )
+        .set_access_list(
+            fuso::penetrate::AccessList::default()
+                .allow(args.allow_ip.clone())
+                .deny(args.deny_ip.clone()),
+        )
+        .max_clients(args.max_clients)
+        .max_visitors(args.max_visitors)
+        .max_visitor_rate(args.max_visitor_rate)
+        .idle_timeout(args.idle_timeout.map(std::time::Duration::from_secs))
+        .linger(std::time::Duration::from_secs(args.linger))
+        .heartbeat_timeout(std::time::Duration::from_secs(args.heartbeat_delay))
+}
+
 fn main() -> fuso::Result<()> {
     use fuso::{
-        observer::Executable, penetrate::PenetrateRsaAndAesHandshake, FusoExecutor,
-        FusoUdpServerProvider, Socket, FusoUdpForwardProvider,
+        guard::Limits,
+        observer::Executable,
+        penetrate::{
+            PenetrateRsaAndAesHandshake, Reloader, ServerHandshakeConfig, ServerIdentity,
+            VirtualHosts,
+        },
+        Executor, FusoAccepter, FusoExecutor, FusoUdpForwardProvider, FusoUdpServerProvider,
+        Socket, WrappedProvider,
     };
-    use std::time::Duration;
+    use std::{sync::Arc, time::Duration};
 
-    let args = FusoArgs::parse();
+    let matches = FusoArgs::command().get_matches();
+    let args = parse_args(&matches)?;
 
     #[cfg(feature = "fus-log")]
     init_logger(args.log_level);
 
+    let config = Arc::new(
+        ServerHandshakeConfig::default()
+            .identity(
+                args.identity
+                    .as_deref()
+                    .map(ServerIdentity::load_or_generate)
+                    .transpose()?,
+            )
+            .ciphers(args.cipher.clone()),
+    );
+
+    let handshake = match args.handshake.as_str() {
+        #[cfg(feature = "fuso-crypt-x25519")]
+        "x25519" => WrappedProvider::wrap(
+            fuso::penetrate::PenetrateX25519Handshake::ConfiguredServer(config),
+        ),
+        #[cfg(not(feature = "fuso-crypt-x25519"))]
+        "x25519" => {
+            return Err(fuso::Kind::Message(String::from(
+                "the x25519 handshake requires the fuso-crypt-x25519 feature",
+            ))
+            .into())
+        }
+        _ => WrappedProvider::wrap(PenetrateRsaAndAesHandshake::ConfiguredServer(config)),
+    };
+
+    #[cfg(feature = "fuso-tls")]
+    let tls = match (args.tls_cert.clone(), args.tls_key.clone()) {
+        (Some(cert), Some(key)) => Some(fuso::tls::TlsServerConfig::load(
+            cert,
+            key,
+            args.tls_client_ca.clone(),
+        )?),
+        _ => None,
+    };
+
+    let reloader = Reloader::new(settings(&args));
+    let executable = Executable::new(args.observer.clone(), FusoExecutor);
+    let limits = Limits::new(
+        args.bandwidth.unwrap_or_default(),
+        args.client_bandwidth.clone(),
+    );
+
+    // 重新读取配置文件, 只替换可以热更新的配置, 已建立的连接不会中断
+    let reload = {
+        let reloader = reloader.clone();
+        let executable = executable.clone();
+        let limits = limits.clone();
+        move || -> fuso::Result<()> {
+            let args = parse_args(&matches)?;
+            reloader.reload(settings(&args));
+            executable.reload(args.observer);
+            limits.reset(args.bandwidth.unwrap_or_default(), args.client_bandwidth);
+            log::info!("the configuration has been reloaded");
+            Ok(())
+        }
+    };
+
     fuso::block_on(async move {
-        fuso::builder_server(Executable::new(args.observer, FusoExecutor))
-            .using_handshake(PenetrateRsaAndAesHandshake::Server)
-            .using_kcp(FusoUdpServerProvider, FusoExecutor)
+        #[cfg(feature = "fuso-metrics")]
+        if let Some(listen) = args.metrics_listen {
+            let _ = FusoExecutor.spawn(fuso::metrics::Metrics::serve(listen));
+        }
+
+        #[cfg(unix)]
+        {
+            let reload = reload.clone();
+            let _ = FusoExecutor.spawn(async move {
+                use tokio::signal::unix::{signal, SignalKind};
+
+                let mut hangup = signal(SignalKind::hangup())?;
+
+                while hangup.recv().await.is_some() {
+                    log::info!("received SIGHUP, reloading the configuration");
+
+                    if let Err(e) = reload() {
+                        log::warn!("failed to reload the configuration {}", e);
+                    }
+                }
+
+                Ok::<_, fuso::Error>(())
+            });
+        }
+
+        #[cfg(feature = "fuso-metrics")]
+        let observer = fuso::metrics::MetricsObserver::new(executable);
+        #[cfg(not(feature = "fuso-metrics"))]
+        let observer = executable;
+
+        let fuso = fuso::builder_server(observer)
+            .using_handshake(handshake)
+            .using_kcp(FusoUdpServerProvider, FusoExecutor);
+
+        #[cfg(feature = "fuso-tls")]
+        let fuso = fuso.using_tls(Socket::tcp((args.listen, args.tls_port)), tls);
+
+        let fuso = fuso
             .using_penetrate()
-            .heartbeat_timeout(Duration::from_secs(args.heartbeat_delay))
-            .using_adapter()
+            .using_reloader(reloader)
+            .using_limits(limits.clone())
+            .using_adapter();
+
+        let fuso = match [args.http_port, args.https_port] {
+            [None, None] => fuso,
+            ports => {
+                let vhosts = VirtualHosts::new();
+
+                for port in ports.into_iter().flatten() {
+                    let serve =
+                        vhosts.serve(FusoExecutor, FusoAccepter, Socket::tcp((args.listen, port)));
+                    let _ = FusoExecutor.spawn(serve);
+                }
+
+                fuso.using_vhost(vhosts.clone()).using_sni(vhosts)
+            }
+        };
+
+        let fuso = fuso
             .using_direct()
             .using_socks()
             .using_udp_forward(FusoUdpForwardProvider)
             .build()
-            .bind(Socket::tcp((args.listen, args.port)))
-            .run()
-            .await
+            .bind(Socket::tcp((args.listen, args.port)));
+
+        #[cfg(feature = "fuso-api")]
+        let fuso = match args.api_listen {
+            None => fuso,
+            Some(listen) => {
+                let api = fuso::http::routes::FusoApi::new(FusoExecutor)
+                    .using_reload(reload)
+                    .using_limits(limits)
+                    .using_token(args.api_token);
+                let _ = FusoExecutor.spawn(api.serve(listen));
+
+                fuso.using_api(api)
+            }
+        };
+
+        let serve = fuso.run();
+
+        let _ = FusoExecutor.spawn(
+            serve
+                .shutdown()
+                .on_signal(Duration::from_secs(args.shutdown_timeout)),
+        );
+
+        serve.await
     })
 }
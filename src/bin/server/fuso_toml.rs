@@ -0,0 +1,73 @@
+use std::net::IpAddr;
+
+use fuso::{
+    encryption::CipherSuite,
+    guard::{Bandwidth, Quota},
+    penetrate::{Cidr, PortRange, Reservation},
+};
+use serde::Deserialize;
+
+use crate::common::{parse, parse_vec};
+
+/// 服务端配置文件, 字段名与命令行参数相同(`-` 换成 `_`), 命令行参数优先
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FusoConfig {
+    pub listen: Option<IpAddr>,
+    pub port: Option<u16>,
+    pub enable_ufd: Option<bool>,
+    pub enable_socks: Option<bool>,
+    pub observer: Option<String>,
+    pub heartbeat_delay: Option<u64>,
+    pub maximum_wait: Option<u64>,
+    pub fallback_strict_mode: Option<bool>,
+    pub secret: Option<Vec<String>>,
+    #[serde(deserialize_with = "parse")]
+    pub port_range: Option<PortRange>,
+    #[serde(deserialize_with = "parse_vec")]
+    pub deny_port: Option<Vec<PortRange>>,
+    #[serde(deserialize_with = "parse_vec")]
+    pub reserve: Option<Vec<Reservation>>,
+    #[serde(deserialize_with = "parse_vec")]
+    pub allow_ip: Option<Vec<Cidr>>,
+    #[serde(deserialize_with = "parse_vec")]
+    pub deny_ip: Option<Vec<Cidr>>,
+    #[serde(deserialize_with = "parse")]
+    pub bandwidth: Option<Bandwidth>,
+    #[serde(deserialize_with = "parse_vec")]
+    pub client_bandwidth: Option<Vec<Quota>>,
+    pub max_clients: Option<usize>,
+    pub max_visitors: Option<usize>,
+    pub max_visitor_rate: Option<u32>,
+    pub idle_timeout: Option<u64>,
+    pub linger: Option<u64>,
+    pub shutdown_timeout: Option<u64>,
+    pub identity: Option<String>,
+    #[serde(deserialize_with = "parse_vec")]
+    pub cipher: Option<Vec<CipherSuite>>,
+    pub handshake: Option<String>,
+    #[cfg(feature = "fuso-tls")]
+    pub tls_port: Option<u16>,
+    #[cfg(feature = "fuso-tls")]
+    pub tls_cert: Option<String>,
+    #[cfg(feature = "fuso-tls")]
+    pub tls_key: Option<String>,
+    #[cfg(feature = "fuso-tls")]
+    pub tls_client_ca: Option<String>,
+    #[cfg(feature = "fuso-api")]
+    pub api_listen: Option<std::net::SocketAddr>,
+    #[cfg(feature = "fuso-api")]
+    pub api_token: Option<String>,
+    #[cfg(feature = "fuso-metrics")]
+    pub metrics_listen: Option<std::net::SocketAddr>,
+    pub http_port: Option<u16>,
+    pub https_port: Option<u16>,
+    #[serde(deserialize_with = "parse")]
+    pub log_level: Option<log::LevelFilter>,
+}
+
+impl FusoConfig {
+    pub fn load(path: &str) -> fuso::Result<Self> {
+        crate::common::load(path)
+    }
+}
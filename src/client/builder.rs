@@ -1,18 +1,20 @@
-use std::{future::Future, pin::Pin, sync::Arc, time::Duration};
+use std::{future::Future, pin::Pin, sync::Arc};
 
 use crate::{
     generator::Generator, ClientProvider, DecorateProvider, Executor, Fuso, Processor, Provider,
     Socket, Stream, WrappedProvider,
 };
 
-use super::Client;
+use super::{Client, Endpoints, Failover, ReconnectPolicy};
 
 type BoxedFuture<T> = Pin<Box<dyn Future<Output = crate::Result<T>> + Send + 'static>>;
 
 pub struct ClientBuilder<E, P, S> {
     pub(crate) executor: E,
-    pub(crate) retry_delay: Option<Duration>,
-    pub(crate) maximum_retries: Option<usize>,
+    pub(crate) reconnect: ReconnectPolicy,
+    /// 备用的服务端地址
+    pub(crate) backup_servers: Vec<Socket>,
+    pub(crate) failover: Failover,
     pub(crate) handshake: Option<WrappedProvider<S, (S, Option<DecorateProvider<S>>)>>,
     pub(crate) client_provider: ClientProvider<P>,
 }
@@ -34,6 +36,18 @@ where
         self
     }
 
+    pub fn using_reconnect(mut self, reconnect: ReconnectPolicy) -> Self {
+        self.reconnect = reconnect;
+        self
+    }
+
+    /// 连接失败时按strategy切换到备用的服务端地址
+    pub fn using_failover(mut self, backup_servers: Vec<Socket>, strategy: Failover) -> Self {
+        self.backup_servers = backup_servers;
+        self.failover = strategy;
+        self
+    }
+
     pub fn build<A: Into<Socket>, H, G>(self, socket: A, handler: H) -> Fuso<Client<E, H, P, S>>
     where
         G: Generator<Output = Option<BoxedFuture<()>>> + Unpin + Send + 'static,
@@ -44,14 +58,21 @@ where
     {
         let socket = socket.into();
 
+        let endpoints = Endpoints::new(
+            std::iter::once(socket.clone())
+                .chain(self.backup_servers)
+                .collect(),
+            self.failover,
+        );
+
         Fuso(Client {
-            socket: socket.clone(),
-            maximum_retries: self.maximum_retries,
-            retry_delay: self.retry_delay.unwrap_or(Duration::from_secs(5)),
+            endpoints,
+            reconnect: self.reconnect,
             handler: Arc::new(handler),
             executor: Arc::new(self.executor),
             handshake: self.handshake,
             client_provider: self.client_provider.set_server_socket(socket),
+            shutdown: Default::default(),
         })
     }
 }
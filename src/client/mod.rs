@@ -1,13 +1,16 @@
 mod builder;
+mod reconnect;
 
-use std::{future::Future, pin::Pin, sync::Arc, time::Duration};
+use std::{future::Future, pin::Pin, sync::Arc};
 
 pub use builder::*;
+pub use reconnect::*;
 
 use crate::{
     generator::{Generator, GeneratorEx},
+    select::Select,
     time, ClientProvider, DecorateProvider, Executor, Fuso, Kind, Processor, Provider, Serve,
-    Socket, Stream, WrappedProvider,
+    Shutdown, Socket, Stream, WrappedProvider,
 };
 
 type BoxedFuture<T> = Pin<Box<dyn Future<Output = crate::Result<T>> + Send + 'static>>;
@@ -18,13 +21,13 @@ pub enum Route<S> {
 }
 
 pub struct Client<E, H, P, S> {
-    pub(crate) socket: Socket,
+    pub(crate) endpoints: Endpoints,
     pub(crate) executor: Arc<E>,
     pub(crate) handler: Arc<H>,
-    pub(crate) maximum_retries: Option<usize>,
-    pub(crate) retry_delay: Duration,
+    pub(crate) reconnect: ReconnectPolicy,
     pub(crate) handshake: Option<WrappedProvider<S, (S, Option<DecorateProvider<S>>)>>,
     pub(crate) client_provider: ClientProvider<P>,
+    pub(crate) shutdown: Shutdown,
 }
 
 impl<E, H, P, S, G> Client<E, H, P, S>
@@ -42,30 +45,31 @@ where
         let executor = self.executor;
         let provider = self.client_provider.clone();
         let handshake = self.handshake;
-        let mut retries_count = 0;
-        let maximum_retries = self.maximum_retries;
+        let endpoints = self.endpoints;
+        let mut reconnect = self.reconnect;
+        let shutdown = self.shutdown;
 
-        loop {
-            let socket = self.socket.clone();
+        while !shutdown.is_triggered() {
+            let socket = endpoints.active();
 
-            let stream = match self.client_provider.connect(socket).await {
+            let stream = match self.client_provider.connect(socket.clone()).await {
                 Ok(stream) => {
-                    log::info!("connection established");
+                    log::info!("connection established with {}", socket);
                     stream
                 }
                 Err(e) => {
-                    log::warn!("connect to {} failed err: {}", self.socket, e);
-                    time::sleep(self.retry_delay).await;
+                    log::warn!("connect to {} failed err: {}", socket, e);
 
-                    if let Some(retries) = maximum_retries {
-                        if retries > retries_count {
-                            break Err(Kind::MaxRetries(retries).into());
-                        }
+                    // 所有地址都失败一轮后才等待, 否则立即尝试下一个地址
+                    if endpoints.failover(&format!("connect failed: {}", e)) {
+                        Self::backoff(&mut reconnect, &shutdown).await?;
                     }
 
-                    retries_count += 1;
-
-                    log::debug!("reconnect({}) to {} ", retries_count, self.socket);
+                    log::debug!(
+                        "reconnect({}) to {}",
+                        reconnect.attempts(),
+                        endpoints.active()
+                    );
 
                     continue;
                 }
@@ -80,35 +84,99 @@ where
                 Ok(stream) => stream,
                 Err(e) => {
                     log::error!("handshake failed {}", e);
-                    break Ok(());
+                    #[cfg(feature = "fuso-metrics")]
+                    crate::metrics::metrics().handshake_failures.inc();
+
+                    if endpoints.failover(&format!("handshake failed: {}", e)) {
+                        Self::backoff(&mut reconnect, &shutdown).await?;
+                    }
+
+                    continue;
                 }
             };
 
-            let processor = Processor::new(Arc::new(provider.clone()), None, decorator);
+            // 映射的连接也需要发往当前使用的地址
+            let provider = provider.clone().set_server_socket(socket);
+
+            let processor =
+                Processor::new(Arc::new(provider), None, decorator).with_shutdown(shutdown.clone());
 
             let mut generate = match self.handler.call((server, processor)).await {
                 Ok(generate) => generate,
                 Err(e) => {
                     log::warn!("processing failed ! err: {}", e);
-                    time::sleep(self.retry_delay).await;
+
+                    if endpoints.failover(&format!("processing failed: {}", e)) {
+                        Self::backoff(&mut reconnect, &shutdown).await?;
+                    }
+
                     continue;
                 }
             };
 
-            loop {
-                match generate.next().await {
-                    Ok(None) => break,
-                    Ok(Some(fut)) => {
-                        executor.spawn(fut);
-                    }
-                    Err(e) => {
-                        log::error!("encountered an error err: {}", e);
-                        time::sleep(self.retry_delay).await;
-                        break;
+            endpoints.established();
+            reconnect.reset();
+
+            let run_fut = {
+                let executor = executor.clone();
+                let shutdown = shutdown.clone();
+                async move {
+                    loop {
+                        match generate.next().await {
+                            Ok(None) => break String::from("connection closed"),
+                            Ok(Some(fut)) => {
+                                executor.spawn(shutdown.track(fut));
+                            }
+                            Err(e) => {
+                                log::error!("encountered an error err: {}", e);
+                                break e.to_string();
+                            }
+                        }
                     }
                 }
+            };
+
+            // 关闭时继续处理已建立的连接, 直到转发结束或者超时
+            let drain_fut = {
+                let shutdown = shutdown.clone();
+                async move {
+                    shutdown.wait().await;
+                    shutdown.drain().await;
+                    String::from("shutting down")
+                }
+            };
+
+            let reason = Select::select(run_fut, drain_fut).await;
+
+            if !shutdown.is_triggered() {
+                endpoints.disconnected(&reason);
+                Self::backoff(&mut reconnect, &shutdown).await?;
             }
         }
+
+        let remaining = shutdown.drain().await;
+
+        if remaining > 0 {
+            log::warn!("{} forwards are still running, force exit", remaining);
+        }
+
+        log::info!("the client has been shut down");
+
+        Ok(())
+    }
+
+    /// 按重连策略等待, 超过最大重试次数时返回错误, 关闭时立即返回
+    async fn backoff(reconnect: &mut ReconnectPolicy, shutdown: &Shutdown) -> crate::Result<()> {
+        let delay = match reconnect.next_delay() {
+            Some(delay) => delay,
+            None => return Err(Kind::MaxRetries(reconnect.attempts()).into()),
+        };
+
+        log::debug!("wait {:?} before reconnecting", delay);
+
+        let _ = time::wait_for(delay, shutdown.wait()).await;
+
+        Ok(())
     }
 }
 
@@ -123,9 +191,17 @@ where
     S: Stream + Send + 'static,
     G: Generator<Output = Option<BoxedFuture<()>>> + Unpin + Send + 'static,
 {
+    /// 服务端地址列表, 可以获取当前使用的地址以及最近一次切换的原因
+    pub fn endpoints(&self) -> Endpoints {
+        self.0.endpoints.clone()
+    }
+
     pub fn run(self) -> Fuso<Serve> {
+        let shutdown = self.0.shutdown.clone();
+
         Fuso(Serve {
             fut: Box::pin(self.0.run()),
+            shutdown,
         })
     }
 }
@@ -0,0 +1,330 @@
+use std::{
+    fmt::Display,
+    str::FromStr,
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+use rand::Rng;
+
+use crate::{Kind, Socket};
+
+/// 重连策略, 指数退避并加入随机抖动, 连接成功后重置
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    initial: Duration,
+    maximum: Duration,
+    multiplier: f64,
+    /// 抖动比例, 0.2表示在 ±20% 范围内随机
+    jitter: f64,
+    /// 最大重试次数, None表示永不停止
+    maximum_retries: Option<usize>,
+    attempts: usize,
+}
+
+/// 服务端地址的切换方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failover {
+    /// 每一轮都从第一个地址开始尝试
+    Priority,
+    /// 依次轮流尝试
+    RoundRobin,
+}
+
+/// 服务端地址列表, 克隆后共享当前使用的地址
+#[derive(Clone)]
+pub struct Endpoints(Arc<Mutex<EndpointState>>);
+
+struct EndpointState {
+    sockets: Vec<Socket>,
+    strategy: Failover,
+    current: usize,
+    /// 本轮已经失败的地址数
+    failed: usize,
+    last_failover: Option<String>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_secs(1),
+            maximum: Duration::from_secs(60),
+            multiplier: 2.0,
+            jitter: 0.2,
+            maximum_retries: None,
+            attempts: 0,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// 固定的重连延时, 不退避也不抖动
+    pub fn fixed(delay: Duration) -> Self {
+        Self::default()
+            .initial_delay(delay)
+            .maximum_delay(delay)
+            .multiplier(1.0)
+            .jitter(0.0)
+    }
+
+    pub fn initial_delay(mut self, delay: Duration) -> Self {
+        self.initial = delay;
+        self.maximum = self.maximum.max(delay);
+        self
+    }
+
+    pub fn maximum_delay(mut self, delay: Duration) -> Self {
+        self.maximum = delay.max(self.initial);
+        self
+    }
+
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier.max(1.0);
+        self
+    }
+
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn maximum_retries(mut self, maximum_retries: Option<usize>) -> Self {
+        self.maximum_retries = maximum_retries;
+        self
+    }
+
+    /// 连续失败的次数
+    pub fn attempts(&self) -> usize {
+        self.attempts
+    }
+
+    /// 下一次重连前的等待时间, 超过最大重试次数时返回None
+    pub(crate) fn next_delay(&mut self) -> Option<Duration> {
+        if matches!(self.maximum_retries, Some(retries) if self.attempts >= retries) {
+            return None;
+        }
+
+        let exponent = self.attempts.min(i32::MAX as usize) as i32;
+        let delay = self.initial.as_secs_f64() * self.multiplier.powi(exponent);
+
+        // 先抖动再限制, 保证不会超过最大延时
+        let delay = if self.jitter > 0.0 {
+            delay * rand::thread_rng().gen_range(1.0 - self.jitter..=1.0 + self.jitter)
+        } else {
+            delay
+        };
+
+        let delay = delay.min(self.maximum.as_secs_f64());
+
+        self.attempts += 1;
+
+        Some(Duration::from_secs_f64(delay))
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.attempts = 0;
+    }
+}
+
+impl FromStr for Failover {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "priority" => Ok(Self::Priority),
+            "round-robin" => Ok(Self::RoundRobin),
+            _ => Err(Kind::Message(format!("invalid failover strategy {}", s)).into()),
+        }
+    }
+}
+
+impl Display for Failover {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Priority => write!(f, "priority"),
+            Self::RoundRobin => write!(f, "round-robin"),
+        }
+    }
+}
+
+impl Endpoints {
+    pub fn new(sockets: Vec<Socket>, strategy: Failover) -> Self {
+        assert!(
+            !sockets.is_empty(),
+            "at least one server address is required"
+        );
+
+        let endpoints = Self(Arc::new(Mutex::new(EndpointState {
+            sockets,
+            strategy,
+            current: 0,
+            failed: 0,
+            last_failover: None,
+        })));
+
+        #[cfg(feature = "fuso-metrics")]
+        endpoints.update_metrics();
+
+        endpoints
+    }
+
+    /// 当前使用的地址
+    pub fn active(&self) -> Socket {
+        let state = self.lock();
+        state.sockets[state.current].clone()
+    }
+
+    pub fn sockets(&self) -> Vec<Socket> {
+        self.lock().sockets.clone()
+    }
+
+    pub fn strategy(&self) -> Failover {
+        self.lock().strategy
+    }
+
+    /// 最近一次切换地址的原因
+    pub fn last_failover(&self) -> Option<String> {
+        self.lock().last_failover.clone()
+    }
+
+    /// 当前地址连接失败, 切换到下一个地址, 所有地址都失败一轮后返回true
+    pub(crate) fn failover(&self, reason: &str) -> bool {
+        let (next, exhausted) = {
+            let mut state = self.lock();
+            state.failed += 1;
+
+            let exhausted = state.failed >= state.sockets.len();
+
+            if exhausted {
+                state.failed = 0;
+            }
+
+            match (state.strategy, exhausted) {
+                (Failover::Priority, true) => (0, true),
+                _ => ((state.current + 1) % state.sockets.len(), exhausted),
+            }
+        };
+
+        self.switch(next, reason);
+
+        exhausted
+    }
+
+    /// 连接断开, 按优先级时回到第一个地址, 轮流时切换到下一个地址
+    pub(crate) fn disconnected(&self, reason: &str) {
+        let next = {
+            let mut state = self.lock();
+            state.failed = 0;
+            match state.strategy {
+                Failover::Priority => 0,
+                Failover::RoundRobin => (state.current + 1) % state.sockets.len(),
+            }
+        };
+
+        self.switch(next, reason);
+    }
+
+    /// 连接成功, 重新开始计算本轮失败的地址
+    pub(crate) fn established(&self) {
+        self.lock().failed = 0;
+    }
+
+    fn switch(&self, next: usize, reason: &str) {
+        let mut state = self.lock();
+
+        if next == state.current {
+            return;
+        }
+
+        let reason = format!(
+            "{} -> {}: {}",
+            state.sockets[state.current], state.sockets[next], reason
+        );
+
+        log::warn!("failover {}", reason);
+
+        state.current = next;
+        state.last_failover = Some(reason);
+
+        drop(state);
+
+        #[cfg(feature = "fuso-metrics")]
+        {
+            crate::metrics::metrics().failovers.inc();
+            self.update_metrics();
+        }
+    }
+
+    #[cfg(feature = "fuso-metrics")]
+    fn update_metrics(&self) {
+        let state = self.lock();
+        for (index, socket) in state.sockets.iter().enumerate() {
+            crate::metrics::metrics()
+                .endpoints
+                .with_label_values(&[&socket.to_string()])
+                .set((index == state.current) as i64);
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, EndpointState> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Endpoints, Failover, ReconnectPolicy};
+    use crate::Socket;
+
+    #[test]
+    fn test_reconnect_policy() {
+        let mut policy = ReconnectPolicy::default()
+            .initial_delay(Duration::from_secs(1))
+            .maximum_delay(Duration::from_secs(4))
+            .jitter(0.0)
+            .maximum_retries(Some(4));
+
+        let delays = std::iter::from_fn(|| policy.next_delay())
+            .map(|delay| delay.as_secs())
+            .collect::<Vec<_>>();
+
+        assert_eq!(delays, vec![1, 2, 4, 4]);
+
+        policy.reset();
+        assert_eq!(policy.next_delay(), Some(Duration::from_secs(1)));
+
+        let mut policy = ReconnectPolicy::default()
+            .initial_delay(Duration::from_secs(10))
+            .jitter(0.5);
+
+        let delay = policy.next_delay().unwrap();
+        assert!(delay >= Duration::from_secs(5) && delay <= Duration::from_secs(15));
+
+        let mut policy = ReconnectPolicy::fixed(Duration::from_secs(4)).jitter(0.5);
+        assert!((0..20).all(|_| policy.next_delay().unwrap() <= Duration::from_secs(4)));
+
+        let servers = vec![Socket::tcp(1001), Socket::tcp(1002), Socket::tcp(1003)];
+
+        let endpoints = Endpoints::new(servers.clone(), Failover::Priority);
+        assert!(!endpoints.failover("refused"));
+        assert_eq!(endpoints.active(), servers[1]);
+        assert!(endpoints.last_failover().unwrap().ends_with("refused"));
+        assert!(!endpoints.failover("refused"));
+        assert!(endpoints.failover("refused"));
+        assert_eq!(endpoints.active(), servers[0]);
+        endpoints.failover("refused");
+        endpoints.established();
+        endpoints.disconnected("closed");
+        assert_eq!(endpoints.active(), servers[0]);
+
+        let endpoints = Endpoints::new(servers.clone(), Failover::RoundRobin);
+        endpoints.disconnected("closed");
+        assert_eq!(endpoints.active(), servers[1]);
+        assert!(!endpoints.failover("refused"));
+        assert!(!endpoints.failover("refused"));
+        assert!(endpoints.failover("refused"));
+        assert_eq!(endpoints.active(), servers[1]);
+    }
+}
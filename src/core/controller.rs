@@ -16,6 +16,16 @@ pub trait Environ {
     fn conn(&self) -> Address;
 
     fn info(&self) -> crate::Result<serde_json::Value>;
+
+    /// 访问端口
+    fn visitor(&self) -> Option<Address> {
+        None
+    }
+
+    /// 客户端数据通道端口
+    fn client(&self) -> Option<Address> {
+        None
+    }
 }
 
 impl<C> Controller for Arc<C>
@@ -43,4 +53,12 @@ where
     fn info(&self) -> crate::Result<serde_json::Value> {
         (**self).info()
     }
+
+    fn visitor(&self) -> Option<Address> {
+        (**self).visitor()
+    }
+
+    fn client(&self) -> Option<Address> {
+        (**self).client()
+    }
 }
@@ -0,0 +1,442 @@
+use std::{pin::Pin, task::Poll};
+
+use aes_gcm::{aead::Aead, Aes256Gcm, KeyInit};
+use chacha20poly1305::ChaCha20Poly1305;
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+use crate::{
+    error::{EncryptionErr, Kind},
+    guard::buffer::Buffer,
+    AsyncRead, AsyncWrite, NetSocket, ReadBuf,
+};
+
+use super::{CipherSuite, Decrypt, Encrypt};
+
+/// 每个方向的随机盐长度, 用于派生子密钥
+const SALT_SIZE: usize = 16;
+const TAG_SIZE: usize = 16;
+/// 单帧最大明文长度
+const MAXIMUM_FRAME: usize = 16 * 1024;
+
+enum Cipher {
+    Aes256Gcm(Box<Aes256Gcm>),
+    ChaCha20Poly1305(Box<ChaCha20Poly1305>),
+}
+
+/// 读取状态
+enum Stage {
+    Salt,
+    Head,
+    Body,
+}
+
+pub struct AEADEncryptor<T> {
+    target: T,
+    suite: CipherSuite,
+    key: [u8; 32],
+    sealer: Option<(Cipher, u64)>,
+    opener: Option<(Cipher, u64)>,
+    ebuf: Option<Vec<u8>>,
+    epos: usize,
+    elen: usize,
+    dbuf: Buffer<u8>,
+    rbuf: Vec<u8>,
+    rpos: usize,
+    stage: Stage,
+}
+
+impl Cipher {
+    fn derive(suite: CipherSuite, key: &[u8; 32], salt: &[u8]) -> crate::Result<Self> {
+        let mut subkey = [0u8; 32];
+
+        Hkdf::<Sha256>::new(Some(salt), key)
+            .expand(b"fuso-subkey", &mut subkey)
+            .map_err(|_| Kind::Encryption(EncryptionErr::Aead))?;
+
+        match suite {
+            CipherSuite::Aes256Gcm => Ok(Cipher::Aes256Gcm(Box::new(Aes256Gcm::new(
+                &subkey.into(),
+            )))),
+            CipherSuite::ChaCha20Poly1305 => Ok(Cipher::ChaCha20Poly1305(Box::new(
+                ChaCha20Poly1305::new(&subkey.into()),
+            ))),
+            suite => Err(Kind::Encryption(EncryptionErr::Cipher(format!(
+                "{} is not an aead cipher",
+                suite
+            )))
+            .into()),
+        }
+    }
+
+    fn nonce(counter: u64) -> [u8; 12] {
+        let mut nonce = [0u8; 12];
+        nonce[..8].copy_from_slice(&counter.to_le_bytes());
+        nonce
+    }
+
+    fn seal(&self, counter: u64, data: &[u8]) -> crate::Result<Vec<u8>> {
+        let nonce = Self::nonce(counter);
+        let sealed = match self {
+            Cipher::Aes256Gcm(cipher) => cipher.encrypt(&nonce.into(), data)?,
+            Cipher::ChaCha20Poly1305(cipher) => cipher.encrypt(&nonce.into(), data)?,
+        };
+
+        Ok(sealed)
+    }
+
+    fn open(&self, counter: u64, data: &[u8]) -> crate::Result<Vec<u8>> {
+        let nonce = Self::nonce(counter);
+        let opened = match self {
+            Cipher::Aes256Gcm(cipher) => cipher.decrypt(&nonce.into(), data)?,
+            Cipher::ChaCha20Poly1305(cipher) => cipher.decrypt(&nonce.into(), data)?,
+        };
+
+        Ok(opened)
+    }
+}
+
+impl<T> AEADEncryptor<T> {
+    pub fn new(target: T, suite: CipherSuite, key: [u8; 32]) -> Self {
+        Self {
+            target,
+            suite,
+            key,
+            sealer: None,
+            opener: None,
+            ebuf: None,
+            epos: 0,
+            elen: 0,
+            dbuf: Default::default(),
+            rbuf: vec![0u8; SALT_SIZE],
+            rpos: 0,
+            stage: Stage::Salt,
+        }
+    }
+}
+
+impl<T> NetSocket for AEADEncryptor<T>
+where
+    T: NetSocket,
+{
+    fn peer_addr(&self) -> crate::Result<crate::Address> {
+        self.target.peer_addr()
+    }
+
+    fn local_addr(&self) -> crate::Result<crate::Address> {
+        self.target.local_addr()
+    }
+}
+
+impl<T> AsyncRead for AEADEncryptor<T>
+where
+    T: AsyncRead + Unpin,
+{
+    fn poll_read(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut crate::ReadBuf<'_>,
+    ) -> std::task::Poll<crate::Result<usize>> {
+        if !self.dbuf.is_empty() {
+            let n = self.dbuf.read_to_buffer(buf.initialize_unfilled());
+            buf.advance(n);
+            Poll::Ready(Ok(n))
+        } else {
+            self.poll_decrypt_read(cx, buf)
+        }
+    }
+}
+
+impl<T> AsyncWrite for AEADEncryptor<T>
+where
+    T: AsyncWrite + Unpin,
+{
+    fn poll_write(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<crate::Result<usize>> {
+        if let Some(ebuf) = self.ebuf.take() {
+            loop {
+                let epos = self.epos;
+                match Pin::new(&mut self.target).poll_write(cx, &ebuf[epos..])? {
+                    Poll::Ready(0) => break Poll::Ready(Ok(0)),
+                    Poll::Ready(n) => {
+                        self.epos += n;
+                        if self.epos == ebuf.len() {
+                            break Poll::Ready(Ok(self.elen));
+                        }
+                    }
+                    Poll::Pending => {
+                        self.ebuf.replace(ebuf);
+                        break Poll::Pending;
+                    }
+                }
+            }
+        } else {
+            self.poll_encrypt_write(cx, buf)
+        }
+    }
+
+    fn poll_flush(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<crate::Result<()>> {
+        Pin::new(&mut self.target).poll_flush(cx)
+    }
+
+    fn poll_close(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<crate::Result<()>> {
+        Pin::new(&mut self.target).poll_close(cx)
+    }
+}
+
+impl<T> Encrypt for AEADEncryptor<T>
+where
+    T: AsyncWrite + Unpin,
+{
+    fn poll_encrypt_write(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<crate::Result<usize>> {
+        let buf = &buf[..buf.len().min(MAXIMUM_FRAME)];
+        let mut encrypted_buf = Vec::with_capacity(SALT_SIZE + 4 + buf.len() + TAG_SIZE);
+
+        // 首次写入时发送随机盐, 每个连接的每个方向使用不同的子密钥
+        if self.sealer.is_none() {
+            let mut salt = [0u8; SALT_SIZE];
+            salt.fill_with(rand::random);
+            let cipher = Cipher::derive(self.suite, &self.key, &salt)?;
+            encrypted_buf.extend_from_slice(&salt);
+            self.sealer = Some((cipher, 0));
+        }
+
+        let sealed = {
+            let (cipher, counter) = unsafe { self.sealer.as_mut().unwrap_unchecked() };
+            let sealed = cipher.seal(*counter, buf)?;
+            *counter += 1;
+            sealed
+        };
+
+        encrypted_buf.extend_from_slice(&(sealed.len() as u32).to_le_bytes());
+        encrypted_buf.extend(sealed);
+
+        let mut epos = 0;
+
+        loop {
+            match Pin::new(&mut self.target).poll_write(cx, &encrypted_buf[epos..])? {
+                Poll::Ready(0) => break Poll::Ready(Ok(0)),
+                Poll::Ready(n) => {
+                    epos += n;
+                    if epos == encrypted_buf.len() {
+                        break Poll::Ready(Ok(buf.len()));
+                    }
+                }
+                Poll::Pending => {
+                    self.epos = 0;
+                    self.elen = buf.len();
+                    self.ebuf.replace(encrypted_buf[epos..].to_vec());
+                    break Poll::Pending;
+                }
+            }
+        }
+    }
+}
+
+impl<T> Decrypt for AEADEncryptor<T>
+where
+    T: AsyncRead + Unpin,
+{
+    fn poll_decrypt_read(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut crate::ReadBuf<'_>,
+    ) -> std::task::Poll<crate::Result<usize>> {
+        loop {
+            if self.rpos == self.rbuf.len() {
+                self.rpos = 0;
+                match self.stage {
+                    Stage::Salt => {
+                        let cipher = Cipher::derive(self.suite, &self.key, &self.rbuf)?;
+                        self.opener = Some((cipher, 0));
+                        self.rbuf = vec![0u8; 4];
+                        self.stage = Stage::Head;
+                    }
+                    Stage::Head => {
+                        let len = u32::from_le_bytes([
+                            self.rbuf[0],
+                            self.rbuf[1],
+                            self.rbuf[2],
+                            self.rbuf[3],
+                        ]) as usize;
+
+                        if !(TAG_SIZE..=MAXIMUM_FRAME + TAG_SIZE).contains(&len) {
+                            return Poll::Ready(Err(Kind::Encryption(EncryptionErr::Aead).into()));
+                        }
+
+                        self.rbuf = vec![0u8; len];
+                        self.stage = Stage::Body;
+                    }
+                    Stage::Body => {
+                        let opened = {
+                            let sealed = std::mem::replace(&mut self.rbuf, vec![0u8; 4]);
+                            let (cipher, counter) =
+                                unsafe { self.opener.as_mut().unwrap_unchecked() };
+                            let opened = cipher.open(*counter, &sealed)?;
+                            *counter += 1;
+                            opened
+                        };
+
+                        self.stage = Stage::Head;
+
+                        let unfilled = buf.initialize_unfilled();
+                        let n = unfilled.len().min(opened.len());
+                        unfilled[..n].copy_from_slice(&opened[..n]);
+                        buf.advance(n);
+
+                        if n < opened.len() {
+                            self.dbuf.push_back(&opened[n..]);
+                        }
+
+                        // 空帧不代表连接关闭, 继续读取
+                        if n > 0 {
+                            return Poll::Ready(Ok(n));
+                        }
+
+                        continue;
+                    }
+                }
+            }
+
+            let rpos = self.rpos;
+            let mut rbuf = std::mem::take(&mut self.rbuf);
+            let mut read_buf = ReadBuf::new(&mut rbuf[rpos..]);
+            let poll = Pin::new(&mut self.target).poll_read(cx, &mut read_buf);
+            self.rbuf = rbuf;
+
+            match poll? {
+                // 只有在帧边界上才是正常关闭, 否则说明帧被截断
+                Poll::Ready(0) if self.rpos == 0 && !matches!(self.stage, Stage::Body) => {
+                    return Poll::Ready(Ok(0))
+                }
+                Poll::Ready(0) => {
+                    return Poll::Ready(Err(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        "aead frame truncated",
+                    )
+                    .into()))
+                }
+                Poll::Ready(n) => {
+                    self.rpos += n;
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AEADEncryptor, Cipher};
+    use crate::encryption::CipherSuite;
+
+    #[test]
+    fn test_aead_tamper() {
+        let key = [7u8; 32];
+        let salt = [1u8; super::SALT_SIZE];
+
+        for suite in CipherSuite::defaults() {
+            let sealer = Cipher::derive(suite, &key, &salt).unwrap();
+            let opener = Cipher::derive(suite, &key, &salt).unwrap();
+
+            let mut sealed = sealer.seal(0, b"hello fuso").unwrap();
+            assert_eq!(opener.open(0, &sealed).unwrap(), b"hello fuso");
+            assert!(opener.open(1, &sealed).is_err());
+
+            sealed[0] ^= 0xff;
+            assert!(opener.open(0, &sealed).is_err());
+        }
+
+        assert!(Cipher::derive(CipherSuite::Aes128Cbc, &key, &salt).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "fuso-rt-tokio")]
+    fn test_aead_half_close() {
+        use crate::ext::{AsyncReadExt, AsyncWriteExt};
+
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let key = [7u8; 32];
+                let suite = CipherSuite::ChaCha20Poly1305;
+
+                let (mut visitor, s1) = tokio::io::duplex(64);
+                let (s2, target) = tokio::io::duplex(64);
+                let s2 = AEADEncryptor::new(s2, suite, key);
+                let mut target = AEADEncryptor::new(target, suite, key);
+
+                let forward = tokio::spawn(crate::io::forward(s1, s2));
+
+                visitor.write_all(b"request").await.unwrap();
+                visitor.close().await.unwrap();
+
+                let mut buf = [0u8; 7];
+                target.read_exact(&mut buf).await.unwrap();
+                assert_eq!(&buf, b"request");
+                assert_eq!(target.read(&mut buf).await.unwrap(), 0);
+
+                target.write_all(b"response").await.unwrap();
+                target.close().await.unwrap();
+
+                let mut buf = [0u8; 8];
+                visitor.read_exact(&mut buf).await.unwrap();
+                assert_eq!(&buf, b"response");
+                assert_eq!(visitor.read(&mut buf).await.unwrap(), 0);
+
+                let transferred = forward.await.unwrap().unwrap();
+                assert_eq!((transferred.forward, transferred.backward), (7, 8));
+            });
+    }
+
+    #[test]
+    #[cfg(feature = "fuso-rt-tokio")]
+    fn test_aead_truncated() {
+        use crate::ext::{AsyncReadExt, AsyncWriteExt};
+
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let key = [7u8; 32];
+                let suite = CipherSuite::ChaCha20Poly1305;
+
+                let (writer, mut raw) = tokio::io::duplex(1024);
+                let mut writer = AEADEncryptor::new(writer, suite, key);
+                writer.write_all(b"hello fuso").await.unwrap();
+                writer.close().await.unwrap();
+
+                let mut frame = vec![0u8; 1024];
+                let mut len = 0;
+                loop {
+                    match raw.read(&mut frame[len..]).await.unwrap() {
+                        0 => break,
+                        n => len += n,
+                    }
+                }
+                frame.truncate(len);
+
+                for len in [1, super::SALT_SIZE + 2, frame.len() - 1] {
+                    let (mut raw, reader) = tokio::io::duplex(1024);
+                    let mut reader = AEADEncryptor::new(reader, suite, key);
+                    raw.write_all(&frame[..len]).await.unwrap();
+                    raw.close().await.unwrap();
+
+                    let mut buf = [0u8; 10];
+                    assert!(reader.read(&mut buf).await.is_err());
+                }
+            });
+    }
+}
@@ -1,14 +1,103 @@
+#[cfg(feature = "fuso-crypt-aead")]
+mod aead;
 mod aes;
 mod rsa;
 
+#[cfg(feature = "fuso-crypt-aead")]
+pub use crate::core::encryption::aead::AEADEncryptor;
 pub use crate::core::encryption::{aes::AESEncryptor, rsa::RSAEncryptor};
 
 use std::{
+    fmt::Display,
     pin::Pin,
+    str::FromStr,
     task::{Context, Poll},
 };
 
-use crate::ReadBuf;
+use crate::{
+    error::{EncryptionErr, Kind},
+    ReadBuf,
+};
+
+/// 数据通道加密套件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CipherSuite {
+    /// 旧版本使用的加密方式, 没有完整性校验
+    Aes128Cbc,
+    Aes256Gcm,
+    ChaCha20Poly1305,
+}
+
+impl CipherSuite {
+    pub fn id(&self) -> u8 {
+        match self {
+            CipherSuite::Aes128Cbc => 0x01,
+            CipherSuite::Aes256Gcm => 0x02,
+            CipherSuite::ChaCha20Poly1305 => 0x03,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        let suite = match id {
+            0x01 => CipherSuite::Aes128Cbc,
+            0x02 => CipherSuite::Aes256Gcm,
+            0x03 => CipherSuite::ChaCha20Poly1305,
+            _ => return None,
+        };
+
+        Some(suite).filter(CipherSuite::is_supported)
+    }
+
+    /// 未开启fuso-crypt-aead时只能使用旧版本的加密方式
+    pub fn is_supported(&self) -> bool {
+        cfg!(feature = "fuso-crypt-aead") || self.is_legacy()
+    }
+
+    pub fn is_legacy(&self) -> bool {
+        CipherSuite::Aes128Cbc.eq(self)
+    }
+
+    /// 默认启用的加密套件, 不包含旧版本的加密方式
+    pub fn defaults() -> Vec<Self> {
+        if cfg!(feature = "fuso-crypt-aead") {
+            vec![CipherSuite::ChaCha20Poly1305, CipherSuite::Aes256Gcm]
+        } else {
+            vec![CipherSuite::Aes128Cbc]
+        }
+    }
+}
+
+impl FromStr for CipherSuite {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let suite = match s.to_lowercase().as_str() {
+            "aes-128-cbc" => Some(CipherSuite::Aes128Cbc),
+            "aes-256-gcm" => Some(CipherSuite::Aes256Gcm),
+            "chacha20-poly1305" => Some(CipherSuite::ChaCha20Poly1305),
+            _ => None,
+        };
+
+        match suite.filter(CipherSuite::is_supported) {
+            Some(suite) => Ok(suite),
+            None => {
+                Err(Kind::Encryption(EncryptionErr::Cipher(format!("not support {}", s))).into())
+            }
+        }
+    }
+}
+
+impl Display for CipherSuite {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            CipherSuite::Aes128Cbc => "aes-128-cbc",
+            CipherSuite::Aes256Gcm => "aes-256-gcm",
+            CipherSuite::ChaCha20Poly1305 => "chacha20-poly1305",
+        };
+
+        write!(f, "{}", name)
+    }
+}
 
 pub trait Decrypt {
     fn poll_decrypt_read(
@@ -1,6 +1,6 @@
 use std::{pin::Pin, task::Poll};
 
-use rsa::{PaddingScheme, PublicKey, RsaPrivateKey, RsaPublicKey};
+use rsa::{PaddingScheme, PublicKey, PublicKeyParts, RsaPrivateKey, RsaPublicKey};
 
 use crate::{guard::buffer::Buffer, AsyncRead, AsyncWrite, NetSocket, ReadBuf};
 
@@ -12,6 +12,7 @@ pub struct RSAEncryptor<T> {
     rbuf: Option<Vec<u8>>,
     wbuf: Option<Vec<u8>>,
     wpos: usize,
+    wlen: usize,
     rpos: usize,
     dinit: bool,
     rsa_priv: RsaPrivateKey,
@@ -28,6 +29,7 @@ impl<T> RSAEncryptor<T> {
             rbuf: Default::default(),
             wbuf: Default::default(),
             wpos: Default::default(),
+            wlen: Default::default(),
             rpos: Default::default(),
             dinit: Default::default(),
         }
@@ -88,7 +90,7 @@ where
                     Poll::Ready(n) => {
                         self.wpos += n;
                         if self.wpos == wbuf.len() {
-                            break Poll::Ready(Ok(buf.len()));
+                            break Poll::Ready(Ok(self.wlen));
                         }
                     }
                     Poll::Pending => {
@@ -128,6 +130,8 @@ where
     ) -> std::task::Poll<crate::Result<usize>> {
         let mut rng = rand::thread_rng();
         let ps = PaddingScheme::new_pkcs1v15_encrypt();
+        // pkcs1v15 单次最多加密 k - 11 字节, 超出部分由调用者再次写入
+        let buf = &buf[..buf.len().min(self.rsa_publ.size() - 11)];
         let encrypted_data = self.rsa_publ.encrypt(&mut rng, ps, buf)?;
         let encrypted_len = encrypted_data.len() as u32;
         let mut encrypted_buf = Vec::new();
@@ -148,6 +152,7 @@ where
                 }
                 std::task::Poll::Pending => {
                     self.wpos = 0;
+                    self.wlen = buf.len();
                     drop(std::mem::replace(
                         &mut self.wbuf,
                         Some(encrypted_buf[pos..].to_vec()),
@@ -202,18 +202,23 @@ where
     ) -> std::task::Poll<Self::Output> {
         let this = &mut self.0;
         if let Some(mut marked) = this.marked_buf.take() {
+            // 尚未读取的数据需要排在已标记的数据之后
             let backed_buf = if let Some(mut backed) = this.backed_buf.take() {
-                let mut buf = Vec::with_capacity(marked.len());
+                let mut buf = Vec::with_capacity(backed.len());
                 unsafe {
-                    buf.set_len(marked.len());
+                    buf.set_len(backed.len());
                 }
-                marked.read_to_buffer(&mut buf);
-                backed.push_all(buf);
-                backed
+                backed.read_to_buffer(&mut buf);
+                marked.push_all(buf);
+                marked
             } else {
                 marked
             };
-            drop(std::mem::replace(&mut this.backed_buf, Some(backed_buf)));
+
+            // 空的缓冲区会被当作连接已关闭
+            if !backed_buf.is_empty() {
+                drop(std::mem::replace(&mut this.backed_buf, Some(backed_buf)));
+            }
         }
 
         Poll::Ready(Ok(()))
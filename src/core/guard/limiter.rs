@@ -0,0 +1,632 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    future::Future,
+    pin::Pin,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, PoisonError, RwLock,
+    },
+    task::Poll,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{AsyncRead, AsyncWrite, Kind, NetSocket, ReadBuf};
+
+type Delay = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/// 带宽限制, 单位为字节每秒, 0表示不限制
+/// upload: 访问者 -> 服务, download: 服务 -> 访问者
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Bandwidth {
+    pub upload: u64,
+    pub download: u64,
+}
+
+/// 指定客户端或映射的带宽限制, 格式为 name=上传:下载, 映射的名称为 客户端名称/映射名称
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Quota {
+    key: String,
+    bandwidth: Bandwidth,
+}
+
+/// 令牌桶, 允许欠费, 欠费时等待令牌补齐后再继续读写
+#[derive(Debug)]
+struct Bucket {
+    rate: u64,
+    tokens: f64,
+    last: Instant,
+}
+
+/// 一组共享的上传与下载令牌桶
+#[derive(Debug)]
+pub struct Limiter {
+    upload: Mutex<Bucket>,
+    download: Mutex<Bucket>,
+}
+
+/// 全局, 客户端以及映射的限速器, 修改后对已建立的连接立即生效
+#[derive(Clone, Default)]
+pub struct Limits(Arc<RwLock<Registry>>);
+
+#[derive(Default)]
+struct Registry {
+    global: Arc<Limiter>,
+    entries: HashMap<String, Arc<Limiter>>,
+    /// 通过set修改过的限制, reset时保留
+    overridden: HashSet<String>,
+    global_overridden: bool,
+}
+
+/// 限制同时存在的连接数
+#[derive(Clone, Default)]
+pub struct Slots(Arc<AtomicUsize>);
+
+/// 占用的名额, 释放时归还
+pub struct Slot(Arc<AtomicUsize>);
+
+/// 作用于一个连接的限速器以及占用的名额
+#[derive(Default)]
+pub struct Lease {
+    limiters: Vec<Arc<Limiter>>,
+    slot: Option<Slot>,
+}
+
+/// 读取受upload限制, 写入受download限制, 结束时归还占用的名额
+pub struct Limited<T> {
+    target: T,
+    limiters: Vec<Arc<Limiter>>,
+    read_delay: Option<Delay>,
+    write_delay: Option<Delay>,
+    _slot: Option<Slot>,
+}
+
+fn parse_rate(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let (number, unit) = match s.char_indices().find(|(_, c)| !c.is_ascii_digit()) {
+        Some((idx, _)) => s.split_at(idx),
+        None => (s, ""),
+    };
+
+    let unit = match unit.to_ascii_lowercase().trim_end_matches('b') {
+        "" => 1,
+        "k" => 1 << 10,
+        "m" => 1 << 20,
+        "g" => 1 << 30,
+        _ => return None,
+    };
+
+    number.parse::<u64>().ok()?.checked_mul(unit)
+}
+
+fn format_rate(rate: u64) -> String {
+    match rate {
+        rate if rate != 0 && rate % (1 << 30) == 0 => format!("{}G", rate >> 30),
+        rate if rate != 0 && rate % (1 << 20) == 0 => format!("{}M", rate >> 20),
+        rate if rate != 0 && rate % (1 << 10) == 0 => format!("{}K", rate >> 10),
+        rate => rate.to_string(),
+    }
+}
+
+impl Bandwidth {
+    pub fn new(upload: u64, download: u64) -> Self {
+        Self { upload, download }
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        self.upload == 0 && self.download == 0
+    }
+}
+
+impl FromStr for Bandwidth {
+    type Err = crate::Error;
+
+    /// 1M 表示上传与下载都为1M, 512K:2M 分别表示上传与下载
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid =
+            || -> crate::Error { Kind::Message(format!("invalid bandwidth {}", s)).into() };
+        let rate = |rate: &str| parse_rate(rate).ok_or_else(invalid);
+
+        match s.split_once(':') {
+            None => rate(s).map(|rate| Self::new(rate, rate)),
+            Some((upload, download)) => Ok(Self::new(rate(upload)?, rate(download)?)),
+        }
+    }
+}
+
+impl Display for Bandwidth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}",
+            format_rate(self.upload),
+            format_rate(self.download)
+        )
+    }
+}
+
+impl Quota {
+    pub fn new(key: &str, bandwidth: Bandwidth) -> Self {
+        Self {
+            key: key.to_owned(),
+            bandwidth,
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn bandwidth(&self) -> Bandwidth {
+        self.bandwidth
+    }
+}
+
+impl FromStr for Quota {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((key, bandwidth)) if !key.trim().is_empty() => {
+                Ok(Self::new(key.trim(), bandwidth.parse()?))
+            }
+            _ => Err(Kind::Message(format!("invalid bandwidth quota {}", s)).into()),
+        }
+    }
+}
+
+impl Bucket {
+    fn new(rate: u64) -> Self {
+        Self {
+            rate,
+            tokens: rate as f64,
+            last: Instant::now(),
+        }
+    }
+
+    fn set_rate(&mut self, rate: u64) {
+        if self.rate != rate {
+            *self = Self::new(rate);
+        }
+    }
+
+    /// 欠费时需要等待的时间
+    fn delay(&mut self) -> Option<Duration> {
+        if self.rate == 0 {
+            return None;
+        }
+
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate as f64).min(self.rate as f64);
+        self.last = now;
+
+        if self.tokens >= 0.0 {
+            None
+        } else {
+            Some(Duration::from_secs_f64(-self.tokens / self.rate as f64))
+        }
+    }
+
+    fn consume(&mut self, n: usize) {
+        if self.rate != 0 {
+            self.tokens -= n as f64;
+        }
+    }
+}
+
+impl Default for Limiter {
+    fn default() -> Self {
+        Self::new(Bandwidth::default())
+    }
+}
+
+impl Limiter {
+    pub fn new(bandwidth: Bandwidth) -> Self {
+        Self {
+            upload: Mutex::new(Bucket::new(bandwidth.upload)),
+            download: Mutex::new(Bucket::new(bandwidth.download)),
+        }
+    }
+
+    pub fn bandwidth(&self) -> Bandwidth {
+        Bandwidth::new(
+            Self::lock(&self.upload).rate,
+            Self::lock(&self.download).rate,
+        )
+    }
+
+    pub fn set_bandwidth(&self, bandwidth: Bandwidth) {
+        Self::lock(&self.upload).set_rate(bandwidth.upload);
+        Self::lock(&self.download).set_rate(bandwidth.download);
+    }
+
+    fn lock(bucket: &Mutex<Bucket>) -> std::sync::MutexGuard<'_, Bucket> {
+        bucket.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Limits {
+    pub fn new(global: Bandwidth, quotas: Vec<Quota>) -> Self {
+        let limits = Self::default();
+        limits.reset(global, quotas);
+        limits
+    }
+
+    pub fn global(&self) -> Bandwidth {
+        self.read().global.bandwidth()
+    }
+
+    /// 修改全局限制, 之后reset不再替换
+    pub fn set_global(&self, bandwidth: Bandwidth) {
+        let mut registry = self.write();
+        registry.global_overridden = true;
+        registry.global.set_bandwidth(bandwidth);
+    }
+
+    pub fn get(&self, key: &str) -> Bandwidth {
+        self.read()
+            .entries
+            .get(key)
+            .map(|limiter| limiter.bandwidth())
+            .unwrap_or_default()
+    }
+
+    /// 修改客户端或映射的限制, 之后reset不再替换
+    pub fn set(&self, key: &str, bandwidth: Bandwidth) {
+        self.entry(key).set_bandwidth(bandwidth);
+        self.write().overridden.insert(key.to_owned());
+    }
+
+    /// 所有设置了限制的客户端与映射
+    pub fn list(&self) -> Vec<Quota> {
+        let mut quotas = self
+            .read()
+            .entries
+            .iter()
+            .map(|(key, limiter)| Quota::new(key, limiter.bandwidth()))
+            .filter(|quota| !quota.bandwidth.is_unlimited())
+            .collect::<Vec<_>>();
+
+        quotas.sort_by(|a, b| a.key.cmp(&b.key));
+        quotas
+    }
+
+    /// 使用新的配置替换限制, 通过set与set_global修改过的限制保持不变
+    pub fn reset(&self, global: Bandwidth, quotas: Vec<Quota>) {
+        let mut registry = self.write();
+
+        if !registry.global_overridden {
+            registry.global.set_bandwidth(global);
+        }
+
+        for (key, limiter) in registry.entries.iter() {
+            if !registry.overridden.contains(key) {
+                limiter.set_bandwidth(Bandwidth::default());
+            }
+        }
+
+        registry.evict();
+
+        let overridden = registry.overridden.clone();
+
+        drop(registry);
+
+        for quota in quotas {
+            if !overridden.contains(&quota.key) {
+                self.entry(&quota.key).set_bandwidth(quota.bandwidth);
+            }
+        }
+    }
+
+    /// 作用于名为name的映射的限速器: 全局, 客户端以及映射, name为 客户端名称[/映射名称]
+    pub fn limiters(&self, name: &str) -> Vec<Arc<Limiter>> {
+        let mut limiters = vec![self.read().global.clone()];
+
+        if let Some((client, _)) = name.split_once('/') {
+            limiters.push(self.entry(client));
+        }
+
+        limiters.push(self.entry(name));
+        limiters
+    }
+
+    fn entry(&self, key: &str) -> Arc<Limiter> {
+        if let Some(limiter) = self.read().entries.get(key) {
+            return limiter.clone();
+        }
+
+        let mut registry = self.write();
+
+        if !registry.entries.contains_key(key) {
+            registry.evict();
+        }
+
+        registry.entries.entry(key.to_owned()).or_default().clone()
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, Registry> {
+        self.0.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, Registry> {
+        self.0.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Registry {
+    /// 移除没有设置限制并且没有连接在使用的限速器, 避免随客户端名称无限增长
+    fn evict(&mut self) {
+        let overridden = &self.overridden;
+        self.entries.retain(|key, limiter| {
+            Arc::strong_count(limiter) > 1
+                || !limiter.bandwidth().is_unlimited()
+                || overridden.contains(key)
+        });
+    }
+}
+
+impl Slots {
+    /// 超过maximum时返回None, 0表示不限制
+    pub fn acquire(&self, maximum: usize) -> Option<Slot> {
+        self.0
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |active| {
+                (maximum == 0 || active < maximum).then_some(active + 1)
+            })
+            .ok()
+            .map(|_| Slot(self.0.clone()))
+    }
+
+    pub fn active(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Lease {
+    pub fn new(limiters: Vec<Arc<Limiter>>) -> Self {
+        Self {
+            limiters,
+            slot: None,
+        }
+    }
+
+    pub fn hold(mut self, slot: Option<Slot>) -> Self {
+        self.slot = slot;
+        self
+    }
+}
+
+impl<T> Limited<T> {
+    pub fn new(target: T, lease: Lease) -> Self {
+        Self {
+            target,
+            limiters: lease.limiters,
+            read_delay: None,
+            write_delay: None,
+            _slot: lease.slot,
+        }
+    }
+
+    fn poll_delay<F>(
+        delay: &mut Option<Delay>,
+        limiters: &[Arc<Limiter>],
+        bucket: F,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<()>
+    where
+        F: Fn(&Limiter) -> &Mutex<Bucket>,
+    {
+        loop {
+            if let Some(fut) = delay.as_mut() {
+                if fut.as_mut().poll(cx).is_pending() {
+                    return Poll::Pending;
+                }
+
+                *delay = None;
+            }
+
+            let wait = limiters
+                .iter()
+                .filter_map(|limiter| Limiter::lock(bucket(limiter)).delay())
+                .max();
+
+            match wait {
+                None => return Poll::Ready(()),
+                Some(wait) => *delay = Some(Box::pin(crate::time::sleep(wait))),
+            }
+        }
+    }
+
+    fn consume<F>(limiters: &[Arc<Limiter>], bucket: F, n: usize)
+    where
+        F: Fn(&Limiter) -> &Mutex<Bucket>,
+    {
+        for limiter in limiters {
+            Limiter::lock(bucket(limiter)).consume(n);
+        }
+    }
+}
+
+impl<T> NetSocket for Limited<T>
+where
+    T: NetSocket,
+{
+    fn peer_addr(&self) -> crate::Result<crate::Address> {
+        self.target.peer_addr()
+    }
+
+    fn local_addr(&self) -> crate::Result<crate::Address> {
+        self.target.local_addr()
+    }
+}
+
+impl<T> AsyncRead for Limited<T>
+where
+    T: AsyncRead + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<crate::Result<usize>> {
+        let this = &mut *self;
+
+        if Self::poll_delay(&mut this.read_delay, &this.limiters, |l| &l.upload, cx).is_pending() {
+            return Poll::Pending;
+        }
+
+        let poll = Pin::new(&mut this.target).poll_read(cx, buf);
+
+        if let Poll::Ready(Ok(n)) = poll {
+            Self::consume(&this.limiters, |l| &l.upload, n);
+        }
+
+        poll
+    }
+}
+
+impl<T> AsyncWrite for Limited<T>
+where
+    T: AsyncWrite + Unpin,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> Poll<crate::Result<usize>> {
+        let this = &mut *self;
+
+        if Self::poll_delay(&mut this.write_delay, &this.limiters, |l| &l.download, cx).is_pending()
+        {
+            return Poll::Pending;
+        }
+
+        let poll = Pin::new(&mut this.target).poll_write(cx, buf);
+
+        if let Poll::Ready(Ok(n)) = poll {
+            Self::consume(&this.limiters, |l| &l.download, n);
+        }
+
+        poll
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<crate::Result<()>> {
+        Pin::new(&mut self.target).poll_flush(cx)
+    }
+
+    fn poll_close(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<crate::Result<()>> {
+        Pin::new(&mut self.target).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{Bandwidth, Lease, Limited, Limits, Quota, Slots};
+
+    #[test]
+    fn test_limits() {
+        assert_eq!(
+            "512K:2M".parse::<Bandwidth>().unwrap(),
+            Bandwidth::new(512 << 10, 2 << 20)
+        );
+        assert_eq!(
+            "100".parse::<Bandwidth>().unwrap(),
+            Bandwidth::new(100, 100)
+        );
+        assert_eq!(Bandwidth::new(512 << 10, 3).to_string(), "512K:3");
+        assert!("1X".parse::<Bandwidth>().is_err());
+        assert!("=1M".parse::<Quota>().is_err());
+
+        let limits = Limits::new(
+            Bandwidth::new(10 << 20, 0),
+            vec![
+                "alice=1M".parse().unwrap(),
+                "alice/ssh=1K:2K".parse().unwrap(),
+            ],
+        );
+
+        assert_eq!(limits.limiters("alice/ssh").len(), 3);
+        assert_eq!(
+            limits.limiters("alice")[1].bandwidth(),
+            Bandwidth::new(1 << 20, 1 << 20)
+        );
+        assert_eq!(limits.get("alice/ssh"), Bandwidth::new(1 << 10, 2 << 10));
+
+        // 已取得的限速器随配置一起更新
+        let limiters = limits.limiters("alice/ssh");
+        limits.reset(Bandwidth::default(), vec![]);
+        assert!(limiters.iter().all(|l| l.bandwidth().is_unlimited()));
+        assert!(limits.list().is_empty());
+
+        // 没有限制也没有连接使用的限速器会被移除
+        drop(limiters);
+        drop(limits.limiters("bob"));
+        limits.limiters("carol");
+        assert!(!limits.read().entries.contains_key("bob"));
+        assert!(!limits.read().entries.contains_key("alice/ssh"));
+
+        // 通过set修改的限制在reset时保留, 包括取消的限制
+        limits.set_global(Bandwidth::new(1, 1));
+        limits.set("alice", Bandwidth::new(2, 2));
+        limits.set("bob", Bandwidth::default());
+        limits.reset(
+            Bandwidth::new(3, 3),
+            vec![
+                "alice=4".parse().unwrap(),
+                "bob=5".parse().unwrap(),
+                "dave=6".parse().unwrap(),
+            ],
+        );
+        assert_eq!(limits.global(), Bandwidth::new(1, 1));
+        assert_eq!(limits.get("alice"), Bandwidth::new(2, 2));
+        assert!(limits.get("bob").is_unlimited());
+        assert_eq!(limits.get("dave"), Bandwidth::new(6, 6));
+
+        let slots = Slots::default();
+        let slot = slots.acquire(1);
+        assert!(slot.is_some() && slots.acquire(1).is_none());
+        drop(slot);
+        assert_eq!(slots.active(), 0);
+        assert!(slots.acquire(0).is_some());
+    }
+
+    #[test]
+    #[cfg(feature = "fuso-rt-tokio")]
+    fn test_throttle() {
+        use crate::ext::AsyncWriteExt;
+
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let limits = Limits::new(Bandwidth::default(), vec!["alice=64K".parse().unwrap()]);
+                let mut stream =
+                    Limited::new(tokio::io::sink(), Lease::new(limits.limiters("alice")));
+
+                // 第一秒的额度用完后, 剩下的32K需要等待约0.5秒
+                let start = Instant::now();
+                for _ in 0..4 {
+                    stream.write_all(&[0u8; 32 << 10]).await.unwrap();
+                }
+
+                assert!(start.elapsed() >= Duration::from_millis(400));
+            });
+    }
+}
@@ -5,3 +5,9 @@ pub use fallback::*;
 
 mod timer;
 pub use timer::*;
+
+mod traffic;
+pub use traffic::*;
+
+mod limiter;
+pub use limiter::*;
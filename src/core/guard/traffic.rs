@@ -0,0 +1,216 @@
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    task::Poll,
+    time::Duration,
+};
+
+use serde::{Serialize, Serializer};
+
+use crate::{AsyncRead, AsyncWrite, NetSocket, ReadBuf};
+
+/// 连接数以及流量统计
+#[derive(Debug, Default)]
+pub struct Traffic {
+    /// 正在转发的连接数
+    active: AtomicUsize,
+    /// 累计转发的连接数
+    total: AtomicU64,
+    /// 从访问者读取的字节数
+    inbound: AtomicU64,
+    /// 写给访问者的字节数
+    outbound: AtomicU64,
+    /// 映射失败的次数
+    errors: AtomicU64,
+    #[cfg(feature = "fuso-metrics")]
+    meter: Option<crate::metrics::Meter>,
+}
+
+/// 连接结束时自动减少活跃连接数
+pub struct Connection(Arc<Traffic>);
+
+/// 统计读写字节数
+pub struct Counter<T> {
+    target: T,
+    traffic: Arc<Traffic>,
+}
+
+#[derive(Serialize)]
+struct Snapshot {
+    active: usize,
+    total: u64,
+    inbound: u64,
+    outbound: u64,
+    errors: u64,
+}
+
+impl Traffic {
+    /// 同时记录到名为name的指标中
+    pub fn new(name: &str) -> Self {
+        #[cfg(not(feature = "fuso-metrics"))]
+        let _ = name;
+
+        Self {
+            #[cfg(feature = "fuso-metrics")]
+            meter: Some(crate::metrics::metrics().meter(name)),
+            ..Default::default()
+        }
+    }
+
+    pub fn connect(self: &Arc<Self>) -> Connection {
+        self.active.fetch_add(1, Ordering::Relaxed);
+        self.total.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "fuso-metrics")]
+        if let Some(meter) = self.meter.as_ref() {
+            meter.connect();
+        }
+        Connection(self.clone())
+    }
+
+    /// 映射建立完成
+    pub fn mapped(&self, elapsed: Duration) {
+        #[cfg(not(feature = "fuso-metrics"))]
+        let _ = elapsed;
+
+        #[cfg(feature = "fuso-metrics")]
+        if let Some(meter) = self.meter.as_ref() {
+            meter.mapped(elapsed);
+        }
+    }
+
+    pub fn map_failed(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "fuso-metrics")]
+        if let Some(meter) = self.meter.as_ref() {
+            meter.map_failed();
+        }
+    }
+
+    fn add_inbound(&self, n: usize) {
+        self.inbound.fetch_add(n as u64, Ordering::Relaxed);
+        #[cfg(feature = "fuso-metrics")]
+        if let Some(meter) = self.meter.as_ref() {
+            meter.inbound(n);
+        }
+    }
+
+    fn add_outbound(&self, n: usize) {
+        self.outbound.fetch_add(n as u64, Ordering::Relaxed);
+        #[cfg(feature = "fuso-metrics")]
+        if let Some(meter) = self.meter.as_ref() {
+            meter.outbound(n);
+        }
+    }
+
+    pub fn active(&self) -> usize {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    pub fn inbound(&self) -> u64 {
+        self.inbound.load(Ordering::Relaxed)
+    }
+
+    pub fn outbound(&self) -> u64 {
+        self.outbound.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.0.active.fetch_sub(1, Ordering::Relaxed);
+        #[cfg(feature = "fuso-metrics")]
+        if let Some(meter) = self.0.meter.as_ref() {
+            meter.disconnect();
+        }
+    }
+}
+
+impl Serialize for Traffic {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        Snapshot {
+            active: self.active(),
+            total: self.total.load(Ordering::Relaxed),
+            inbound: self.inbound(),
+            outbound: self.outbound(),
+            errors: self.errors.load(Ordering::Relaxed),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<T> Counter<T> {
+    pub fn new(target: T, traffic: Arc<Traffic>) -> Self {
+        Self { target, traffic }
+    }
+}
+
+impl<T> NetSocket for Counter<T>
+where
+    T: NetSocket,
+{
+    fn peer_addr(&self) -> crate::Result<crate::Address> {
+        self.target.peer_addr()
+    }
+
+    fn local_addr(&self) -> crate::Result<crate::Address> {
+        self.target.local_addr()
+    }
+}
+
+impl<T> AsyncRead for Counter<T>
+where
+    T: AsyncRead + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<crate::Result<usize>> {
+        let poll = Pin::new(&mut self.target).poll_read(cx, buf);
+
+        if let Poll::Ready(Ok(n)) = poll {
+            self.traffic.add_inbound(n);
+        }
+
+        poll
+    }
+}
+
+impl<T> AsyncWrite for Counter<T>
+where
+    T: AsyncWrite + Unpin,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> Poll<crate::Result<usize>> {
+        let poll = Pin::new(&mut self.target).poll_write(cx, buf);
+
+        if let Poll::Ready(Ok(n)) = poll {
+            self.traffic.add_outbound(n);
+        }
+
+        poll
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<crate::Result<()>> {
+        Pin::new(&mut self.target).poll_flush(cx)
+    }
+
+    fn poll_close(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<crate::Result<()>> {
+        Pin::new(&mut self.target).poll_close(cx)
+    }
+}
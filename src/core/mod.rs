@@ -21,6 +21,9 @@ pub use boxed::*;
 mod pool;
 pub use pool::*;
 
+mod shutdown;
+pub use shutdown::*;
+
 mod socket;
 use serde::{Deserialize, Serialize};
 pub use socket::*;
@@ -107,6 +110,7 @@ impl Default for Platform {
 
 pub struct Serve {
     pub(crate) fut: Pin<Box<dyn std::future::Future<Output = crate::Result<()>> + 'static>>,
+    pub(crate) shutdown: Shutdown,
 }
 
 unsafe impl Send for Serve {}
@@ -145,6 +149,13 @@ where
     }
 }
 
+impl Fuso<Serve> {
+    /// 触发后停止接受新的连接, 正在转发的连接结束或者超时后退出
+    pub fn shutdown(&self) -> Shutdown {
+        self.0.shutdown.clone()
+    }
+}
+
 impl Future for Fuso<Serve> {
     type Output = crate::Result<()>;
 
@@ -21,6 +21,15 @@ pub trait Observer {
         log::debug!("on_handshake {}", address);
     }
 
+    #[inline]
+    fn on_handshake_error(&self, error: &crate::Error, address: &Address)
+    where
+        Self: Sized,
+    {
+        #[cfg(debug_assertions)]
+        log::debug!("on_handshake_error {:?} {}", error, address);
+    }
+
     #[inline]
     fn on_stop(&self, time: Instant, address: &Address)
     where
@@ -57,6 +66,13 @@ where
         self.as_ref().map(|obs| obs.on_error(error, address));
     }
 
+    #[inline]
+    fn on_handshake_error(&self, error: &crate::Error, address: &Address) {
+        if let Some(obs) = self {
+            obs.on_handshake_error(error, address);
+        }
+    }
+
     #[inline]
     fn on_stop(&self, time: Instant, address: &Address) {
         self.as_ref().map(|obs| obs.on_stop(time, address));
@@ -69,6 +85,11 @@ impl<T> Observer for Arc<T>
 where
     T: Observer,
 {
+    #[inline]
+    fn on_handshake_error(&self, error: &crate::Error, address: &Address) {
+        (**self).on_handshake_error(error, address)
+    }
+
     #[inline]
     fn on_stop(&self, time: Instant, address: &Address) {
         (**self).on_stop(time, address)
@@ -1,6 +1,6 @@
 use std::{ops::Deref, pin::Pin, sync::Arc};
 
-use crate::{ClientProvider, DecorateProvider, Provider, Socket};
+use crate::{ClientProvider, DecorateProvider, Provider, Shutdown, Socket};
 
 type BoxedFuture<O> = Pin<Box<dyn std::future::Future<Output = crate::Result<O>> + Send + 'static>>;
 
@@ -8,6 +8,7 @@ pub struct Processor<P, S, O> {
     provider: Arc<P>,
     observer: Option<Arc<O>>,
     decorator: Option<DecorateProvider<S>>,
+    shutdown: Shutdown,
 }
 
 impl<P, S, O> Processor<P, S, O> {
@@ -20,9 +21,19 @@ impl<P, S, O> Processor<P, S, O> {
             provider,
             observer,
             decorator,
+            shutdown: Shutdown::default(),
         }
     }
 
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    pub fn shutdown(&self) -> &Shutdown {
+        &self.shutdown
+    }
+
     pub fn observer(&self) -> &Option<Arc<O>> {
         &self.observer
     }
@@ -58,6 +69,7 @@ impl<P, S, O> Clone for Processor<P, S, O> {
             provider: self.provider.clone(),
             observer: self.observer.clone(),
             decorator: self.decorator.clone(),
+            shutdown: self.shutdown.clone(),
         }
     }
 }
@@ -24,8 +24,11 @@ pub enum Connect {
 
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 pub enum Bind {
-    Setup(Socket, Socket),
+    /// 数据通道地址, 访问地址, 客户端名称
+    Setup(Socket, Socket, String),
     Success(Address, Address),
+    /// 额外映射的访问地址, 按照映射id排列
+    Mapped(Vec<Address>),
     Failed(String),
 }
 
@@ -35,15 +38,49 @@ pub enum Auth {
     NoAuth,
 }
 
+/// 客户端身份验证
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Token {
+    /// 客户端请求验证
+    Request,
+    /// 服务端下发的随机数
+    Challenge(Vec<u8>),
+    /// 客户端使用密钥对随机数计算的hmac
+    Answer(Vec<u8>),
+    Success,
+    Failed(String),
+}
+
+/// 多路复用帧
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Frame {
+    /// 打开一个新的流
+    Syn(u32),
+    Data(u32, Vec<u8>),
+    /// 窗口更新
+    Window(u32, u32),
+    /// 半关闭
+    Fin(u32),
+    /// 重置
+    Rst(u32),
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 pub enum Poto {
     Ping,
     Close,
     MapError(u32, String),
     Bind(Bind),
-    Map(u32, Socket),
+    /// 连接id, 映射id, 目标地址
+    Map(u32, u32, Socket),
     Connect(Connect, Auth),
     Forward(Addr),
+    Mux(Frame),
+    Token(Token),
+    /// 映射id, 本地服务不可用时的http响应内容
+    Suspend(u32, Option<String>),
+    /// 映射id, 本地服务恢复
+    Resume(u32),
 }
 
 impl Packet {
@@ -99,3 +136,20 @@ impl Display for Poto {
         write!(f, "{:#?}", self)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{IntoPacket, Poto, TryToPoto};
+
+    #[test]
+    fn test_suspend_resume() {
+        let suspend = Poto::Suspend(1, Some(String::from("<h1>503</h1>")));
+        assert_eq!(suspend.clone().into_packet().try_poto().unwrap(), suspend);
+
+        let suspend = Poto::Suspend(2, None);
+        assert_eq!(suspend.clone().into_packet().try_poto().unwrap(), suspend);
+
+        let resume = Poto::Resume(1);
+        assert_eq!(resume.clone().into_packet().try_poto().unwrap(), resume);
+    }
+}
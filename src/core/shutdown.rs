@@ -0,0 +1,256 @@
+use std::{
+    future::Future,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    guard::{Slot, Slots},
+    select::Select,
+    time,
+};
+
+/// 关闭句柄, 触发后停止接受新的连接, 等待正在转发的连接结束
+#[derive(Clone)]
+pub struct Shutdown {
+    /// 只用于广播关闭, 不会发送任何数据
+    sender: async_channel::Sender<()>,
+    receiver: async_channel::Receiver<()>,
+    /// 触发时确定的最后期限, 之后不再等待转发结束
+    deadline: Arc<Mutex<Option<Instant>>>,
+    /// 正在转发的连接
+    active: Slots,
+    /// 最后一个正在转发的连接结束时通知drain
+    drained: (async_channel::Sender<()>, async_channel::Receiver<()>),
+}
+
+/// 正在转发的连接占用的名额, 释放后没有其他连接时通知drain
+struct Tracked {
+    slot: Option<Slot>,
+    active: Slots,
+    drained: async_channel::Sender<()>,
+}
+
+/// 发送给对端的下一个包
+pub(crate) enum Outbound<T> {
+    Data(T),
+    /// 超时没有数据, 需要发送心跳
+    Ping,
+    /// 已触发关闭, 需要通知对端, 只返回一次
+    Close,
+}
+
+/// 等待发送给对端的数据, 超时时发送心跳, 触发关闭时通知对端
+pub(crate) struct Keepalive {
+    closing: Option<Shutdown>,
+    timeout: Duration,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        let (sender, receiver) = async_channel::bounded(1);
+
+        Self {
+            sender,
+            receiver,
+            deadline: Default::default(),
+            active: Slots::default(),
+            drained: async_channel::bounded(1),
+        }
+    }
+}
+
+impl Shutdown {
+    /// 最多等待deadline, 重复触发时以最后一次为准
+    pub fn trigger(&self, deadline: Duration) {
+        *self.deadline.lock().unwrap_or_else(PoisonError::into_inner) =
+            Some(Instant::now() + deadline);
+        self.sender.close();
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.sender.is_closed()
+    }
+
+    /// 触发后完成
+    pub fn wait(&self) -> impl Future<Output = ()> + Send + 'static {
+        let receiver = self.receiver.clone();
+        async move {
+            let _ = receiver.recv().await;
+        }
+    }
+
+    /// 正在转发的连接, 结束前drain会一直等待
+    pub fn track<F>(&self, fut: F) -> impl Future<Output = F::Output> + Send + 'static
+    where
+        F: Future + Send + 'static,
+    {
+        let tracked = Tracked {
+            slot: self.active.acquire(0),
+            active: self.active.clone(),
+            drained: self.drained.0.clone(),
+        };
+
+        async move {
+            let _tracked = tracked;
+            fut.await
+        }
+    }
+
+    pub fn active(&self) -> usize {
+        self.active.active()
+    }
+
+    /// 等待正在转发的连接结束, 返回超时后仍未结束的数量
+    pub async fn drain(&self) -> usize {
+        let deadline = self
+            .deadline
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .unwrap_or_else(Instant::now);
+
+        while self.active() > 0 {
+            let now = Instant::now();
+
+            if now >= deadline {
+                break;
+            }
+
+            let drained = self.drained.1.clone();
+            let _ = time::wait_for(deadline - now, async move {
+                let _ = drained.recv().await;
+            })
+            .await;
+        }
+
+        self.active()
+    }
+
+    /// 第一次收到SIGTERM或者Ctrl-C时触发, 最多等待timeout, 再次收到时立即退出
+    #[cfg(feature = "fuso-rt-tokio")]
+    pub async fn on_signal(self, timeout: Duration) -> std::io::Result<()> {
+        terminated().await?;
+        log::info!(
+            "shutting down, waiting up to {}s for forwards to finish",
+            timeout.as_secs()
+        );
+        self.trigger(timeout);
+
+        terminated().await?;
+        log::warn!("received the signal again, exit immediately");
+        std::process::exit(1)
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        drop(self.slot.take());
+
+        if self.active.active() == 0 {
+            let _ = self.drained.try_send(());
+        }
+    }
+}
+
+impl Keepalive {
+    pub(crate) fn new(shutdown: Shutdown, timeout: Duration) -> Self {
+        Self {
+            closing: Some(shutdown),
+            timeout,
+        }
+    }
+
+    /// 等待next最多timeout, 超时返回Ping, 第一次触发关闭时返回Close
+    pub(crate) async fn next<F, T>(&mut self, next: F) -> crate::Result<Outbound<T>>
+    where
+        F: Future<Output = crate::Result<T>> + Send + 'static,
+        T: Send + 'static,
+    {
+        let closing = self.closing.as_ref().map(Shutdown::wait);
+        let next = time::wait_for(self.timeout, async move {
+            let next = async move { next.await.map(Outbound::Data) };
+            match closing {
+                None => next.await,
+                Some(closing) => {
+                    let closing = async move {
+                        closing.await;
+                        Ok(Outbound::Close)
+                    };
+                    // 优先检查关闭, 避免持续转发时一直无法通知
+                    Select::select(closing, next).await
+                }
+            }
+        })
+        .await;
+
+        match next {
+            Ok(Ok(Outbound::Close)) => {
+                self.closing = None;
+                Ok(Outbound::Close)
+            }
+            Ok(next) => next,
+            Err(_) => Ok(Outbound::Ping),
+        }
+    }
+}
+
+/// 等待SIGTERM或者Ctrl-C
+#[cfg(feature = "fuso-rt-tokio")]
+pub async fn terminated() -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate())?;
+
+        tokio::select! {
+            _ = terminate.recv() => Ok(()),
+            interrupted = tokio::signal::ctrl_c() => interrupted,
+        }
+    }
+
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::Shutdown;
+
+    #[test]
+    fn test_shutdown() {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let shutdown = Shutdown::default();
+                let (tx, rx) = async_channel::bounded::<()>(1);
+
+                let forward = tokio::spawn(shutdown.track(async move {
+                    let _ = rx.recv().await;
+                }));
+
+                assert!(!shutdown.is_triggered());
+                assert_eq!(shutdown.active(), 1);
+
+                shutdown.trigger(Duration::from_millis(200));
+                shutdown.wait().await;
+                assert!(shutdown.is_triggered());
+                assert_eq!(shutdown.drain().await, 1);
+
+                tx.close();
+                forward.await.unwrap();
+                assert_eq!(shutdown.drain().await, 0);
+
+                // 最后一个连接结束时立即返回, 不等待到最后期限
+                let forward = shutdown.track(tokio::time::sleep(Duration::from_millis(100)));
+                let _ = tokio::spawn(forward);
+
+                shutdown.trigger(Duration::from_secs(10));
+                let start = Instant::now();
+                assert_eq!(shutdown.drain().await, 0);
+                assert!(start.elapsed() < Duration::from_secs(1));
+            });
+    }
+}
@@ -52,20 +52,30 @@ pub enum SocketKind {
     Quic,
     /// udp forward
     Ufd,
+    /// tcp over tls
+    Tls,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, Hash)]
 pub struct Socket {
     kind: SocketKind,
-    origin: Option<Addr>,
+    origin: Option<Box<Origin>>,
     target: Addr,
     is_mixed: bool,
 }
 
+/// 访问者的真实地址, 以及访问者连接的地址
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, Hash)]
+pub struct Origin {
+    source: Addr,
+    destination: Addr,
+}
+
 impl_socket!(udp, is_udp, Udp);
 impl_socket!(kcp, is_kcp, Kcp);
 impl_socket!(tcp, is_tcp, Tcp);
 impl_socket!(quic, is_quic, Quic);
+impl_socket!(tls, is_tls, Tls);
 impl_socket!(ufd, is_ufd, Ufd);
 
 impl From<SocketAddr> for Addr {
@@ -184,6 +194,10 @@ impl SocketKind {
     pub fn is_quic(&self) -> bool {
         self == &Self::Quic
     }
+
+    pub fn is_tls(&self) -> bool {
+        self == &Self::Tls
+    }
 }
 
 #[cfg(debug_assertions)]
@@ -195,6 +209,7 @@ impl Display for SocketKind {
             SocketKind::Tcp => "TCP",
             SocketKind::Quic => "QUIC",
             SocketKind::Ufd => "UFD",
+            SocketKind::Tls => "TLS",
         };
 
         write!(f, "{}", fmt)
@@ -210,6 +225,7 @@ impl Display for SocketKind {
             SocketKind::Tcp => "T",
             SocketKind::Quic => "Q",
             SocketKind::Ufd => "F",
+            SocketKind::Tls => "S",
         };
 
         write!(f, "{}", fmt)
@@ -364,8 +380,12 @@ impl Socket {
         self.kind
     }
 
-    pub fn set_origin(&mut self, origin: Option<Addr>) {
-        self.origin = origin;
+    pub fn set_origin(&mut self, origin: Option<Origin>) {
+        self.origin = origin.map(Box::new);
+    }
+
+    pub fn origin(&self) -> Option<&Origin> {
+        self.origin.as_deref()
     }
 
     pub fn with_kind(mut self, kind: SocketKind) -> Self {
@@ -380,16 +400,35 @@ impl Socket {
 
     pub fn default_or<S: Into<Self>>(self, socket: S) -> Self {
         if self.is_default() {
-            socket
+            let mut socket = socket
                 .into()
                 .if_stream_mixed(self.is_mixed)
-                .with_kind(self.kind)
+                .with_kind(self.kind);
+            socket.origin = self.origin;
+            socket
         } else {
             self
         }
     }
 }
 
+impl Origin {
+    pub fn new(source: Addr, destination: Addr) -> Self {
+        Self {
+            source,
+            destination,
+        }
+    }
+
+    pub fn source(&self) -> &Addr {
+        &self.source
+    }
+
+    pub fn destination(&self) -> &Addr {
+        &self.destination
+    }
+}
+
 impl Address {
     pub fn first_addr(&self) -> Option<Addr> {
         match self {
@@ -489,13 +528,14 @@ impl Address {
                 let addrs = addrs.into_iter().filter(|addr| match socket.kind() {
                     SocketKind::Kcp => addr.is_kcp(),
                     SocketKind::Udp => addr.is_udp(),
-                    SocketKind::Tcp => addr.is_tcp(),
+                    SocketKind::Tcp | SocketKind::Tls => addr.is_tcp() || addr.is_tls(),
                     SocketKind::Quic => addr.is_quic(),
                     SocketKind::Ufd => {
                         addr.is_kcp()
                             || addr.is_ufd()
                             || addr.is_udp()
                             || addr.is_tcp()
+                            || addr.is_tls()
                             || addr.is_quic()
                     }
                 });
@@ -73,6 +73,9 @@ pub enum AesErr {
 #[derive(Debug)]
 pub enum EncryptionErr {
     Aes(AesErr),
+    #[cfg(feature = "fuso-crypt-aead")]
+    Aead,
+    Cipher(String),
     Rsa(rsa::errors::Error),
     RsaPkcs7(rsa::pkcs1::Error),
     RsaSpki(rsa::pkcs8::spki::Error),
@@ -115,6 +118,13 @@ pub enum Kind {
     Improper(Socket),
     Text(String),
     MaxRetries(usize),
+    Unauthorized(String),
+    Forbidden(String),
+    Identity(String),
+    #[cfg(feature = "fuso-tls")]
+    Tls(String),
+    /// 转发空闲超时
+    Idle(std::time::Duration),
 }
 
 impl Display for SyncErr {
@@ -203,6 +213,20 @@ impl From<cbc::cipher::block_padding::UnpadError> for Error {
     }
 }
 
+#[cfg(feature = "fuso-crypt-aead")]
+impl From<aes_gcm::aead::Error> for Error {
+    fn from(_: aes_gcm::aead::Error) -> Self {
+        Kind::Encryption(EncryptionErr::Aead).into()
+    }
+}
+
+#[cfg(feature = "fuso-tls")]
+impl From<tokio_rustls::rustls::Error> for Error {
+    fn from(e: tokio_rustls::rustls::Error) -> Self {
+        Kind::Tls(e.to_string()).into()
+    }
+}
+
 impl From<rsa::pkcs1::Error> for Error {
     fn from(e: rsa::pkcs1::Error) -> Self {
         Kind::Encryption(EncryptionErr::RsaPkcs7(e)).into()
@@ -250,6 +274,9 @@ impl Display for EncryptionErr {
         write!(f, "{}", {
             match self {
                 EncryptionErr::Aes(e) => format!("{}", e),
+                #[cfg(feature = "fuso-crypt-aead")]
+                EncryptionErr::Aead => String::from("aead authentication failed"),
+                EncryptionErr::Cipher(e) => format!("cipher {}", e),
                 EncryptionErr::Rsa(e) => format!("{}", e),
                 EncryptionErr::RsaPkcs7(e) => format!("{}", e),
                 EncryptionErr::RsaSpki(e) => format!("{}", e),
@@ -290,6 +317,12 @@ impl Display for Error {
             Kind::Improper(e) => format!("no suitable ones {}", e),
             Kind::Text(txt) => format!("{}", txt),
             Kind::MaxRetries(retry) => format!("exceeded maximum number of attempts {}", retry),
+            Kind::Unauthorized(e) => format!("unauthorized {}", e),
+            Kind::Forbidden(e) => format!("forbidden: {}", e),
+            Kind::Identity(e) => format!("server identity error: {}", e),
+            #[cfg(feature = "fuso-tls")]
+            Kind::Tls(e) => format!("tls error: {}", e),
+            Kind::Idle(timeout) => format!("idle for more than {:?}", timeout),
         };
         write!(f, "{}", fmt)
     }
@@ -464,4 +497,8 @@ impl Error {
             _ => false,
         }
     }
+
+    pub fn is_idle(&self) -> bool {
+        matches!(self.kind, Kind::Idle(_))
+    }
 }
@@ -1,5 +1,5 @@
 #[cfg(feature = "fuso-api")]
 pub mod routes;
 
-#[cfg(all(feature = "fuso-api", feature = "fuso-web"))]
+#[cfg(feature = "fuso-dashboard")]
 pub mod pages;
@@ -1 +1,12 @@
+use axum::{response::Html, routing::get, Router};
 
+/// 管理面板, 页面直接编译进程序, 不依赖外部资源
+const INDEX: &str = include_str!("index.html");
+
+pub fn router() -> Router {
+    Router::new().route("/", get(index))
+}
+
+async fn index() -> Html<&'static str> {
+    Html(INDEX)
+}
@@ -1,21 +1,424 @@
-use std::{future::Future, pin::Pin};
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-use crate::{server::Server, Controller, Fuso};
+use axum::{
+    body::Body,
+    extract::{Extension, Path},
+    http::{header, Request, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post, put},
+    Json, Router,
+};
+use base64::Engine;
+use serde_json::{json, Value};
+
+use crate::{
+    guard::{Bandwidth, Limits},
+    server::Server,
+    Controller, Environ, Executor, Fuso, Kind, Task,
+};
 
 type BoxedFuture<T> = Pin<Box<dyn Future<Output = crate::Result<T>> + Send + 'static>>;
 
-pub struct FusoApi {}
+type Sessions = Arc<Mutex<Registry>>;
+
+type Reload = Option<Arc<dyn Fn() -> crate::Result<()> + Send + Sync + 'static>>;
+
+type Limiting = Option<Limits>;
+
+type Token = Option<Arc<String>>;
+
+/// 管理接口, 记录所有已注册的连接
+#[derive(Clone)]
+pub struct FusoApi<E> {
+    executor: E,
+    sessions: Sessions,
+    reload: Reload,
+    limits: Limiting,
+    token: Token,
+}
+
+#[derive(Default)]
+struct Registry {
+    next_id: u64,
+    active: BTreeMap<u64, Session>,
+}
+
+struct Session {
+    env: Arc<dyn Environ + Send + Sync + 'static>,
+    task: Task<()>,
+    /// 注册时间, unix时间戳
+    since: u64,
+}
+
+impl Session {
+    fn to_json(&self, id: u64) -> Value {
+        json!({
+            "id": id,
+            "conn": self.env.conn().to_string(),
+            "visitor": self.env.visitor().map(|addr| addr.to_string()),
+            "client": self.env.client().map(|addr| addr.to_string()),
+            "since": self.since,
+            "info": self.env.info().unwrap_or(Value::Null),
+        })
+    }
+}
 
-impl Controller for FusoApi {
+impl<E> Controller for FusoApi<E>
+where
+    E: Executor + Send + Sync + 'static,
+{
     fn register(
         &self,
-        env: std::sync::Arc<dyn crate::Environ + Send + Sync + 'static>,
+        env: Arc<dyn Environ + Send + Sync + 'static>,
         fut: BoxedFuture<()>,
     ) -> crate::Result<()> {
-        unimplemented!()
+        // 持有锁直到记录完成, 避免任务先结束导致无法移除
+        let mut registry = self.sessions.lock()?;
+
+        let id = registry.next_id;
+        registry.next_id += 1;
+
+        let sessions = self.sessions.clone();
+
+        let task = self.executor.spawn(async move {
+            if let Err(e) = fut.await {
+                log::debug!("session {} finished with error {}", id, e);
+            }
+
+            if let Ok(mut registry) = sessions.lock() {
+                registry.active.remove(&id);
+            }
+        });
+
+        let since = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs())
+            .unwrap_or_default();
+
+        registry.active.insert(id, Session { env, task, since });
+
+        Ok(())
+    }
+}
+
+impl<E> FusoApi<E>
+where
+    E: Executor + Send + Sync + 'static,
+{
+    pub fn new(executor: E) -> Self {
+        Self {
+            executor,
+            sessions: Default::default(),
+            reload: None,
+            limits: None,
+            token: None,
+        }
+    }
+
+    /// 通过 POST /api/reload 重新加载配置
+    pub fn using_reload<F>(mut self, reload: F) -> Self
+    where
+        F: Fn() -> crate::Result<()> + Send + Sync + 'static,
+    {
+        self.reload = Some(Arc::new(reload));
+        self
+    }
+
+    /// 通过 /api/limits 查看与修改带宽限制
+    pub fn using_limits(mut self, limits: Limits) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    /// 请求需要携带 Authorization: Bearer <token>, 浏览器访问管理面板时以token作为密码登录,
+    /// 不指定时只能监听本地回环地址
+    pub fn using_token(mut self, token: Option<String>) -> Self {
+        self.token = token.map(Arc::new);
+        self
+    }
+
+    pub fn serve<A: Into<SocketAddr>>(&self, listen: A) -> BoxedFuture<()> {
+        let listen = listen.into();
+
+        if self.token.is_none() && !listen.ip().is_loopback() {
+            log::error!("the api requires a token to listen on {}", listen);
+            return Box::pin(async move {
+                let message = format!("the api requires a token to listen on {}", listen);
+                Err(Kind::Message(message).into())
+            });
+        }
+
+        let app = Router::new()
+            .route("/api/sessions", get(list_sessions))
+            .route("/api/sessions/:id", get(show_session).delete(abort_session))
+            .route("/api/reload", post(reload))
+            .route("/api/limits", get(list_limits).put(set_global_limit))
+            .route(
+                "/api/limits/:name",
+                put(set_client_limit).delete(remove_client_limit),
+            )
+            .route(
+                "/api/limits/:name/:mapping",
+                put(set_mapping_limit).delete(remove_mapping_limit),
+            );
+
+        #[cfg(feature = "fuso-dashboard")]
+        let app = app.merge(crate::http::pages::router());
+
+        // 管理面板与接口使用相同的token
+        let app = match self.token.clone() {
+            None => app,
+            Some(token) => app.layer(middleware::from_fn(move |req, next| {
+                authorize(token.clone(), req, next)
+            })),
+        };
+
+        let app = app
+            .layer(Extension(self.sessions.clone()))
+            .layer(Extension(self.reload.clone()))
+            .layer(Extension(self.limits.clone()));
+
+        Box::pin(async move {
+            let serve = match axum::Server::try_bind(&listen) {
+                Ok(server) => {
+                    log::info!("the api listens on {}", listen);
+                    server.serve(app.into_make_service()).await
+                }
+                Err(e) => Err(e),
+            };
+
+            serve.map_err(|e| {
+                log::error!("the api server failed {}", e);
+                Kind::Message(e.to_string()).into()
+            })
+        })
     }
 }
 
+/// 比较所有字节, 耗时与不同字节的位置无关, 避免通过耗时猜测token
+fn same(a: &str, b: &str) -> bool {
+    let diff = a
+        .bytes()
+        .zip(b.bytes())
+        .fold(0, |diff, (a, b)| diff | (a ^ b));
+    a.len() == b.len() && diff == 0
+}
+
+/// 取出请求携带的token, 支持 Bearer 以及浏览器使用的 Basic(用户名任意, 密码为token)
+fn credential(value: &str) -> Option<String> {
+    if let Some(token) = value.strip_prefix("Bearer ") {
+        return Some(token.to_owned());
+    }
+
+    let encoded = value.strip_prefix("Basic ")?;
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .ok()?;
+
+    String::from_utf8(decoded)
+        .ok()?
+        .split_once(':')
+        .map(|(_, password)| password.to_owned())
+}
+
+/// 校验请求携带的token, 失败时提示浏览器输入
+async fn authorize(token: Arc<String>, req: Request<Body>, next: Next<Body>) -> Response {
+    let authorized = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(credential)
+        .is_some_and(|value| same(&value, &token));
+
+    if authorized {
+        next.run(req).await
+    } else {
+        (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Basic realm=\"fuso\"")],
+        )
+            .into_response()
+    }
+}
+
+async fn list_sessions(
+    Extension(sessions): Extension<Sessions>,
+) -> Result<Json<Value>, StatusCode> {
+    let registry = sessions
+        .lock()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(Value::Array(
+        registry
+            .active
+            .iter()
+            .map(|(id, session)| session.to_json(*id))
+            .collect(),
+    )))
+}
+
+async fn show_session(
+    Path(id): Path<u64>,
+    Extension(sessions): Extension<Sessions>,
+) -> Result<Json<Value>, StatusCode> {
+    let registry = sessions
+        .lock()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    registry
+        .active
+        .get(&id)
+        .map(|session| Json(session.to_json(id)))
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// 中断连接, 客户端的控制连接与访问端口会随之关闭
+async fn abort_session(
+    Path(id): Path<u64>,
+    Extension(sessions): Extension<Sessions>,
+) -> StatusCode {
+    let session = match sessions.lock() {
+        Ok(mut registry) => registry.active.remove(&id),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR,
+    };
+
+    match session {
+        None => StatusCode::NOT_FOUND,
+        Some(mut session) => {
+            log::info!("abort session {} {}", id, session.env.conn());
+            session.task.abort();
+            StatusCode::NO_CONTENT
+        }
+    }
+}
+
+/// 重新加载配置, 已建立的连接不会中断
+async fn reload(Extension(reload): Extension<Reload>) -> (StatusCode, String) {
+    let reload = match reload {
+        None => return (StatusCode::NOT_FOUND, String::from("reload is not enabled")),
+        Some(reload) => reload,
+    };
+
+    match reload() {
+        Ok(()) => (StatusCode::OK, String::from("reloaded")),
+        Err(e) => {
+            log::warn!("failed to reload the configuration {}", e);
+            (StatusCode::UNPROCESSABLE_ENTITY, e.to_string())
+        }
+    }
+}
+
+async fn list_limits(Extension(limits): Extension<Limiting>) -> Result<Json<Value>, StatusCode> {
+    let limits = limits.ok_or(StatusCode::NOT_FOUND)?;
+
+    let clients = limits
+        .list()
+        .into_iter()
+        .map(|quota| (quota.key().to_owned(), json!(quota.bandwidth())))
+        .collect::<serde_json::Map<_, _>>();
+
+    Ok(Json(json!({
+        "global": limits.global(),
+        "clients": clients,
+    })))
+}
+
+async fn set_global_limit(
+    Extension(limits): Extension<Limiting>,
+    Json(bandwidth): Json<Bandwidth>,
+) -> StatusCode {
+    match limits {
+        None => StatusCode::NOT_FOUND,
+        Some(limits) => {
+            log::info!("set the global bandwidth to {}", bandwidth);
+            limits.set_global(bandwidth);
+            StatusCode::NO_CONTENT
+        }
+    }
+}
+
+/// 修改客户端或映射的带宽限制, 0表示不限制
+fn set_limit(limits: Limiting, key: &str, bandwidth: Bandwidth) -> StatusCode {
+    match limits {
+        None => StatusCode::NOT_FOUND,
+        Some(limits) => {
+            log::info!("set the bandwidth of {} to {}", key, bandwidth);
+            limits.set(key, bandwidth);
+            StatusCode::NO_CONTENT
+        }
+    }
+}
+
+async fn set_client_limit(
+    Path(name): Path<String>,
+    Extension(limits): Extension<Limiting>,
+    Json(bandwidth): Json<Bandwidth>,
+) -> StatusCode {
+    set_limit(limits, &name, bandwidth)
+}
+
+async fn remove_client_limit(
+    Path(name): Path<String>,
+    Extension(limits): Extension<Limiting>,
+) -> StatusCode {
+    set_limit(limits, &name, Bandwidth::default())
+}
+
+async fn set_mapping_limit(
+    Path((name, mapping)): Path<(String, String)>,
+    Extension(limits): Extension<Limiting>,
+    Json(bandwidth): Json<Bandwidth>,
+) -> StatusCode {
+    set_limit(limits, &format!("{}/{}", name, mapping), bandwidth)
+}
+
+async fn remove_mapping_limit(
+    Path((name, mapping)): Path<(String, String)>,
+    Extension(limits): Extension<Limiting>,
+) -> StatusCode {
+    set_limit(
+        limits,
+        &format!("{}/{}", name, mapping),
+        Bandwidth::default(),
+    )
+}
+
 impl<E, H, P, S, O> Fuso<Server<E, H, P, S, O>> {
-    
+    /// 通过管理接口管理所有连接
+    pub fn using_api<C>(mut self, api: FusoApi<C>) -> Self
+    where
+        C: Executor + Send + Sync + 'static,
+    {
+        self.0.controller = Some(Arc::new(api));
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{credential, same};
+
+    #[test]
+    fn test_credential() {
+        assert_eq!(credential("Bearer secret").as_deref(), Some("secret"));
+        // base64("admin:secret")
+        assert_eq!(
+            credential("Basic YWRtaW46c2VjcmV0").as_deref(),
+            Some("secret")
+        );
+        assert!(credential("Basic !!!").is_none());
+        assert!(credential("secret").is_none());
+
+        assert!(same("secret", "secret"));
+        assert!(!same("secret", "secreT"));
+        assert!(!same("secret", "secret1"));
+    }
 }
@@ -5,10 +5,13 @@ mod net;
 mod runtime;
 pub mod observer;
 
+#[cfg(feature = "fuso-metrics")]
+pub mod metrics;
+
 pub mod client;
 pub mod server;
 
-#[cfg(any(feature = "fuso-web", feature = "fuso-api"))]
+#[cfg(any(feature = "fuso-dashboard", feature = "fuso-api"))]
 pub mod http;
 
 pub use crate::core::*;
@@ -0,0 +1,446 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Mutex, OnceLock, PoisonError},
+    time::{Duration, Instant},
+};
+
+use axum::{http::header, response::IntoResponse, routing::get, Router};
+use prometheus::{
+    core::{Collector, MetricVec, MetricVecBuilder},
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    IntGaugeVec, Opts, Registry, TextEncoder,
+};
+
+use crate::{penetrate::PenetrateObserver, Address, Kind, Observer};
+
+type BoxedFuture<T> = Pin<Box<dyn Future<Output = crate::Result<T>> + Send + 'static>>;
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+/// prometheus指标, 服务端与客户端共用
+pub struct Metrics {
+    registry: Registry,
+    /// 在线的客户端(服务端) 或 已建立的隧道(客户端)
+    pub(crate) clients: IntGauge,
+    /// 正在转发的连接
+    pub(crate) streams: IntGaugeVec,
+    /// 转发的字节数, in: 访问者 -> 服务, out: 服务 -> 访问者
+    pub(crate) bytes: IntCounterVec,
+    pub(crate) handshake_failures: IntCounter,
+    pub(crate) map_errors: IntCounterVec,
+    /// 从访问者连入到映射建立完成的耗时
+    pub(crate) map_latency: HistogramVec,
+    pub(crate) kcp_retransmits: IntCounter,
+    /// 被黑白名单或连接数限制拒绝的访问者
+    pub(crate) denied_visitors: IntCounterVec,
+    /// 超过客户端数量限制被拒绝的客户端
+    pub(crate) rejected_clients: IntCounter,
+    /// 空闲超时被关闭的访问者
+    pub(crate) idle_visitors: IntCounterVec,
+    /// 客户端当前使用的服务端地址, 1表示正在使用
+    pub(crate) endpoints: IntGaugeVec,
+    /// 客户端切换服务端地址的次数
+    pub(crate) failovers: IntCounter,
+    /// 每个客户端名称在线的数量, 全部断开后移除该名称的指标
+    online: Mutex<HashMap<String, usize>>,
+}
+
+/// 单个客户端的指标
+#[derive(Clone)]
+pub struct Meter {
+    inbound: IntCounter,
+    outbound: IntCounter,
+    streams: IntGauge,
+    errors: IntCounter,
+    latency: Histogram,
+}
+
+/// 在原有的观察者基础上记录指标
+pub struct MetricsObserver<O> {
+    observer: O,
+}
+
+pub fn metrics() -> &'static Metrics {
+    METRICS.get_or_init(Metrics::new)
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("fuso".into()), None).unwrap();
+
+        let clients = IntGauge::new("clients", "connected clients").unwrap();
+
+        let streams =
+            IntGaugeVec::new(Opts::new("streams", "active forwarded streams"), &["name"]).unwrap();
+
+        let bytes = IntCounterVec::new(
+            Opts::new("bytes_total", "forwarded bytes"),
+            &["name", "direction"],
+        )
+        .unwrap();
+
+        let handshake_failures =
+            IntCounter::new("handshake_failures_total", "failed handshakes").unwrap();
+
+        let map_errors =
+            IntCounterVec::new(Opts::new("map_errors_total", "failed mappings"), &["name"])
+                .unwrap();
+
+        let map_latency = HistogramVec::new(
+            HistogramOpts::new("map_latency_seconds", "time taken to establish a mapping").buckets(
+                vec![
+                    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+                ],
+            ),
+            &["name"],
+        )
+        .unwrap();
+
+        let kcp_retransmits =
+            IntCounter::new("kcp_retransmits_total", "retransmitted kcp segments").unwrap();
+
+        let denied_visitors = IntCounterVec::new(
+            Opts::new(
+                "denied_visitors_total",
+                "visitors rejected by access lists or connection limits",
+            ),
+            &["name"],
+        )
+        .unwrap();
+
+        let rejected_clients = IntCounter::new(
+            "rejected_clients_total",
+            "clients rejected by the client limit",
+        )
+        .unwrap();
+
+        let idle_visitors = IntCounterVec::new(
+            Opts::new("idle_visitors_total", "visitors closed by the idle timeout"),
+            &["name"],
+        )
+        .unwrap();
+
+        let endpoints = IntGaugeVec::new(
+            Opts::new("endpoint", "the server address used by the client"),
+            &["endpoint"],
+        )
+        .unwrap();
+
+        let failovers = IntCounter::new("failovers_total", "server address switches").unwrap();
+
+        registry.register(Box::new(clients.clone())).unwrap();
+        registry.register(Box::new(streams.clone())).unwrap();
+        registry.register(Box::new(bytes.clone())).unwrap();
+        registry
+            .register(Box::new(handshake_failures.clone()))
+            .unwrap();
+        registry.register(Box::new(map_errors.clone())).unwrap();
+        registry.register(Box::new(map_latency.clone())).unwrap();
+        registry
+            .register(Box::new(kcp_retransmits.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(denied_visitors.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(rejected_clients.clone()))
+            .unwrap();
+        registry.register(Box::new(idle_visitors.clone())).unwrap();
+        registry.register(Box::new(endpoints.clone())).unwrap();
+        registry.register(Box::new(failovers.clone())).unwrap();
+
+        Self {
+            registry,
+            clients,
+            streams,
+            bytes,
+            handshake_failures,
+            map_errors,
+            map_latency,
+            kcp_retransmits,
+            denied_visitors,
+            rejected_clients,
+            idle_visitors,
+            endpoints,
+            failovers,
+            online: Default::default(),
+        }
+    }
+
+    pub(crate) fn online(&self, name: &str) {
+        *self.lock().entry(name.to_owned()).or_default() += 1;
+    }
+
+    /// 同名的客户端全部断开后移除name以及name/映射的指标, 避免标签随客户端名称无限增长
+    pub(crate) fn offline(&self, name: &str) {
+        let mut online = self.lock();
+
+        match online.get_mut(name) {
+            Some(count) if *count > 1 => *count -= 1,
+            _ => {
+                online.remove(name);
+                forget(&self.streams, name);
+                forget(&self.bytes, name);
+                forget(&self.map_errors, name);
+                forget(&self.map_latency, name);
+                forget(&self.denied_visitors, name);
+                forget(&self.idle_visitors, name);
+            }
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, usize>> {
+        self.online.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn meter(&self, name: &str) -> Meter {
+        Meter {
+            inbound: self.bytes.with_label_values(&[name, "in"]),
+            outbound: self.bytes.with_label_values(&[name, "out"]),
+            streams: self.streams.with_label_values(&[name]),
+            errors: self.map_errors.with_label_values(&[name]),
+            latency: self.map_latency.with_label_values(&[name]),
+        }
+    }
+
+    /// prometheus文本格式
+    pub fn encode(&self) -> String {
+        let mut buf = Vec::new();
+
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buf) {
+            log::warn!("failed to encode metrics {}", e);
+        }
+
+        String::from_utf8(buf).unwrap_or_default()
+    }
+
+    /// 在 /metrics 上提供指标
+    pub fn serve<A: Into<SocketAddr>>(listen: A) -> BoxedFuture<()> {
+        let listen = listen.into();
+        let app = Router::new().route("/metrics", get(export));
+
+        Box::pin(async move {
+            let serve = match axum::Server::try_bind(&listen) {
+                Ok(server) => {
+                    log::info!("the metrics listens on {}", listen);
+                    server.serve(app.into_make_service()).await
+                }
+                Err(e) => Err(e),
+            };
+
+            serve.map_err(|e| {
+                log::error!("the metrics server failed {}", e);
+                Kind::Message(e.to_string()).into()
+            })
+        })
+    }
+}
+
+/// 移除name标签为name或者name/映射的指标
+fn forget<T: MetricVecBuilder>(vec: &MetricVec<T>, name: &str) {
+    let prefix = format!("{}/", name);
+
+    for family in vec.collect() {
+        for metric in family.get_metric() {
+            let labels = metric
+                .get_label()
+                .iter()
+                .map(|label| (label.get_name(), label.get_value()))
+                .collect::<HashMap<_, _>>();
+
+            match labels.get("name") {
+                Some(&value) if value == name || value.starts_with(&prefix) => {
+                    let _ = vec.remove(&labels);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+async fn export() -> impl IntoResponse {
+    (
+        [(
+            header::CONTENT_TYPE,
+            TextEncoder::new().format_type().to_string(),
+        )],
+        metrics().encode(),
+    )
+}
+
+impl Meter {
+    pub(crate) fn inbound(&self, n: usize) {
+        self.inbound.inc_by(n as u64);
+    }
+
+    pub(crate) fn outbound(&self, n: usize) {
+        self.outbound.inc_by(n as u64);
+    }
+
+    pub(crate) fn connect(&self) {
+        self.streams.inc();
+    }
+
+    pub(crate) fn disconnect(&self) {
+        self.streams.dec();
+    }
+
+    pub(crate) fn map_failed(&self) {
+        self.errors.inc();
+    }
+
+    pub(crate) fn mapped(&self, elapsed: Duration) {
+        self.latency.observe(elapsed.as_secs_f64());
+    }
+}
+
+impl std::fmt::Debug for Meter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Meter").finish_non_exhaustive()
+    }
+}
+
+impl<O> MetricsObserver<O> {
+    pub fn new(observer: O) -> Self {
+        Self { observer }
+    }
+}
+
+impl<O> Observer for MetricsObserver<O>
+where
+    O: Observer,
+{
+    fn on_connect(&self, address: &Address) {
+        self.observer.on_connect(address)
+    }
+
+    fn on_handshake(&self, address: &Address) {
+        self.observer.on_handshake(address)
+    }
+
+    fn on_handshake_error(&self, error: &crate::Error, address: &Address) {
+        metrics().handshake_failures.inc();
+        self.observer.on_handshake_error(error, address)
+    }
+
+    fn on_stop(&self, time: Instant, address: &Address) {
+        self.observer.on_stop(time, address)
+    }
+
+    fn on_error(&self, error: &crate::Error, address: &Address) {
+        self.observer.on_error(error, address)
+    }
+}
+
+impl<O> PenetrateObserver for MetricsObserver<O>
+where
+    O: PenetrateObserver,
+{
+    fn on_pen_start(
+        &self,
+        client: &Address,
+        visit: &Address,
+        server: &Address,
+        config: &crate::penetrate::server::Config,
+    ) {
+        metrics().clients.inc();
+        metrics().online(config.name());
+        self.observer.on_pen_start(client, visit, server, config)
+    }
+
+    fn on_pen_stop(
+        &self,
+        client: &Address,
+        visit: &Address,
+        server: &Address,
+        config: &crate::penetrate::server::Config,
+    ) {
+        metrics().clients.dec();
+        metrics().offline(config.name());
+        self.observer.on_pen_stop(client, visit, server, config)
+    }
+
+    fn on_pen_route(&self, client: &Address, from: &Address, to: &Address) {
+        self.observer.on_pen_route(client, from, to)
+    }
+
+    fn on_pen_error(
+        &self,
+        client: &Address,
+        config: &crate::penetrate::server::Config,
+        error: &crate::Error,
+    ) {
+        self.observer.on_pen_error(client, config, error)
+    }
+
+    fn on_pen_deny(
+        &self,
+        client: &Address,
+        visit: &Address,
+        config: &crate::penetrate::server::Config,
+        reason: &str,
+    ) {
+        metrics()
+            .denied_visitors
+            .with_label_values(&[config.name()])
+            .inc();
+        self.observer.on_pen_deny(client, visit, config, reason)
+    }
+
+    fn on_pen_reject(
+        &self,
+        client: &Address,
+        config: &crate::penetrate::server::Config,
+        reason: &str,
+    ) {
+        metrics().rejected_clients.inc();
+        self.observer.on_pen_reject(client, config, reason)
+    }
+
+    fn on_pen_idle(
+        &self,
+        client: &Address,
+        visit: &Address,
+        config: &crate::penetrate::server::Config,
+        timeout: Duration,
+    ) {
+        metrics()
+            .idle_visitors
+            .with_label_values(&[config.name()])
+            .inc();
+        self.observer.on_pen_idle(client, visit, config, timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Metrics;
+
+    #[test]
+    fn test_offline() {
+        let metrics = Metrics::new();
+
+        for name in ["alice", "alice/ssh", "bob"] {
+            metrics.meter(name).inbound(1);
+            metrics.idle_visitors.with_label_values(&[name]).inc();
+        }
+
+        metrics.online("alice");
+        metrics.online("alice");
+        metrics.online("bob");
+
+        // 同名的客户端仍然在线时保留
+        metrics.offline("alice");
+        assert!(metrics.encode().contains("name=\"alice/ssh\""));
+
+        metrics.offline("alice");
+        let encoded = metrics.encode();
+        assert!(!encoded.contains("name=\"alice\""));
+        assert!(!encoded.contains("name=\"alice/ssh\""));
+        assert!(encoded.contains("fuso_bytes_total{direction=\"in\",name=\"bob\"} 1"));
+        assert!(encoded.contains("fuso_idle_visitors_total{name=\"bob\"} 1"));
+    }
+}
@@ -179,6 +179,8 @@ where
             write_waker: None,
             read_waker: None,
             close_waker: None,
+            read_eof: false,
+            write_eof: false,
         }));
 
         let kupdate = KcpUpdate {
@@ -821,4 +823,53 @@ mod tests {
                 }
             });
     }
+
+    #[test]
+    #[cfg(feature = "fuso-rt-tokio")]
+    pub fn test_forward_half_close() {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let udp = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+                let addr = udp.local_addr().unwrap();
+                let mut listener = KcpListener::bind(Arc::new(udp), FusoExecutor).unwrap();
+
+                // 只有在accept时才会处理收到的数据
+                let (sender, receiver) = async_channel::unbounded();
+                tokio::spawn(async move {
+                    while let Ok(kcp) = listener.accept().await {
+                        let _ = sender.send(kcp).await;
+                    }
+                });
+
+                let udp = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+                udp.connect(addr).await.unwrap();
+                // 连接器负责接收数据, 需要一直持有
+                let connector = KcpConnector::new(Arc::new(udp), FusoExecutor);
+                let kcp = connector.connect().await.unwrap();
+
+                let (mut visitor, s1) = tokio::io::duplex(64);
+                let forward = tokio::spawn(io::forward(s1, kcp));
+
+                visitor.write_all(b"request").await.unwrap();
+                visitor.close().await.unwrap();
+
+                let mut target = receiver.recv().await.unwrap();
+                let mut buf = [0u8; 7];
+                target.read_exact(&mut buf).await.unwrap();
+                assert_eq!(&buf, b"request");
+                assert_eq!(target.read(&mut buf).await.unwrap(), 0);
+
+                target.write_all(b"response").await.unwrap();
+                target.close().await.unwrap();
+
+                let mut buf = [0u8; 8];
+                visitor.read_exact(&mut buf).await.unwrap();
+                assert_eq!(&buf, b"response");
+                assert_eq!(visitor.read(&mut buf).await.unwrap(), 0);
+
+                let transferred = forward.await.unwrap().unwrap();
+                assert_eq!((transferred.forward, transferred.backward), (7, 8));
+            });
+    }
 }
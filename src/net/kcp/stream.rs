@@ -28,6 +28,10 @@ pub struct KcpCore<C> {
     pub(crate) read_waker: Option<Waker>,
     pub(crate) write_waker: Option<Waker>,
     pub(crate) close_waker: Option<Waker>,
+    /// 对端已经关闭写, 之后的读总是返回0
+    pub(crate) read_eof: bool,
+    /// 已经关闭写, 不能再继续写入
+    pub(crate) write_eof: bool,
 }
 
 pub struct KcpStream<C> {
@@ -35,7 +39,7 @@ pub struct KcpStream<C> {
     pub(crate) local_addr: Address,
     pub(crate) peer_addr: Address,
     pub(crate) kcore: Arc<std::sync::Mutex<KcpCore<C>>>,
-    /// 在被drop或手动调用close时触发通知内部进行更新
+    /// 在被drop时触发通知内部进行更新, 手动调用close只关闭写
     pub(crate) close_callback: Callback,
 }
 
@@ -87,6 +91,17 @@ where
         cx: &mut std::task::Context<'_>,
         buf: &[u8],
     ) -> Poll<crate::Result<usize>> {
+        if self.write_eof {
+            return Poll::Ready(Err(
+                KcpErr::IoError(std::io::ErrorKind::BrokenPipe.into()).into()
+            ));
+        }
+
+        // 空消息用于通知对端关闭写
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
         match self.kcp.send(buf) {
             Ok(n) => Poll::Ready(Ok(n)),
             Err(e) => match e.kind() {
@@ -134,13 +149,26 @@ where
     ) -> Poll<crate::Result<usize>> {
         let unfilled = buf.initialize_unfilled();
 
+        if unfilled.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
         if !self.kbuf.is_empty() {
             let n = self.kbuf.read_to_buffer(unfilled);
             buf.advance(n);
             return Poll::Ready(Ok(n));
         }
 
+        if self.read_eof {
+            return Poll::Ready(Ok(0));
+        }
+
         match self.kcp.recv(unfilled) {
+            Ok(0) => {
+                log::debug!("peer shutdown write conv={}", self.kcp.conv());
+                self.read_eof = true;
+                Poll::Ready(Ok(0))
+            }
             Ok(n) => {
                 buf.advance(n);
                 return Poll::Ready(Ok(n));
@@ -211,14 +239,26 @@ where
     }
 
     fn poll_close(
-        mut self: std::pin::Pin<&mut Self>,
+        self: std::pin::Pin<&mut Self>,
         _: &mut std::task::Context<'_>,
     ) -> std::task::Poll<crate::Result<()>> {
-        if let Some(close_callback) = self.close_callback.take() {
-            close_callback();
+        let mut kcore = self.kcore.lock()?;
+        Poll::Ready(kcore.shutdown())
+    }
+}
+
+impl<C> KcpCore<C>
+where
+    C: UdpSocket + Unpin + 'static,
+{
+    /// 关闭写, 发送一个空消息, 对端读到后返回eof, 读不受影响
+    fn shutdown(&mut self) -> crate::Result<()> {
+        if !self.write_eof {
+            self.kcp.send(&[])?;
+            self.write_eof = true;
         }
 
-        Poll::Ready(Ok(()))
+        Ok(())
     }
 }
 
@@ -939,6 +939,8 @@ where
 
         let mut lost = false;
         let mut change = 0;
+        #[cfg(feature = "fuso-metrics")]
+        let mut retransmits = 0;
 
         for snd_segment in &mut self.snd_buf {
             let mut need_send = false;
@@ -959,12 +961,20 @@ where
                 }
                 snd_segment.resendts = self.current + snd_segment.rto;
                 lost = true;
+                #[cfg(feature = "fuso-metrics")]
+                {
+                    retransmits += 1;
+                }
             } else if snd_segment.fastack >= resent {
                 need_send = true;
                 snd_segment.xmit += 1;
                 snd_segment.fastack = 0;
                 snd_segment.resendts = self.current + snd_segment.rto;
                 change += 1;
+                #[cfg(feature = "fuso-metrics")]
+                {
+                    retransmits += 1;
+                }
             }
 
             if need_send {
@@ -987,6 +997,11 @@ where
             }
         }
 
+        #[cfg(feature = "fuso-metrics")]
+        if retransmits > 0 {
+            crate::metrics::metrics().kcp_retransmits.inc_by(retransmits);
+        }
+
         // Flush all data in buffer
         if !self.buf.is_empty() {
             self.output.write_all(&self.buf).await?;
@@ -6,9 +6,14 @@ pub mod kcp;
 #[cfg(feature = "fuso-quic")]
 pub mod quic;
 
+#[cfg(feature = "fuso-tls")]
+pub mod tls;
+
 #[cfg(feature = "fuso-socks5")]
 pub mod socks;
 
+pub mod mux;
+
 pub mod tun;
 
 #[cfg(feature = "fuso-proxy")]
@@ -0,0 +1,480 @@
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::{Arc, Mutex, Weak},
+    task::{Context, Poll, Waker},
+};
+
+use crate::{
+    guard::buffer::Buffer, protocol::Frame, Address, AsyncRead, AsyncWrite, Kind, NetSocket,
+    ReadBuf,
+};
+
+/// 每个流的初始窗口大小
+pub const INITIAL_WINDOW: u32 = 256 * 1024;
+
+/// 单个数据帧的最大长度
+pub const MAXIMUM_FRAME: usize = 16 * 1024;
+
+/// 对端打开但还没有被accept的流的最大数量, 超过后直接重置
+pub const MAXIMUM_PENDING: usize = 128;
+
+/// 在控制通道上承载多个逻辑流,
+/// 服务端分配偶数id, 客户端分配奇数id
+#[derive(Clone)]
+pub struct Session(Arc<Inner>);
+
+struct Inner {
+    next_id: Mutex<u32>,
+    local_addr: Address,
+    peer_addr: Address,
+    streams: Mutex<HashMap<u32, Arc<Mutex<Shared>>>>,
+    outbound: (async_channel::Sender<Frame>, async_channel::Receiver<Frame>),
+    incoming: (
+        async_channel::Sender<MuxStream>,
+        async_channel::Receiver<MuxStream>,
+    ),
+}
+
+#[derive(Default)]
+struct Shared {
+    rbuf: Buffer<u8>,
+    recv_window: u32,
+    send_window: u32,
+    consumed: u32,
+    fin_recv: bool,
+    fin_sent: bool,
+    reset: bool,
+    read_waker: Option<Waker>,
+    write_waker: Option<Waker>,
+}
+
+pub struct MuxStream {
+    id: u32,
+    local_addr: Address,
+    peer_addr: Address,
+    shared: Arc<Mutex<Shared>>,
+    session: Weak<Inner>,
+    outbound: async_channel::Sender<Frame>,
+}
+
+impl Shared {
+    fn new() -> Self {
+        Self {
+            recv_window: INITIAL_WINDOW,
+            send_window: INITIAL_WINDOW,
+            ..Default::default()
+        }
+    }
+
+    fn wake(&mut self) {
+        if let Some(waker) = self.read_waker.take() {
+            waker.wake();
+        }
+
+        if let Some(waker) = self.write_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl Session {
+    pub fn new(is_server: bool, local_addr: Address, peer_addr: Address) -> Self {
+        Self(Arc::new(Inner {
+            next_id: Mutex::new(if is_server { 2 } else { 1 }),
+            local_addr,
+            peer_addr,
+            streams: Default::default(),
+            outbound: async_channel::unbounded(),
+            incoming: async_channel::bounded(MAXIMUM_PENDING),
+        }))
+    }
+
+    /// 打开一个新的流, 对端将通过 [`Session::accept`] 得到这个流
+    pub fn open(&self) -> crate::Result<MuxStream> {
+        let mut streams = self.0.streams.lock()?;
+        let mut next_id = self.0.next_id.lock()?;
+
+        let id = loop {
+            let id = *next_id;
+            *next_id = match id.checked_add(2) {
+                Some(next) => next,
+                None => id % 2 + 2,
+            };
+
+            if id != 0 && !streams.contains_key(&id) {
+                break id;
+            }
+        };
+
+        let stream = self.new_stream(id);
+
+        streams.insert(id, stream.shared.clone());
+
+        self.send(Frame::Syn(id))?;
+
+        log::trace!("mux stream {} opened", id);
+
+        Ok(stream)
+    }
+
+    pub async fn accept(&self) -> crate::Result<MuxStream> {
+        self.0.incoming.1.recv().await.map_err(Into::into)
+    }
+
+    /// 等待下一个需要发送到对端的帧
+    pub async fn next_frame(&self) -> crate::Result<Frame> {
+        self.0.outbound.1.recv().await.map_err(Into::into)
+    }
+
+    /// 处理对端发送过来的帧
+    pub fn dispatch(&self, frame: Frame) -> crate::Result<()> {
+        match frame {
+            Frame::Syn(id) => {
+                let mut streams = self.0.streams.lock()?;
+
+                if streams.contains_key(&id) {
+                    log::warn!("mux stream {} already exists", id);
+                    return self.send(Frame::Rst(id));
+                }
+
+                // 对端只能使用另一种奇偶的id
+                if id % 2 == *self.0.next_id.lock()? % 2 {
+                    log::warn!("mux stream {} is not allowed to be opened by peer", id);
+                    return self.send(Frame::Rst(id));
+                }
+
+                let stream = self.new_stream(id);
+                streams.insert(id, stream.shared.clone());
+                drop(streams);
+
+                // 没有accept或者accept太慢时重置, drop时会通知对端
+                if let Err(e) = self.0.incoming.0.try_send(stream) {
+                    log::warn!("too many pending mux streams, reset {}", id);
+                    drop(e.into_inner());
+                }
+            }
+            Frame::Data(id, data) => {
+                let shared = match self.0.streams.lock()?.get(&id) {
+                    Some(shared) => shared.clone(),
+                    None => {
+                        log::trace!("mux stream {} not found, discard {}bytes", id, data.len());
+                        return Ok(());
+                    }
+                };
+
+                let mut shared = shared.lock()?;
+
+                if data.len() > shared.recv_window as usize {
+                    log::warn!("mux stream {} exceeded the receive window", id);
+                    shared.reset = true;
+                    shared.wake();
+                    drop(shared);
+                    self.0.streams.lock()?.remove(&id);
+                    return self.send(Frame::Rst(id));
+                }
+
+                shared.recv_window -= data.len() as u32;
+                shared.rbuf.push_all(data);
+                shared.wake();
+            }
+            Frame::Window(id, size) => {
+                if let Some(shared) = self.0.streams.lock()?.get(&id) {
+                    let mut shared = shared.lock()?;
+                    shared.send_window = shared.send_window.saturating_add(size);
+                    shared.wake();
+                }
+            }
+            Frame::Fin(id) => {
+                if let Some(shared) = self.0.streams.lock()?.get(&id) {
+                    let mut shared = shared.lock()?;
+                    shared.fin_recv = true;
+                    shared.wake();
+                }
+            }
+            Frame::Rst(id) => {
+                if let Some(shared) = self.0.streams.lock()?.remove(&id) {
+                    let mut shared = shared.lock()?;
+                    shared.reset = true;
+                    shared.wake();
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 关闭会话, 所有未结束的流都将被重置
+    pub fn close(&self) {
+        if let Ok(mut streams) = self.0.streams.lock() {
+            for (_, shared) in streams.drain() {
+                if let Ok(mut shared) = shared.lock() {
+                    shared.reset = true;
+                    shared.wake();
+                }
+            }
+        }
+
+        self.0.outbound.0.close();
+        self.0.incoming.0.close();
+
+        while let Ok(stream) = self.0.incoming.1.try_recv() {
+            drop(stream);
+        }
+    }
+
+    fn send(&self, frame: Frame) -> crate::Result<()> {
+        self.0
+            .outbound
+            .0
+            .try_send(frame)
+            .map_err(|_| Kind::Channel.into())
+    }
+
+    fn new_stream(&self, id: u32) -> MuxStream {
+        MuxStream {
+            id,
+            local_addr: self.0.local_addr.clone(),
+            peer_addr: self.0.peer_addr.clone(),
+            shared: Arc::new(Mutex::new(Shared::new())),
+            session: Arc::downgrade(&self.0),
+            outbound: self.0.outbound.0.clone(),
+        }
+    }
+}
+
+impl MuxStream {
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    fn send(&self, frame: Frame) -> crate::Result<()> {
+        self.outbound
+            .try_send(frame)
+            .map_err(|_| std::io::ErrorKind::ConnectionReset.into())
+    }
+}
+
+impl NetSocket for MuxStream {
+    fn peer_addr(&self) -> crate::Result<Address> {
+        Ok(self.peer_addr.clone())
+    }
+
+    fn local_addr(&self) -> crate::Result<Address> {
+        Ok(self.local_addr.clone())
+    }
+}
+
+impl AsyncRead for MuxStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<crate::Result<usize>> {
+        let mut shared = self.shared.lock()?;
+
+        if !shared.rbuf.is_empty() {
+            let n = shared.rbuf.read_to_buffer(buf.initialize_unfilled());
+            buf.advance(n);
+
+            shared.consumed += n as u32;
+
+            if shared.consumed >= INITIAL_WINDOW / 2 && !shared.reset {
+                let consumed = std::mem::take(&mut shared.consumed);
+                shared.recv_window += consumed;
+                self.send(Frame::Window(self.id, consumed))?;
+            }
+
+            Poll::Ready(Ok(n))
+        } else if shared.reset {
+            Poll::Ready(Err(std::io::ErrorKind::ConnectionReset.into()))
+        } else if shared.fin_recv {
+            Poll::Ready(Ok(0))
+        } else {
+            shared.read_waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl AsyncWrite for MuxStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<crate::Result<usize>> {
+        let mut shared = self.shared.lock()?;
+
+        if shared.reset {
+            return Poll::Ready(Err(std::io::ErrorKind::ConnectionReset.into()));
+        }
+
+        if shared.fin_sent {
+            return Poll::Ready(Err(std::io::ErrorKind::BrokenPipe.into()));
+        }
+
+        if shared.send_window == 0 {
+            shared.write_waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+
+        let n = buf
+            .len()
+            .min(MAXIMUM_FRAME)
+            .min(shared.send_window as usize);
+
+        shared.send_window -= n as u32;
+
+        self.send(Frame::Data(self.id, buf[..n].to_vec()))?;
+
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<crate::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<crate::Result<()>> {
+        let mut shared = self.shared.lock()?;
+
+        if !shared.fin_sent && !shared.reset {
+            shared.fin_sent = true;
+            self.send(Frame::Fin(self.id))?;
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for MuxStream {
+    fn drop(&mut self) {
+        let finished = match self.shared.lock() {
+            Ok(shared) => shared.reset || (shared.fin_sent && shared.fin_recv),
+            Err(_) => false,
+        };
+
+        if !finished {
+            log::trace!("mux stream {} dropped before finishing, reset", self.id);
+            let _ = self.send(Frame::Rst(self.id));
+        }
+
+        if let Some(session) = self.session.upgrade() {
+            if let Ok(mut streams) = session.streams.lock() {
+                streams.remove(&self.id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "fuso-rt-tokio")]
+mod tests {
+    use crate::{
+        ext::{AsyncReadExt, AsyncWriteExt},
+        Address, Socket,
+    };
+
+    use super::{Session, INITIAL_WINDOW, MAXIMUM_PENDING};
+    use crate::protocol::Frame;
+
+    fn pair() -> (Session, Session) {
+        let addr = Address::One(Socket::tcp(0));
+        (
+            Session::new(true, addr.clone(), addr.clone()),
+            Session::new(false, addr.clone(), addr),
+        )
+    }
+
+    fn pump(from: &Session, to: &Session) {
+        while let Ok(frame) = from.0.outbound.1.try_recv() {
+            to.dispatch(frame).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_mux_open_and_transfer() {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let (server, client) = pair();
+
+                let mut s1 = server.open().unwrap();
+                pump(&server, &client);
+                let mut s2 = client.accept().await.unwrap();
+
+                assert_eq!(s1.id(), s2.id());
+
+                s1.write_all(b"hello world").await.unwrap();
+                s1.close().await.unwrap();
+                pump(&server, &client);
+
+                let mut buf = [0u8; 11];
+                s2.read_exact(&mut buf).await.unwrap();
+                assert_eq!(&buf, b"hello world");
+                assert_eq!(s2.read(&mut buf).await.unwrap(), 0);
+            });
+    }
+
+    #[test]
+    fn test_mux_window_and_reset() {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let (server, client) = pair();
+
+                let mut s1 = server.open().unwrap();
+                pump(&server, &client);
+                let mut s2 = client.accept().await.unwrap();
+
+                let data = vec![1u8; INITIAL_WINDOW as usize];
+                s1.write_all(&data).await.unwrap();
+                pump(&server, &client);
+
+                let write = tokio::time::timeout(
+                    std::time::Duration::from_millis(50),
+                    s1.write_all(b"x"),
+                )
+                .await;
+
+                assert!(write.is_err(), "send window should be exhausted");
+
+                let mut buf = vec![0u8; INITIAL_WINDOW as usize];
+                s2.read_exact(&mut buf).await.unwrap();
+                pump(&client, &server);
+
+                s1.write_all(b"x").await.unwrap();
+
+                drop(s2);
+                pump(&client, &server);
+
+                assert!(s1.write_all(b"x").await.is_err());
+            });
+    }
+
+    #[test]
+    fn test_mux_pending_limit() {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let (server, client) = pair();
+
+                let streams = (0..=MAXIMUM_PENDING)
+                    .map(|_| server.open().unwrap())
+                    .collect::<Vec<_>>();
+
+                pump(&server, &client);
+                pump(&client, &server);
+
+                let mut last = streams.into_iter().last().unwrap();
+                assert!(last.write_all(b"x").await.is_err());
+
+                // 对端使用本端的id打开流时直接重置
+                client.dispatch(Frame::Syn(1)).unwrap();
+                assert_eq!(client.0.outbound.1.try_recv().unwrap(), Frame::Rst(1));
+
+                let mut first = client.accept().await.unwrap();
+                assert_eq!(first.id(), 2);
+                assert!(first.write_all(b"x").await.is_ok());
+            });
+    }
+}
@@ -1,15 +1,20 @@
 use std::{pin::Pin, task::Poll};
 
-use crate::{Accepter, Address, NetSocket, Stream};
+use crate::{guard::Fallback, Accepter, Address, NetSocket, Stream};
 
 pub enum Pen<S> {
-    Visit(S),
+    /// 映射id, 访问者
+    Visit(u32, S),
     Client(S),
+    /// 通过共享的http端口转发过来的访问者, 已读取的请求头保存在Fallback中
+    Shared(Fallback<S>),
 }
 
+/// 每个映射对应一个accepter, 只有默认映射接收客户端的数据连接
 pub struct PenetrateAccepter<CA, SA> {
+    mapping: u32,
     visit: SA,
-    client: CA,
+    client: Option<CA>,
 }
 
 impl<CA, SA, S> PenetrateAccepter<CA, SA>
@@ -18,7 +23,19 @@ where
     SA: Accepter<Stream = S> + Unpin + 'static,
 {
     pub fn new(visit: SA, client: CA) -> Self {
-        Self { visit, client }
+        Self {
+            mapping: 0,
+            visit,
+            client: Some(client),
+        }
+    }
+
+    pub fn mapping(mapping: u32, visit: SA) -> Self {
+        Self {
+            mapping,
+            visit,
+            client: None,
+        }
     }
 }
 
@@ -28,11 +45,17 @@ where
     SA: NetSocket,
 {
     fn local_addr(&self) -> crate::Result<crate::Address> {
-        Ok(self.visit.local_addr()? + self.client.local_addr()?)
+        match self.client.as_ref() {
+            None => self.visit.local_addr(),
+            Some(client) => Ok(self.visit.local_addr()? + client.local_addr()?),
+        }
     }
 
     fn peer_addr(&self) -> crate::Result<Address> {
-        Ok(self.visit.peer_addr()? + self.client.peer_addr()?)
+        match self.client.as_ref() {
+            None => self.visit.peer_addr(),
+            Some(client) => Ok(self.visit.peer_addr()? + client.peer_addr()?),
+        }
     }
 }
 
@@ -49,14 +72,22 @@ where
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<crate::Result<Self::Stream>> {
         let mut poll_next = true;
+        let mapping = self.mapping;
 
         while poll_next {
             match Pin::new(&mut self.visit).poll_accept(cx)? {
-                std::task::Poll::Ready(visit) => return Poll::Ready(Ok(Pen::Visit(visit))),
+                std::task::Poll::Ready(visit) => {
+                    return Poll::Ready(Ok(Pen::Visit(mapping, visit)))
+                }
                 std::task::Poll::Pending => {}
             }
 
-            match Pin::new(&mut self.client).poll_accept(cx)? {
+            let client = match self.client.as_mut() {
+                None => return Poll::Pending,
+                Some(client) => client,
+            };
+
+            match Pin::new(client).poll_accept(cx)? {
                 std::task::Poll::Ready(client) => return Poll::Ready(Ok(Pen::Client(client))),
                 std::task::Poll::Pending => {
                     poll_next = false;
@@ -0,0 +1,265 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    net::IpAddr,
+    str::FromStr,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Address, Kind};
+
+/// 网段, 例如 192.168.0.0/16, 不指定前缀时表示单个地址
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cidr {
+    ip: IpAddr,
+    prefix: u8,
+}
+
+/// 访问者的黑白名单, 黑名单优先, 白名单为空时不限制
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AccessList {
+    allow: Vec<Cidr>,
+    deny: Vec<Cidr>,
+}
+
+/// 限制每个访问者ip每秒新建的连接数, 所有映射共享
+#[derive(Clone, Default)]
+pub struct Throttle(Arc<Mutex<Windows>>);
+
+/// 每个ip当前一秒的开始时间与连接数
+struct Windows {
+    entries: HashMap<IpAddr, (Instant, u32)>,
+    /// 上一次清理过期记录的时间
+    swept: Instant,
+}
+
+impl Cidr {
+    pub fn new(ip: IpAddr, prefix: u8) -> crate::Result<Self> {
+        let max = if ip.is_ipv4() { 32 } else { 128 };
+
+        if prefix > max {
+            return Err(Kind::Message(format!("invalid cidr {}/{}", ip, prefix)).into());
+        }
+
+        Ok(Self { ip, prefix })
+    }
+
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        // 双栈监听时ipv4访问者的地址为 ::ffff:a.b.c.d
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(*ip),
+            ip => *ip,
+        };
+
+        match (self.ip, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => Self::matches(
+                u32::from(net) as u128,
+                u32::from(ip) as u128,
+                32,
+                self.prefix,
+            ),
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                Self::matches(u128::from(net), u128::from(ip), 128, self.prefix)
+            }
+            _ => false,
+        }
+    }
+
+    fn matches(net: u128, ip: u128, bits: u8, prefix: u8) -> bool {
+        let shift = (bits - prefix) as u32;
+        net.checked_shr(shift).unwrap_or(0) == ip.checked_shr(shift).unwrap_or(0)
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || -> crate::Error { Kind::Message(format!("invalid cidr {}", s)).into() };
+
+        let (ip, prefix) = match s.trim().split_once('/') {
+            Some((ip, prefix)) => (ip, Some(prefix)),
+            None => (s.trim(), None),
+        };
+
+        let ip = ip.parse::<IpAddr>().map_err(|_| invalid())?;
+
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse::<u8>().map_err(|_| invalid())?,
+            None if ip.is_ipv4() => 32,
+            None => 128,
+        };
+
+        Self::new(ip, prefix)
+    }
+}
+
+impl Display for Cidr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.ip, self.prefix)
+    }
+}
+
+impl AccessList {
+    pub fn allow(mut self, cidrs: Vec<Cidr>) -> Self {
+        self.allow = cidrs;
+        self
+    }
+
+    pub fn deny(mut self, cidrs: Vec<Cidr>) -> Self {
+        self.deny = cidrs;
+        self
+    }
+
+    pub(crate) fn push_allow(&mut self, cidr: Cidr) {
+        self.allow.push(cidr);
+    }
+
+    pub(crate) fn push_deny(&mut self, cidr: Cidr) {
+        self.deny.push(cidr);
+    }
+
+    /// 访问者是否允许访问, 无法获取ip的访问者总是允许
+    pub(crate) fn permits(&self, visitor: &Address) -> Result<(), String> {
+        let ip = match visitor.first_addr().and_then(|addr| addr.ip()) {
+            Some(ip) => ip,
+            None => return Ok(()),
+        };
+
+        if let Some(deny) = self.deny.iter().find(|deny| deny.contains(&ip)) {
+            return Err(format!("visitor {} is denied by {}", ip, deny));
+        }
+
+        if !self.allow.is_empty() && !self.allow.iter().any(|allow| allow.contains(&ip)) {
+            return Err(format!("visitor {} is not in the allow list", ip));
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Windows {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+            swept: Instant::now(),
+        }
+    }
+}
+
+impl Throttle {
+    /// 清理过期记录的间隔
+    const SWEEP_INTERVAL: Duration = Duration::from_secs(10);
+
+    /// 每秒最多rate个新连接, 0表示不限制
+    pub(crate) fn permits(&self, visitor: &Address, rate: u32) -> Result<(), String> {
+        let ip = match visitor.first_addr().and_then(|addr| addr.ip()) {
+            Some(ip) if rate != 0 => ip,
+            _ => return Ok(()),
+        };
+
+        let now = Instant::now();
+        let second = Duration::from_secs(1);
+        let mut windows = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+
+        if now.duration_since(windows.swept) >= Self::SWEEP_INTERVAL {
+            windows.swept = now;
+            windows
+                .entries
+                .retain(|_, (start, _)| now.duration_since(*start) < second);
+        }
+
+        let (start, count) = windows.entries.entry(ip).or_insert((now, 0));
+
+        if now.duration_since(*start) >= second {
+            *start = now;
+            *count = 0;
+        }
+
+        *count += 1;
+
+        if *count > rate {
+            Err(format!(
+                "visitor {} exceeds {} connections per second",
+                ip, rate
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::IpAddr,
+        time::{Duration, Instant},
+    };
+
+    use super::{AccessList, Cidr, Throttle};
+    use crate::{Address, Socket};
+
+    fn visitor(ip: &str) -> Address {
+        Address::One(Socket::tcp((ip.parse::<IpAddr>().unwrap(), 1024)))
+    }
+
+    #[test]
+    fn test_access_list() {
+        let cidr = "192.168.0.0/16".parse::<Cidr>().unwrap();
+        assert!(cidr.contains(&"192.168.8.1".parse().unwrap()));
+        assert!(cidr.contains(&"::ffff:192.168.8.1".parse().unwrap()));
+        assert!(!cidr.contains(&"10.0.0.1".parse().unwrap()));
+        assert!("0.0.0.0/0"
+            .parse::<Cidr>()
+            .unwrap()
+            .contains(&"8.8.8.8".parse().unwrap()));
+        assert!("fd00::/8"
+            .parse::<Cidr>()
+            .unwrap()
+            .contains(&"fd12::1".parse().unwrap()));
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+
+        let access = AccessList::default()
+            .allow(vec!["10.0.0.0/8".parse().unwrap()])
+            .deny(vec!["10.0.0.1".parse().unwrap()]);
+
+        assert!(access.permits(&visitor("10.1.2.3")).is_ok());
+        assert!(access.permits(&visitor("10.0.0.1")).is_err());
+        assert!(access.permits(&visitor("192.168.1.1")).is_err());
+        assert!(AccessList::default()
+            .permits(&visitor("192.168.1.1"))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_throttle() {
+        let throttle = Throttle::default();
+        assert!(throttle.permits(&visitor("10.0.0.1"), 2).is_ok());
+        assert!(throttle.permits(&visitor("10.0.0.1"), 2).is_ok());
+        assert!(throttle.permits(&visitor("10.0.0.1"), 2).is_err());
+        assert!(throttle.permits(&visitor("10.0.0.2"), 2).is_ok());
+        assert!(throttle.permits(&visitor("10.0.0.1"), 0).is_ok());
+
+        // 超过清理间隔后移除已经过期的记录
+        {
+            let mut windows = throttle.0.lock().unwrap();
+            let past = Instant::now() - Throttle::SWEEP_INTERVAL;
+            windows.swept = past;
+            windows
+                .entries
+                .get_mut(&"10.0.0.1".parse().unwrap())
+                .unwrap()
+                .0 = past;
+        }
+
+        assert!(throttle.permits(&visitor("10.0.0.3"), 2).is_ok());
+
+        let windows = throttle.0.lock().unwrap();
+        assert_eq!(windows.entries.len(), 2);
+        assert!(!windows.entries.contains_key(&"10.0.0.1".parse().unwrap()));
+        assert!(windows.swept.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,136 @@
+#[cfg(feature = "fuso-crypt-hmac")]
+use hmac::{Hmac, Mac};
+#[cfg(feature = "fuso-crypt-hmac")]
+use sha2::Sha256;
+
+use crate::{
+    error::Kind,
+    protocol::{AsyncRecvPacket, AsyncSendPacket, Poto, ToBytes, Token, TryToPoto},
+    Stream,
+};
+
+#[cfg(feature = "fuso-crypt-hmac")]
+type HmacSha256 = Hmac<Sha256>;
+
+/// 随机数长度
+const NONCE_SIZE: usize = 32;
+
+#[cfg(feature = "fuso-crypt-hmac")]
+fn sign(secret: &str, nonce: &[u8]) -> Vec<u8> {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("hmac accepts any key length");
+    mac.update(nonce);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// 返回匹配的密钥
+#[cfg(feature = "fuso-crypt-hmac")]
+fn verify<'a>(secrets: &'a [String], nonce: &[u8], answer: &[u8]) -> Option<&'a String> {
+    secrets.iter().find(|secret| {
+        let mut mac =
+            HmacSha256::new_from_slice(secret.as_bytes()).expect("hmac accepts any key length");
+        mac.update(nonce);
+        mac.verify_slice(answer).is_ok()
+    })
+}
+
+#[cfg(not(feature = "fuso-crypt-hmac"))]
+fn sign(_: &str, _: &[u8]) -> Vec<u8> {
+    Vec::new()
+}
+
+/// 未开启fuso-crypt-hmac时无法校验, 配置了密钥的服务端拒绝所有客户端
+#[cfg(not(feature = "fuso-crypt-hmac"))]
+fn verify<'a>(_: &'a [String], _: &[u8], _: &[u8]) -> Option<&'a String> {
+    None
+}
+
+/// 服务端验证客户端, 失败时会通知客户端, 成功时返回客户端使用的密钥
+/// 未配置密钥时直接通过
+pub(super) async fn server_authenticate<S>(
+    client: &mut S,
+    secrets: &[String],
+) -> crate::Result<Option<String>>
+where
+    S: Stream + Unpin,
+{
+    if secrets.is_empty() {
+        return client
+            .send_packet(&Poto::Token(Token::Success).bytes())
+            .await
+            .map(|_| None);
+    }
+
+    let mut nonce = [0u8; NONCE_SIZE];
+    nonce.fill_with(rand::random);
+
+    client
+        .send_packet(&Poto::Token(Token::Challenge(nonce.to_vec())).bytes())
+        .await?;
+
+    let reason = match client.recv_packet().await?.try_poto()? {
+        Poto::Token(Token::Answer(answer)) => match verify(secrets, &nonce, &answer) {
+            Some(secret) => {
+                let secret = secret.clone();
+                return client
+                    .send_packet(&Poto::Token(Token::Success).bytes())
+                    .await
+                    .map(|_| Some(secret));
+            }
+            None => String::from("invalid secret"),
+        },
+        message => format!("unexpected message {}", message),
+    };
+
+    let message = Poto::Token(Token::Failed(reason.clone())).bytes();
+
+    if let Err(e) = client.send_packet(&message).await {
+        log::warn!("failed to send failure message to client err={}", e);
+    }
+
+    Err(Kind::Unauthorized(reason).into())
+}
+
+/// 客户端使用密钥应答服务端的验证
+pub(super) async fn client_authenticate<S>(stream: &mut S, secret: &str) -> crate::Result<()>
+where
+    S: Stream + Unpin,
+{
+    stream
+        .send_packet(&Poto::Token(Token::Request).bytes())
+        .await?;
+
+    loop {
+        match stream.recv_packet().await?.try_poto()? {
+            Poto::Token(Token::Challenge(nonce)) => {
+                let answer = Poto::Token(Token::Answer(sign(secret, &nonce))).bytes();
+                stream.send_packet(&answer).await?;
+            }
+            Poto::Token(Token::Success) => break Ok(()),
+            Poto::Token(Token::Failed(reason)) => break Err(Kind::Unauthorized(reason).into()),
+            message => {
+                break Err(Kind::Unexpected(format!("{}", message)).into());
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "fuso-crypt-hmac"))]
+mod tests {
+
+    use super::{sign, verify};
+
+    #[test]
+    fn test_verify_secret() {
+        let secrets = vec![String::from("foo"), String::from("bar")];
+        let nonce = [7u8; super::NONCE_SIZE];
+
+        assert_eq!(
+            verify(&secrets, &nonce, &sign("bar", &nonce)),
+            Some(&secrets[1])
+        );
+        assert!(verify(&secrets, &nonce, &sign("baz", &nonce)).is_none());
+        assert!(verify(&secrets, &[0u8; 4], &sign("foo", &nonce)).is_none());
+        assert!(verify(&[], &nonce, &sign("foo", &nonce)).is_none());
+    }
+}
@@ -0,0 +1,384 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    fmt::Display,
+    hash::{Hash, Hasher},
+    net::IpAddr,
+    pin::Pin,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use crate::{
+    client::Route,
+    guard::{Slot, Slots},
+    join::Join,
+    time, Kind, Provider, Socket,
+};
+
+type BoxedFuture<T> = Pin<Box<dyn std::future::Future<Output = crate::Result<T>> + Send + 'static>>;
+
+/// 多个转发目标之间的负载均衡方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Balance {
+    /// 依次轮流
+    #[default]
+    RoundRobin,
+    /// 正在转发的连接最少的目标
+    LeastConn,
+    /// 按访问者ip选择, 同一个访问者总是转发到同一个目标
+    IpHash,
+}
+
+/// 映射的转发目标, 克隆后共享健康状态与连接数
+#[derive(Clone)]
+pub struct Upstream(Arc<UpstreamInner>);
+
+struct UpstreamInner {
+    balance: Balance,
+    targets: Vec<Target>,
+    next: AtomicUsize,
+    /// 是否参与健康检查, 不检查时也不会因为目标不可用而暂停映射
+    checked: bool,
+}
+
+struct Target {
+    socket: Socket,
+    healthy: AtomicBool,
+    active: Slots,
+}
+
+/// 所有映射的转发目标, 下标为映射id, 0为默认映射
+#[derive(Clone, Default)]
+pub struct Upstreams {
+    upstreams: Arc<Vec<Upstream>>,
+    /// 健康检查的间隔, 0表示不检查
+    interval: Duration,
+}
+
+impl FromStr for Balance {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "round-robin" => Ok(Self::RoundRobin),
+            "least-conn" => Ok(Self::LeastConn),
+            "ip-hash" => Ok(Self::IpHash),
+            _ => Err(Kind::Message(format!("invalid balance {}", s)).into()),
+        }
+    }
+}
+
+impl Display for Balance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::RoundRobin => write!(f, "round-robin"),
+            Self::LeastConn => write!(f, "least-conn"),
+            Self::IpHash => write!(f, "ip-hash"),
+        }
+    }
+}
+
+impl Upstream {
+    /// 多个目标时默认参与健康检查
+    pub fn new(targets: Vec<Socket>, balance: Balance) -> Self {
+        let checked = targets.len() > 1;
+        Self::with_check(targets, balance, checked)
+    }
+
+    pub fn with_check(targets: Vec<Socket>, balance: Balance, checked: bool) -> Self {
+        Self(Arc::new(UpstreamInner {
+            balance,
+            targets: targets
+                .into_iter()
+                .map(|socket| Target {
+                    socket,
+                    healthy: AtomicBool::new(true),
+                    active: Slots::default(),
+                })
+                .collect(),
+            next: AtomicUsize::new(0),
+            checked,
+        }))
+    }
+
+    /// 是否有多个目标
+    pub fn is_balanced(&self) -> bool {
+        self.0.targets.len() > 1
+    }
+
+    /// 是否参与健康检查
+    pub fn is_checked(&self) -> bool {
+        self.0.checked
+    }
+
+    /// 是否有健康的目标, 没有时映射需要暂停, 不参与健康检查的映射总是可用
+    pub fn is_available(&self) -> bool {
+        !self.0.checked
+            || self
+                .0
+                .targets
+                .iter()
+                .any(|target| target.healthy.load(Ordering::Relaxed))
+    }
+
+    /// 尝试的顺序, 健康的目标按负载均衡排序, 不健康的目标排在最后
+    fn candidates(&self, visitor: Option<IpAddr>) -> Vec<usize> {
+        let targets = &self.0.targets;
+
+        let (mut healthy, unhealthy): (Vec<usize>, Vec<usize>) =
+            (0..targets.len()).partition(|index| targets[*index].healthy.load(Ordering::Relaxed));
+
+        let len = healthy.len();
+
+        if len > 0 {
+            match (self.0.balance, visitor) {
+                (Balance::LeastConn, _) => {
+                    healthy.sort_by_key(|index| targets[*index].active.active())
+                }
+                (Balance::IpHash, Some(ip)) => {
+                    let mut hasher = DefaultHasher::new();
+                    ip.hash(&mut hasher);
+                    healthy.rotate_left(hasher.finish() as usize % len);
+                }
+                _ => {
+                    let next = self.0.next.fetch_add(1, Ordering::Relaxed);
+                    healthy.rotate_left(next % len);
+                }
+            }
+        }
+
+        healthy.extend(unhealthy);
+        healthy
+    }
+
+    fn mark(&self, index: usize, healthy: bool) {
+        let target = &self.0.targets[index];
+
+        if target.healthy.swap(healthy, Ordering::Relaxed) != healthy {
+            if healthy {
+                log::info!("target {} is back in rotation", target.socket);
+            } else {
+                log::warn!("target {} is out of rotation", target.socket);
+            }
+        }
+    }
+
+    /// 按负载均衡依次尝试转发目标, 所有目标都失败时返回最后一个错误,
+    /// 每个目标平分timeout, 服务端指定了目标(例如socks5)时直接连接
+    pub(crate) async fn connect<C, S>(
+        self,
+        connector: Arc<C>,
+        target: Socket,
+        timeout: Duration,
+    ) -> crate::Result<(Route<S>, Socket, Option<Slot>)>
+    where
+        C: Provider<Socket, Output = BoxedFuture<Route<S>>> + Send + Sync + 'static,
+        S: Send + 'static,
+    {
+        if !target.is_default() {
+            log::debug!("try connect to {}", target);
+            let route = time::wait_for(timeout, connector.call(target.clone())).await??;
+            return Ok((route, target, None));
+        }
+
+        let visitor = target.origin().and_then(|origin| origin.source().ip());
+        let candidates = self.candidates(visitor);
+        let timeout = timeout / candidates.len().max(1) as u32;
+
+        let mut error = None;
+
+        for index in candidates {
+            let socket = target
+                .clone()
+                .default_or(self.0.targets[index].socket.clone());
+
+            log::debug!("try connect to {}", socket);
+
+            match time::wait_for(timeout, connector.call(socket.clone()))
+                .await
+                .and_then(|route| route)
+            {
+                Ok(route) => {
+                    self.mark(index, true);
+                    let slot = self.0.targets[index].active.acquire(0);
+                    return Ok((route, socket, slot));
+                }
+                Err(e) => {
+                    log::warn!("failed to connect to {} err: {}", socket, e);
+                    self.mark(index, false);
+                    error = Some(e);
+                }
+            }
+        }
+
+        Err(error.unwrap_or_else(|| Kind::Message("no target available".into()).into()))
+    }
+
+    /// 同时尝试连接每一个目标, 失败的目标不再参与负载均衡, 直到再次连接成功
+    fn check<C, S>(&self, connector: &Arc<C>, timeout: Duration) -> Join
+    where
+        C: Provider<Socket, Output = BoxedFuture<Route<S>>> + Send + Sync + 'static,
+        S: Send + 'static,
+    {
+        Join::all((0..self.0.targets.len()).map(|index| {
+            let upstream = self.clone();
+            let connector = connector.clone();
+            async move {
+                let socket = upstream.0.targets[index].socket.clone();
+                let healthy = matches!(
+                    time::wait_for(timeout, connector.call(socket)).await,
+                    Ok(Ok(_))
+                );
+
+                upstream.mark(index, healthy);
+            }
+        }))
+    }
+}
+
+impl Upstreams {
+    pub fn new(upstreams: Vec<Upstream>, interval: Duration) -> Self {
+        Self {
+            upstreams: Arc::new(upstreams),
+            interval,
+        }
+    }
+
+    /// 映射的转发目标, 没有配置时只使用target
+    pub(crate) fn get(&self, id: u32, target: Socket) -> Upstream {
+        self.upstreams
+            .get(id as usize)
+            .cloned()
+            .unwrap_or_else(|| Upstream::new(vec![target], Balance::default()))
+    }
+
+    pub(crate) fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.upstreams.len()
+    }
+
+    /// 是否需要健康检查
+    pub(crate) fn needs_check(&self) -> bool {
+        !self.interval.is_zero() && self.upstreams.iter().any(Upstream::is_checked)
+    }
+
+    /// 每个映射是否有健康的目标
+    pub(crate) fn availability(&self) -> Vec<bool> {
+        self.upstreams.iter().map(Upstream::is_available).collect()
+    }
+
+    /// 同时检查所有参与健康检查的映射的转发目标, 最多等待timeout
+    pub(crate) async fn check<C, S>(&self, connector: &Arc<C>, timeout: Duration)
+    where
+        C: Provider<Socket, Output = BoxedFuture<Route<S>>> + Send + Sync + 'static,
+        S: Send + 'static,
+    {
+        Join::all(
+            self.upstreams
+                .iter()
+                .filter(|upstream| upstream.is_checked())
+                .map(|upstream| upstream.check(connector, timeout)),
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::IpAddr, sync::Arc, time::Duration};
+
+    use super::{Balance, BoxedFuture, Upstream};
+    use crate::{client::Route, Provider, Socket};
+
+    /// 8001端口一直连接不上, 其他端口立即成功
+    struct Stalled;
+
+    impl Provider<Socket> for Stalled {
+        type Output = BoxedFuture<Route<()>>;
+
+        fn call(&self, socket: Socket) -> Self::Output {
+            Box::pin(async move {
+                if socket.addr().port() == 8001 {
+                    std::future::pending::<()>().await;
+                }
+                Ok(Route::Forward(()))
+            })
+        }
+    }
+
+    #[test]
+    fn test_balance() {
+        let targets = vec![Socket::tcp(8001), Socket::tcp(8002), Socket::tcp(8003)];
+
+        let upstream = Upstream::new(targets.clone(), Balance::RoundRobin);
+        assert_eq!(upstream.candidates(None), vec![0, 1, 2]);
+        assert_eq!(upstream.candidates(None), vec![1, 2, 0]);
+
+        upstream.mark(2, false);
+        assert_eq!(upstream.candidates(None), vec![0, 1, 2]);
+        assert_eq!(upstream.candidates(None), vec![1, 0, 2]);
+
+        upstream.mark(0, false);
+        upstream.mark(1, false);
+        assert!(!upstream.is_available());
+        upstream.mark(1, true);
+        assert!(upstream.is_available());
+
+        let upstream = Upstream::new(vec![Socket::tcp(8001)], Balance::RoundRobin);
+        upstream.mark(0, false);
+        assert!(!upstream.is_checked());
+        assert!(upstream.is_available());
+
+        let upstream = Upstream::new(targets.clone(), Balance::LeastConn);
+        let _slot = upstream.0.targets[0].active.acquire(0);
+        assert_eq!(upstream.candidates(None)[0], 1);
+
+        let upstream = Upstream::new(targets, Balance::IpHash);
+        let ip = "10.0.0.1".parse::<IpAddr>().ok();
+        assert_eq!(upstream.candidates(ip), upstream.candidates(ip));
+
+        assert_eq!("least-conn".parse::<Balance>().unwrap(), Balance::LeastConn);
+        assert!("random".parse::<Balance>().is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "fuso-rt-tokio")]
+    fn test_connect_timeout() {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let targets = vec![Socket::tcp(8001), Socket::tcp(8002)];
+                let upstream = Upstream::new(targets, Balance::RoundRobin);
+                let connector = Arc::new(Stalled);
+
+                // 第一个目标超时后仍然有时间尝试第二个目标
+                let (_, socket, _) = upstream
+                    .clone()
+                    .connect(
+                        connector.clone(),
+                        Socket::default(),
+                        Duration::from_millis(200),
+                    )
+                    .await
+                    .unwrap();
+                assert_eq!(socket.addr().port(), 8002);
+                assert_eq!(upstream.candidates(None), vec![1, 0]);
+
+                upstream.mark(0, true);
+                tokio::time::timeout(
+                    Duration::from_millis(500),
+                    upstream.check(&connector, Duration::from_millis(100)),
+                )
+                .await
+                .unwrap();
+                assert_eq!(upstream.candidates(None), vec![1, 0]);
+            });
+    }
+}
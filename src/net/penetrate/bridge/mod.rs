@@ -72,7 +72,7 @@ where
         let poto = client.recv_packet().await?.try_poto()?;
 
         let (upstream, client_addr, proxy_accepter, upstream_decorate) = match poto {
-            Poto::Bind(Bind::Setup(client_addr, visitor_addr)) => {
+            Poto::Bind(Bind::Setup(client_addr, visitor_addr, name)) => {
                 let upstream_addr = upstream;
                 let upstream = client_provider.call(upstream_addr.clone()).await?;
 
@@ -81,8 +81,8 @@ where
                     None => (upstream, None),
                 };
 
-                let poto =
-                    Poto::Bind(Bind::Setup(client_addr.clone(), visitor_addr.clone())).bytes();
+                let poto = Poto::Bind(Bind::Setup(client_addr.clone(), visitor_addr.clone(), name))
+                    .bytes();
 
                 upstream.send_packet(&poto).await?;
 
@@ -128,7 +128,7 @@ where
             }
         };
 
-        let f1 = io::forward(client, upstream);
+        let f1 = async move { io::forward(client, upstream).await.map(|_| ()) };
 
         let f2 = async move {
             let mut proxy_accepter = proxy_accepter;
@@ -165,7 +165,7 @@ where
                         upstream.peer_addr()?
                     );
 
-                    io::forward(stream, upstream).await
+                    io::forward(stream, upstream).await.map(|_| ())
                 };
 
                 executor.spawn(async move {
@@ -189,16 +189,18 @@ where
         let bridge_socket = self.socket;
         let accepter_provider = Arc::new(self.accepter_provider);
         let executor = self.client.executor.clone();
-        let server_socket = self.client.socket.clone();
+        let endpoints = self.client.endpoints.clone();
         let handshake = self.client.handshake.clone();
         let client_provider = self.client.client_provider.clone();
         let bridge_handshake = self.bridge_handshake.clone();
 
-        if bridge_socket.eq(&server_socket)
-            || (bridge_socket.is_ip_unspecified()
-                && server_socket.ip().eq(&Some(IpAddr::from([127, 0, 0, 1]))))
-        {
-            return Err(Kind::AddressLoop(bridge_socket).into());
+        for server_socket in endpoints.sockets() {
+            if bridge_socket.eq(&server_socket)
+                || (bridge_socket.is_ip_unspecified()
+                    && server_socket.ip().eq(&Some(IpAddr::from([127, 0, 0, 1]))))
+            {
+                return Err(Kind::AddressLoop(bridge_socket).into());
+            }
         }
 
         let bridge = async move {
@@ -214,7 +216,7 @@ where
                     accepter_provider.clone(),
                     handshake,
                     client_provider.clone(),
-                    server_socket.clone(),
+                    endpoints.active(),
                     executor.clone(),
                     bridge_handshake.clone(),
                 ));
@@ -227,8 +229,11 @@ where
     }
 
     pub fn run(self) -> Fuso<Serve> {
+        let shutdown = self.client.shutdown.clone();
+
         Fuso(Serve {
             fut: Box::pin(self.run_async()),
+            shutdown,
         })
     }
 }
@@ -1,27 +1,29 @@
 use std::{pin::Pin, sync::Arc, time::Duration};
 
 use crate::{
-    client::{Client, ClientBuilder, Route},
-    guard::Fallback,
+    client::{Client, ClientBuilder, Failover, ReconnectPolicy, Route},
+    guard::{Bandwidth, Fallback, Limits},
     server::{Server, ServerBuilder},
     Accepter, Executor, Fuso, Platform, Provider, Socket, Stream, WrappedProvider,
 };
 
 use super::{
-    client::PenetrateClientProvider,
+    client::{Mapping, PenetrateClientProvider},
     server::{Config, Peer, PenetrateProvider},
-    PenetrateObserver,
+    AccessList, Balance, PenetrateObserver, ProxyProtocol, Reloader, Settings, VirtualHosts,
 };
 
 type BoxedFuture<T> = Pin<Box<dyn std::future::Future<Output = crate::Result<T>> + Send + 'static>>;
 
 pub struct PenetrateServerBuilder<E, P, S, O> {
     is_mixed: bool,
-    max_wait_time: Duration,
-    heartbeat_timeout: Duration,
-    read_timeout: Option<Duration>,
-    write_timeout: Option<Duration>,
-    fallback_strict_mode: bool,
+    /// 可以热更新的配置
+    settings: Settings,
+    reloader: Option<Reloader>,
+    /// 带宽限制
+    limits: Limits,
+    /// 通过域名共享http端口
+    pub(crate) vhosts: Option<VirtualHosts<S>>,
     server_builder: ServerBuilder<E, P, S, O>,
 }
 
@@ -32,13 +34,24 @@ pub struct PenetrateClientBuilder<E, CF, S> {
     upstream: Socket,
     /// 下游地址， 也就是本地需要映射的地址
     downstream: Socket,
+    /// 其他的下游地址, 与downstream一起负载均衡
+    replicas: Vec<Socket>,
+    balance: Balance,
+    /// 下游地址健康检查的间隔, 0表示不检查, 不指定时只检查有多个下游地址的映射, 间隔10秒
+    health_check: Option<Duration>,
+    /// 下游不可用时访问者收到的http响应内容
+    suspend_page: Option<String>,
     channel_port: u16,
     /// 创建连接等待时间, 超过视为超时
     maximum_wait: Option<Duration>,
-    /// 重连延时
-    reconnect_delay: Option<Duration>,
-    /// 重连尝试次数，如果为None那么永不停止
-    maximum_retries: Option<usize>,
+    /// 半关闭后另一个方向允许空闲的时间
+    linger: Option<Duration>,
+    /// 重连策略, 包括重连延时以及重连尝试次数
+    reconnect: ReconnectPolicy,
+    /// 备用的服务端地址
+    backup_servers: Vec<Socket>,
+    /// 服务端地址的切换方式
+    failover: Failover,
     /// 心跳延时
     heartbeat_delay: Option<Duration>,
     /// 是否启用 kcp
@@ -51,6 +64,24 @@ pub struct PenetrateClientBuilder<E, CF, S> {
     socks_password: Option<String>,
     /// 是否启用socks5 udp转发
     enable_socks5_udp: bool,
+    /// 是否启用多路复用
+    enable_mux: bool,
+    /// 通过域名访问, 服务端需要开启共享http端口
+    domain: Option<String>,
+    /// 向服务传递访问者的真实ip
+    proxy_protocol: Option<ProxyProtocol>,
+    /// 额外的端口映射
+    mappings: Vec<Mapping>,
+    /// 访问者的黑白名单
+    access: AccessList,
+    /// 客户端所有映射共享的带宽限制
+    bandwidth: Bandwidth,
+    /// 默认映射同时访问的数量
+    maximum_visitors: usize,
+    /// 默认映射的空闲超时
+    idle_timeout: Option<Duration>,
+    /// 服务端验证密钥
+    secret: Option<String>,
     /// builder ...
     client_builder: ClientBuilder<E, CF, S>,
 }
@@ -59,11 +90,10 @@ impl<E, P, S, O> ServerBuilder<E, P, S, O> {
     pub fn using_penetrate(self) -> PenetrateServerBuilder<E, P, S, O> {
         PenetrateServerBuilder {
             is_mixed: self.is_mixed,
-            write_timeout: None,
-            read_timeout: None,
-            max_wait_time: Duration::from_secs(10),
-            heartbeat_timeout: Duration::from_secs(60),
-            fallback_strict_mode: true,
+            settings: Settings::default(),
+            reloader: None,
+            limits: Limits::default(),
+            vhosts: None,
             server_builder: self,
         }
     }
@@ -77,33 +107,46 @@ where
     P: Provider<Socket, Output = BoxedFuture<A>> + Send + Sync + 'static,
     O: PenetrateObserver + Send + Sync + 'static,
 {
-    pub fn read_timeout(mut self, time: Option<Duration>) -> Self {
-        self.read_timeout = time;
+    pub fn max_wait_time(mut self, time: Duration) -> Self {
+        self.settings = self.settings.max_wait_time(time);
         self
     }
 
-    pub fn write_timeout(mut self, time: Option<Duration>) -> Self {
-        self.write_timeout = time;
+    pub fn heartbeat_timeout(mut self, time: Duration) -> Self {
+        self.settings = self.settings.heartbeat_timeout(time);
         self
     }
 
-    pub fn max_wait_time(mut self, time: Duration) -> Self {
-        self.max_wait_time = time.min(Duration::from_secs(10));
+    pub fn enable_fallback_strict_mode(mut self) -> Self {
+        self.settings = self.settings.fallback_strict_mode(true);
         self
     }
 
-    pub fn heartbeat_timeout(mut self, time: Duration) -> Self {
-        self.heartbeat_timeout = time.min(Duration::from_secs(60));
+    pub fn disable_fallback_strict_mode(mut self) -> Self {
+        self.settings = self.settings.fallback_strict_mode(false);
         self
     }
 
-    pub fn enable_fallback_strict_mode(mut self) -> Self {
-        self.fallback_strict_mode = true;
+    pub fn add_secret<T: Into<String>>(mut self, secret: T) -> Self {
+        Arc::make_mut(&mut self.settings.secrets).push(secret.into());
         self
     }
 
-    pub fn disable_fallback_strict_mode(mut self) -> Self {
-        self.fallback_strict_mode = false;
+    pub fn set_secrets(mut self, secrets: Vec<String>) -> Self {
+        self.settings = self.settings.set_secrets(secrets);
+        self
+    }
+
+    /// 使用reloader中的配置, 运行时可以通过reloader替换
+    pub fn using_reloader(mut self, reloader: Reloader) -> Self {
+        self.settings = Settings::clone(&reloader.current());
+        self.reloader = Some(reloader);
+        self
+    }
+
+    /// 使用limits限制转发的带宽, 运行时可以通过limits修改
+    pub fn using_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
         self
     }
 
@@ -116,23 +159,38 @@ where
             + Sync
             + 'static,
     {
+        let reloader = self.reloader.unwrap_or_default();
+
+        let config = Config {
+            whoami: String::from("anonymous"),
+            is_mixed: self.is_mixed,
+            maximum_wait: self.settings.max_wait_time,
+            heartbeat_delay: self.settings.heartbeat_timeout,
+            fallback_strict_mode: self.settings.fallback_strict_mode,
+            enable_socks: false,
+            enable_socks_udp: false,
+            real_ip: false,
+            enable_mux: false,
+            socks5_password: None,
+            socks5_username: None,
+            platform: Default::default(),
+            domain: None,
+            access: Default::default(),
+            maximum_visitors: 0,
+            idle_timeout: None,
+        };
+
+        reloader.reload(self.settings);
+
         self.server_builder.build(PenetrateProvider {
             mock: Arc::new(WrappedProvider::wrap(mock)),
-            config: Config {
-                whoami: String::from("anonymous"),
-                is_mixed: self.is_mixed,
-                maximum_wait: self.max_wait_time,
-                heartbeat_delay: self.heartbeat_timeout,
-                read_timeout: self.read_timeout,
-                write_timeout: self.write_timeout,
-                fallback_strict_mode: self.fallback_strict_mode,
-                enable_socks: false,
-                enable_socks_udp: false,
-                real_ip: false,
-                socks5_password: None,
-                socks5_username: None,
-                platform: Default::default(),
-            },
+            settings: reloader,
+            assignments: Default::default(),
+            limits: self.limits,
+            clients: Default::default(),
+            throttle: Default::default(),
+            vhosts: self.vhosts,
+            config,
         })
     }
 }
@@ -147,17 +205,32 @@ impl<E, CF, S> ClientBuilder<E, CF, S> {
             name: String::from("anonymous"),
             upstream: upstream.into(),
             downstream: downstream.into(),
+            replicas: Vec::new(),
+            balance: Balance::default(),
+            health_check: None,
+            suspend_page: None,
             channel_port: 0,
             client_builder: self,
             maximum_wait: None,
-            maximum_retries: None,
-            reconnect_delay: None,
+            linger: None,
+            reconnect: Default::default(),
+            backup_servers: Vec::new(),
+            failover: Failover::Priority,
             heartbeat_delay: None,
             enable_kcp: false,
             enable_socks5: false,
             socks_username: None,
             socks_password: None,
             enable_socks5_udp: false,
+            enable_mux: false,
+            domain: None,
+            proxy_protocol: None,
+            mappings: Vec::new(),
+            access: AccessList::default(),
+            bandwidth: Bandwidth::default(),
+            maximum_visitors: 0,
+            idle_timeout: None,
+            secret: None,
         }
     }
 }
@@ -168,8 +241,24 @@ where
     CF: Provider<Socket, Output = BoxedFuture<S>> + Send + Sync + 'static,
     S: Stream + Send + 'static,
 {
+    /// 第一次重连的延时, 之后按重连策略递增
     pub fn reconnect_delay(mut self, delay: Duration) -> Self {
-        self.reconnect_delay = Some(delay.min(Duration::from_secs(2)));
+        self.reconnect = self.reconnect.initial_delay(delay);
+        self
+    }
+
+    pub fn reconnect_policy(mut self, reconnect: ReconnectPolicy) -> Self {
+        self.reconnect = reconnect;
+        self
+    }
+
+    pub fn backup_servers(mut self, servers: Vec<Socket>) -> Self {
+        self.backup_servers = servers;
+        self
+    }
+
+    pub fn failover(mut self, strategy: Failover) -> Self {
+        self.failover = strategy;
         self
     }
 
@@ -193,6 +282,72 @@ where
         self
     }
 
+    /// 多路复用的数据经过控制通道, 控制通道使用rsa加密时吞吐量很低, 应该与x25519握手一起使用
+    pub fn enable_mux(mut self, enable: bool) -> Self {
+        self.enable_mux = enable;
+        self
+    }
+
+    pub fn set_domain(mut self, domain: Option<String>) -> Self {
+        self.domain = domain;
+        self
+    }
+
+    pub fn set_proxy_protocol(mut self, proxy_protocol: Option<ProxyProtocol>) -> Self {
+        self.proxy_protocol = proxy_protocol;
+        self
+    }
+
+    pub fn set_replicas(mut self, replicas: Vec<Socket>) -> Self {
+        self.replicas = replicas;
+        self
+    }
+
+    pub fn set_balance(mut self, balance: Balance) -> Self {
+        self.balance = balance;
+        self
+    }
+
+    pub fn health_check(mut self, interval: Option<Duration>) -> Self {
+        self.health_check = interval;
+        self
+    }
+
+    pub fn set_suspend_page(mut self, page: Option<String>) -> Self {
+        self.suspend_page = page;
+        self
+    }
+
+    pub fn set_mappings(mut self, mappings: Vec<Mapping>) -> Self {
+        self.mappings = mappings;
+        self
+    }
+
+    pub fn set_access_list(mut self, access: AccessList) -> Self {
+        self.access = access;
+        self
+    }
+
+    pub fn set_bandwidth(mut self, bandwidth: Bandwidth) -> Self {
+        self.bandwidth = bandwidth;
+        self
+    }
+
+    pub fn maximum_visitors(mut self, maximum: usize) -> Self {
+        self.maximum_visitors = maximum;
+        self
+    }
+
+    pub fn idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    pub fn set_secret(mut self, secret: Option<String>) -> Self {
+        self.secret = secret;
+        self
+    }
+
     pub fn set_socks5_username(mut self, username: Option<String>) -> Self {
         self.socks_username = username;
         self
@@ -204,7 +359,7 @@ where
     }
 
     pub fn maximum_retries(mut self, maximum_retries: Option<usize>) -> Self {
-        self.maximum_retries = maximum_retries;
+        self.reconnect = self.reconnect.maximum_retries(maximum_retries);
         self
     }
 
@@ -218,6 +373,11 @@ where
         self
     }
 
+    pub fn linger(mut self, linger: Duration) -> Self {
+        self.linger = Some(linger);
+        self
+    }
+
     pub fn channel_port(mut self, port: u16) -> Self {
         self.channel_port = port;
         self
@@ -231,10 +391,41 @@ where
     where
         C: Provider<Socket, Output = BoxedFuture<Route<S>>> + Unpin + Send + Sync + 'static,
     {
+        let config = super::client::Config {
+            name: self.name,
+            channel_port: self.channel_port,
+            maximum_wait: self.maximum_wait.unwrap_or(Duration::from_secs(10)),
+            linger: self.linger.unwrap_or(Duration::from_secs(30)),
+            heartbeat_delay: self.heartbeat_delay.unwrap_or(Duration::from_secs(30)),
+            enable_kcp: self.enable_kcp,
+            enable_socks5: self.enable_socks5,
+            socks_username: self.socks_username,
+            socks_password: self.socks_password,
+            enable_socks5_udp: self.enable_socks5_udp,
+            version: String::from(env!("CARGO_PKG_VERSION")),
+            platform: Platform::default(),
+            enable_mux: self.enable_mux,
+            domain: self.domain,
+            proxy_protocol: self.proxy_protocol,
+            mappings: self.mappings,
+            access: self.access,
+            maximum_visitors: self.maximum_visitors,
+            idle_timeout: self.idle_timeout,
+            suspend_page: self.suspend_page,
+        };
+
+        let upstreams = config.upstreams(
+            &self.downstream,
+            self.replicas,
+            self.balance,
+            self.health_check,
+        );
+
         ClientBuilder {
             executor: self.client_builder.executor,
-            retry_delay: self.reconnect_delay,
-            maximum_retries: self.maximum_retries,
+            reconnect: self.reconnect,
+            backup_servers: self.backup_servers,
+            failover: self.failover,
             handshake: self.client_builder.handshake,
             client_provider: self.client_builder.client_provider,
         }
@@ -243,19 +434,10 @@ where
             PenetrateClientProvider {
                 forward: (self.upstream, self.downstream),
                 connector_provider: Arc::new(connector),
-                config: super::client::Config {
-                    name: self.name,
-                    channel_port: self.channel_port,
-                    maximum_wait: self.maximum_wait.unwrap_or(Duration::from_secs(10)),
-                    heartbeat_delay: self.heartbeat_delay.unwrap_or(Duration::from_secs(30)),
-                    enable_kcp: self.enable_kcp,
-                    enable_socks5: self.enable_socks5,
-                    socks_username: self.socks_username,
-                    socks_password: self.socks_password,
-                    enable_socks5_udp: self.enable_socks5_udp,
-                    version: String::from(env!("CARGO_PKG_VERSION")),
-                    platform: Platform::default(),
-                },
+                secret: self.secret,
+                limits: config.limits(self.bandwidth),
+                upstreams,
+                config,
             },
         )
     }
@@ -1,30 +1,36 @@
 use std::pin::Pin;
+use std::str::FromStr;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::{future::Future, task::Poll};
 
 use serde::{Deserialize, Serialize};
 
+use crate::ext::AsyncWriteExt;
 use crate::io::{ReadHalf, WriteHalf};
+use crate::mux::{MuxStream, Session};
 use crate::protocol::IntoPacket;
 use crate::{
     client::Route,
     generator::Generator,
+    guard::{Bandwidth, Counter, Lease, Limited, Limits, Quota, Traffic},
     protocol::{AsyncRecvPacket, AsyncSendPacket, Bind, Poto, ToBytes, TryToPoto},
-    Kind, Socket, Stream, {ClientProvider, Provider},
+    select::Select,
+    Keepalive, Kind, Outbound, Shutdown, Socket, Stream, {ClientProvider, Provider},
 };
 
-use crate::{io, join, time, Address, Processor, Platform};
+use crate::{io, join, time, Addr, Address, AsyncRead, AsyncWrite, Platform, Processor};
+
+use super::{AccessList, Balance, ProxyProtocol, Upstream, Upstreams};
 
 type BoxedFuture<T> = Pin<Box<dyn std::future::Future<Output = crate::Result<T>> + Send + 'static>>;
 
 macro_rules! async_connect {
-    ($writer: expr, $connector: expr, $id: expr, $socket: expr) => {{
-        let socket = $socket.clone();
+    ($writer: expr, $id: expr, $connect: expr) => {{
         let mut writer = $writer.clone();
+        let connect = $connect;
         async move {
-            log::debug!("try connect to {}", socket);
-            match $connector.call(socket).await {
+            match connect.await {
                 Ok(ok) => Ok(ok),
                 Err(err) => {
                     let poto = Poto::MapError($id, err.to_string()).bytes();
@@ -50,6 +56,30 @@ macro_rules! default_socket {
     }};
 }
 
+/// 转发前向服务写入PROXY protocol头
+async fn write_proxy_header<S>(
+    proxy_protocol: Option<ProxyProtocol>,
+    route: &mut Route<S>,
+    socket: &Socket,
+) -> crate::Result<()>
+where
+    S: Stream,
+{
+    if let (Some(proxy_protocol), Route::Forward(target)) = (proxy_protocol, route) {
+        proxy_protocol.write(target, socket.origin()).await?;
+    }
+
+    Ok(())
+}
+
+/// 转发到本地服务, 直到两个方向都结束, 一个方向结束后另一个方向没有数据超过linger时结束
+async fn forward<S1, S2>(s1: S1, s2: S2, linger: Duration) -> crate::Result<()>
+where
+    S1: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    S2: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    io::forward(s1, s2).linger(linger).await.map(|_| ())
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -57,6 +87,9 @@ pub struct Config {
     pub(super) name: String,
     /// 创建连接等待时间, 超过视为超时
     pub(super) maximum_wait: Duration,
+    /// 半关闭后另一个方向允许空闲的时间, 只在本地使用
+    #[serde(skip)]
+    pub(super) linger: Duration,
     /// 心跳延时
     pub(super) heartbeat_delay: Duration,
     /// 是否启用 kcp
@@ -72,19 +105,69 @@ pub struct Config {
     /// 是否启用socks5 udp转发
     pub(super) enable_socks5_udp: bool,
     pub(super) version: String,
-    pub(super) platform: Platform
+    pub(super) platform: Platform,
+    /// 是否在控制通道上进行多路复用
+    pub(super) enable_mux: bool,
+    /// 通过域名访问
+    pub(super) domain: Option<String>,
+    /// 转发前向服务写入的PROXY protocol头
+    pub(super) proxy_protocol: Option<ProxyProtocol>,
+    /// 额外的端口映射, 映射id从1开始
+    pub(super) mappings: Vec<Mapping>,
+    /// 访问者的黑白名单, 由服务端检查
+    pub(super) access: AccessList,
+    /// 同时访问的数量, 由服务端检查, 0表示不限制
+    pub(super) maximum_visitors: usize,
+    /// 访问者空闲超时, 由服务端检查
+    pub(super) idle_timeout: Option<Duration>,
+    /// 本地服务不可用时访问者收到的http响应内容, 暂停映射时发送给服务端
+    #[serde(skip)]
+    pub(super) suspend_page: Option<String>,
+}
+
+/// 端口映射, 每个映射在服务端拥有独立的访问端口
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Mapping {
+    pub(super) name: String,
+    /// 服务端的访问端口
+    pub(super) visit: Socket,
+    /// 转发的目标, 只在客户端使用
+    #[serde(skip)]
+    pub(super) target: Socket,
+    /// 其他的转发目标, 与target一起按balance负载均衡
+    #[serde(skip)]
+    pub(super) replicas: Vec<Socket>,
+    #[serde(skip)]
+    pub(super) balance: Balance,
+    pub(super) enable_socks5: bool,
+    pub(super) enable_socks5_udp: bool,
+    pub(super) socks_username: Option<String>,
+    pub(super) socks_password: Option<String>,
+    pub(super) proxy_protocol: Option<ProxyProtocol>,
+    pub(super) access: AccessList,
+    pub(super) maximum_visitors: usize,
+    pub(super) idle_timeout: Option<Duration>,
+    /// 带宽限制, 只在客户端使用
+    #[serde(skip)]
+    pub(super) bandwidth: Bandwidth,
+    #[serde(skip)]
+    pub(super) suspend_page: Option<String>,
 }
 
 pub struct PenetrateClientProvider<C> {
     pub config: Config,
     pub forward: (Socket, Socket),
     pub connector_provider: Arc<C>,
+    pub secret: Option<String>,
+    pub limits: Limits,
+    pub upstreams: Upstreams,
 }
 
 enum State {
     Leave(Socket),
     Ready(BoxedFuture<()>),
-    Map(u32, Socket),
+    Map(u32, u32, Socket),
+    Accept(MuxStream),
     Error(crate::Error),
 }
 
@@ -96,6 +179,258 @@ pub struct PenetrateClient<P, C, S> {
     futures: Vec<BoxedFuture<State>>,
     processor: Processor<ClientProvider<P>, S, ()>,
     connector_provider: Arc<C>,
+    mux: Option<Session>,
+    traffic: Arc<Traffic>,
+    limits: Limits,
+    upstreams: Upstreams,
+    /// 需要通知服务端的消息, 多路复用时由guard_mux_outbound统一发送
+    notices: async_channel::Sender<Poto>,
+}
+
+impl Config {
+    /// 映射的转发目标以及PROXY protocol, 映射0为默认映射
+    fn mapping(&self, id: u32, local: &Socket) -> Option<(Socket, Option<ProxyProtocol>)> {
+        match id {
+            0 => Some((local.clone(), self.proxy_protocol)),
+            id => self
+                .mappings
+                .get(id as usize - 1)
+                .map(|mapping| (mapping.target.clone(), mapping.proxy_protocol)),
+        }
+    }
+
+    /// 映射暂停时的http响应内容
+    fn suspend_page(&self, id: u32) -> Option<String> {
+        match id {
+            0 => self.suspend_page.clone(),
+            id => self
+                .mappings
+                .get(id as usize - 1)
+                .and_then(|mapping| mapping.suspend_page.clone()),
+        }
+    }
+
+    /// 映射的名称, 与服务端相同, 用于查找限速器
+    fn whoami(&self, id: u32) -> String {
+        match self.mappings.get((id as usize).wrapping_sub(1)) {
+            Some(mapping) if id != 0 => format!("{}/{}", self.name, mapping.name),
+            _ => self.name.clone(),
+        }
+    }
+
+    /// 每个映射的转发目标, local为默认映射的转发目标,
+    /// 没有指定health_check时只检查有多个目标的映射, 访问者自己选择目标(socks5)的映射不检查
+    pub(super) fn upstreams(
+        &self,
+        local: &Socket,
+        replicas: Vec<Socket>,
+        balance: Balance,
+        health_check: Option<Duration>,
+    ) -> Upstreams {
+        let upstream = |targets: Vec<Socket>, balance: Balance, socks: bool| {
+            let checked = !socks && (targets.len() > 1 || health_check.is_some());
+            Upstream::with_check(targets, balance, checked)
+        };
+
+        Upstreams::new(
+            std::iter::once(upstream(
+                std::iter::once(local.clone()).chain(replicas).collect(),
+                balance,
+                self.enable_socks5 || self.enable_socks5_udp,
+            ))
+            .chain(self.mappings.iter().map(|mapping| {
+                upstream(
+                    std::iter::once(mapping.target.clone())
+                        .chain(mapping.replicas.iter().cloned())
+                        .collect(),
+                    mapping.balance,
+                    mapping.enable_socks5 || mapping.enable_socks5_udp,
+                )
+            }))
+            .collect(),
+            health_check.unwrap_or(Duration::from_secs(10)),
+        )
+    }
+
+    /// 客户端的带宽限制以及每个映射的带宽限制
+    pub(super) fn limits(&self, bandwidth: Bandwidth) -> Limits {
+        Limits::new(
+            bandwidth,
+            self.mappings
+                .iter()
+                .map(|mapping| {
+                    Quota::new(
+                        &format!("{}/{}", self.name, mapping.name),
+                        mapping.bandwidth,
+                    )
+                })
+                .collect(),
+        )
+    }
+}
+
+impl Mapping {
+    pub fn new<V, T>(name: &str, visit: V, target: T) -> Self
+    where
+        V: Into<Socket>,
+        T: Into<Socket>,
+    {
+        Self {
+            name: name.to_owned(),
+            visit: visit.into(),
+            target: target.into(),
+            replicas: Vec::new(),
+            balance: Balance::default(),
+            enable_socks5: false,
+            enable_socks5_udp: false,
+            socks_username: None,
+            socks_password: None,
+            proxy_protocol: None,
+            access: AccessList::default(),
+            maximum_visitors: 0,
+            idle_timeout: None,
+            bandwidth: Bandwidth::default(),
+            suspend_page: None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn enable_socks5(mut self, enable: bool) -> Self {
+        self.enable_socks5 = enable;
+        self
+    }
+
+    pub fn enable_socks5_udp(mut self, enable: bool) -> Self {
+        self.enable_socks5_udp = enable;
+        self
+    }
+
+    pub fn set_socks5_username(mut self, username: Option<String>) -> Self {
+        self.socks_username = username;
+        self
+    }
+
+    pub fn set_socks5_password(mut self, password: Option<String>) -> Self {
+        self.socks_password = password;
+        self
+    }
+
+    pub fn set_proxy_protocol(mut self, proxy_protocol: Option<ProxyProtocol>) -> Self {
+        self.proxy_protocol = proxy_protocol;
+        self
+    }
+
+    pub fn set_access_list(mut self, access: AccessList) -> Self {
+        self.access = access;
+        self
+    }
+
+    pub fn set_bandwidth(mut self, bandwidth: Bandwidth) -> Self {
+        self.bandwidth = bandwidth;
+        self
+    }
+
+    pub fn maximum_visitors(mut self, maximum: usize) -> Self {
+        self.maximum_visitors = maximum;
+        self
+    }
+
+    pub fn idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    pub fn add_replica<T: Into<Socket>>(mut self, target: T) -> Self {
+        self.replicas.push(target.into());
+        self
+    }
+
+    pub fn set_balance(mut self, balance: Balance) -> Self {
+        self.balance = balance;
+        self
+    }
+
+    pub fn set_suspend_page(mut self, page: Option<String>) -> Self {
+        self.suspend_page = page;
+        self
+    }
+}
+
+/// name=ssh,visit=2222,target=127.0.0.1:22[,proxy-protocol=v1][,socks][,socks-udp]
+/// [,socks-username=..][,socks-password=..][,allow-ip=..][,deny-ip=..][,bandwidth=512K:2M]
+/// [,max-visitors=..][,balance=round-robin|least-conn|ip-hash][,suspend-page=<file>]
+/// [,idle-timeout=<秒>]
+/// allow-ip, deny-ip与target可以指定多次, 多个target之间负载均衡
+impl FromStr for Mapping {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut name = None;
+        let mut visit = None;
+        let mut target = None;
+        let mut replicas = Vec::new();
+        let mut mapping = Mapping::new("", Socket::default(), Socket::default());
+
+        for option in s.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (key, value) = match option.split_once('=') {
+                Some((key, value)) => (key.trim(), Some(value.trim())),
+                None => (option, None),
+            };
+
+            let invalid = || -> crate::Error {
+                Kind::Message(format!("invalid mapping option {}", option)).into()
+            };
+
+            match (key, value) {
+                ("name", Some(value)) => name = Some(value.to_owned()),
+                ("visit", Some(value)) => {
+                    visit = Some(value.parse::<u16>().map_err(|_| invalid())?)
+                }
+                ("target", Some(value)) if target.is_none() => {
+                    target = Some(value.parse::<Addr>()?)
+                }
+                ("target", Some(value)) => replicas.push(Socket::tcp(value.parse::<Addr>()?)),
+                ("balance", Some(value)) => mapping.balance = value.parse()?,
+                ("proxy-protocol", Some(value)) => mapping.proxy_protocol = Some(value.parse()?),
+                ("socks", None) => mapping.enable_socks5 = true,
+                ("socks-udp", None) => mapping.enable_socks5_udp = true,
+                ("socks-username", Some(value)) => mapping.socks_username = Some(value.to_owned()),
+                ("socks-password", Some(value)) => mapping.socks_password = Some(value.to_owned()),
+                ("allow-ip", Some(value)) => mapping.access.push_allow(value.parse()?),
+                ("deny-ip", Some(value)) => mapping.access.push_deny(value.parse()?),
+                ("bandwidth", Some(value)) => mapping.bandwidth = value.parse()?,
+                ("max-visitors", Some(value)) => {
+                    mapping.maximum_visitors = value.parse().map_err(|_| invalid())?
+                }
+                ("idle-timeout", Some(value)) => {
+                    let timeout = value.parse().map_err(|_| invalid())?;
+                    mapping.idle_timeout = Some(Duration::from_secs(timeout))
+                }
+                ("suspend-page", Some(value)) => {
+                    mapping.suspend_page = Some(std::fs::read_to_string(value).map_err(|e| {
+                        Kind::Message(format!("failed to read suspend page {}: {}", value, e))
+                    })?)
+                }
+                _ => return Err(invalid()),
+            }
+        }
+
+        match (name, target) {
+            (Some(name), Some(target)) => {
+                mapping.name = name;
+                mapping.visit = Socket::tcp(visit.unwrap_or(0));
+                mapping.target = Socket::tcp(target);
+                mapping.replicas = replicas;
+                Ok(mapping)
+            }
+            _ => {
+                Err(Kind::Message(format!("the mapping {} requires a name and a target", s)).into())
+            }
+        }
+    }
 }
 
 impl<P, C, S> Provider<(S, Processor<ClientProvider<P>, S, ()>)> for PenetrateClientProvider<C>
@@ -111,13 +446,26 @@ where
         let config = self.config.clone();
 
         let connector_provider = self.connector_provider.clone();
+        let secret = self.secret.clone();
+        let limits = self.limits.clone();
+        let upstreams = self.upstreams.clone();
 
         Box::pin(async move {
             let mut stream = stream;
+
+            if let Some(secret) = secret {
+                if let Err(e) = super::auth::client_authenticate(&mut stream, &secret).await {
+                    log::error!("server authentication failed err={}", e);
+                    return Err(e);
+                }
+            }
+
             let (visit_addr, route_addr) = socket;
             let bind = Poto::Bind(Bind::Setup(
-                Socket::tcp(config.channel_port).if_stream_mixed(config.enable_kcp || config.enable_socks5_udp),
+                Socket::tcp(config.channel_port)
+                    .if_stream_mixed(config.enable_kcp || config.enable_socks5_udp),
                 visit_addr.clone(),
+                config.name.clone(),
             ))
             .bytes();
 
@@ -173,6 +521,25 @@ where
                         return Err(configured.into());
                     };
 
+                    if !copy_cfg.mappings.is_empty() {
+                        match stream.recv_packet().await?.try_poto()? {
+                            Poto::Bind(Bind::Mapped(addrs)) => {
+                                for (mapping, mut addr) in copy_cfg.mappings.iter().zip(addrs) {
+                                    default_socket!(addr, processor.default_socket());
+                                    log::info!("please visit {} for {}", addr, mapping.name);
+                                }
+                            }
+                            Poto::Bind(Bind::Failed(fail)) => {
+                                log::error!("failed to create the mapping listener {}", fail);
+                                return Err(Kind::Message(fail).into());
+                            }
+                            message => {
+                                log::error!("unexpected mapping message {}", message);
+                                return Err(Kind::Unexpected(format!("{}", message)).into());
+                            }
+                        }
+                    }
+
                     default_socket!(visit_addr, processor.default_socket());
                     default_socket!(server_addr, processor.default_socket());
 
@@ -185,7 +552,9 @@ where
                         copy_cfg,
                         processor,
                         connector_provider,
-                    ))
+                    )
+                    .with_limits(limits)
+                    .with_upstreams(upstreams))
                 }
                 Poto::Bind(Bind::Failed(fail)) => {
                     log::error!(
@@ -219,42 +588,217 @@ where
         processor: Processor<ClientProvider<P>, S, ()>,
         connector_provider: Arc<C>,
     ) -> Self {
+        let mux = if config.enable_mux {
+            match (conn.local_addr(), conn.peer_addr()) {
+                (Ok(local_addr), Ok(peer_addr)) => Some(Session::new(false, local_addr, peer_addr)),
+                _ => None,
+            }
+        } else {
+            None
+        };
+
         let (reader, writer) = io::split(conn);
 
-        let fut1 = Box::pin(Self::register_server_handle(reader.clone()));
-        let fut2 = Box::pin(Self::guard_server_heartbeat(
-            writer.clone(),
-            config.maximum_wait,
-        ));
+        let mut futures: Vec<BoxedFuture<State>> = vec![Box::pin(Self::register_server_handle(
+            reader.clone(),
+            mux.clone(),
+        ))];
+
+        let shutdown = processor.shutdown().clone();
+        let (notices, receiver) = async_channel::unbounded();
+
+        match mux.clone() {
+            None => {
+                futures.push(Box::pin(Self::guard_server_heartbeat(
+                    writer.clone(),
+                    config.maximum_wait,
+                    shutdown,
+                )));
+                futures.push(Box::pin(Self::send_server_notices(
+                    writer.clone(),
+                    receiver,
+                )));
+            }
+            Some(mux) => {
+                futures.push(Box::pin(Self::accept_mux_stream(mux.clone())));
+                futures.push(Box::pin(Self::guard_mux_outbound(
+                    mux,
+                    writer.clone(),
+                    receiver,
+                    config.heartbeat_delay,
+                    shutdown,
+                )));
+            }
+        }
+
+        #[cfg(feature = "fuso-metrics")]
+        crate::metrics::metrics().clients.inc();
 
         Self {
             forward: socket,
             processor,
+            traffic: Arc::new(Traffic::new(&config.name)),
+            limits: Limits::default(),
+            upstreams: Upstreams::default(),
+            notices,
             config,
             connector_provider,
+            mux,
+            futures,
             reader: reader.clone(),
             writer: writer.clone(),
-            futures: vec![fut1, fut2],
+        }
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn with_upstreams(mut self, upstreams: Upstreams) -> Self {
+        if upstreams.needs_check() {
+            self.futures.push(Box::pin(Self::guard_upstream_health(
+                upstreams.clone(),
+                self.connector_provider.clone(),
+                self.config.clone(),
+                self.notices.clone(),
+            )));
+        }
+
+        self.upstreams = upstreams;
+        self
+    }
+
+    /// 定期检查转发目标, 映射的所有目标都不可用时通知服务端暂停映射, 恢复后通知服务端继续
+    async fn guard_upstream_health(
+        upstreams: Upstreams,
+        connector: Arc<C>,
+        config: Config,
+        notices: async_channel::Sender<Poto>,
+    ) -> crate::Result<State> {
+        // 每次连接服务端后所有映射都是未暂停的
+        let mut suspended = Vec::new();
+
+        loop {
+            upstreams.check(&connector, config.maximum_wait).await;
+
+            suspended.resize(upstreams.len(), false);
+
+            for (id, available) in upstreams.availability().into_iter().enumerate() {
+                if suspended[id] != available {
+                    continue;
+                }
+
+                suspended[id] = !available;
+
+                let id = id as u32;
+                let notice = if available {
+                    log::info!("local service of {} is back, resume it", config.whoami(id));
+                    Poto::Resume(id)
+                } else {
+                    log::warn!("local service of {} is down, suspend it", config.whoami(id));
+                    Poto::Suspend(id, config.suspend_page(id))
+                };
+
+                if notices.send(notice).await.is_err() {
+                    return Ok(State::Error(Kind::Channel.into()));
+                }
+            }
+
+            time::sleep(upstreams.interval()).await;
+        }
+    }
+
+    async fn send_server_notices(
+        mut writer: WriteHalf<S>,
+        notices: async_channel::Receiver<Poto>,
+    ) -> crate::Result<State> {
+        while let Ok(notice) = notices.recv().await {
+            if let Err(e) = writer.send_packet(&notice.bytes()).await {
+                log::error!("failed to send notice to server err={}", e);
+                return Ok(State::Error(e));
+            }
+        }
+
+        Ok(State::Error(Kind::Channel.into()))
+    }
+
+    async fn guard_mux_outbound(
+        mux: Session,
+        mut writer: WriteHalf<S>,
+        notices: async_channel::Receiver<Poto>,
+        timeout: Duration,
+        shutdown: Shutdown,
+    ) -> crate::Result<State> {
+        let mut keepalive = Keepalive::new(shutdown, timeout);
+
+        loop {
+            let next_frame = {
+                let mux = mux.clone();
+                let notices = notices.clone();
+                let next_frame = async move { mux.next_frame().await.map(Poto::Mux) };
+                let notice = async move { notices.recv().await.map_err(|_| Kind::Channel.into()) };
+                keepalive.next(Select::select(notice, next_frame)).await
+            };
+
+            let packet = match next_frame {
+                Ok(Outbound::Data(poto)) => poto.bytes(),
+                // 通知服务端不再发送新的访问者, 已建立的流继续转发
+                Ok(Outbound::Close) => {
+                    log::info!("notify the server that the client is shutting down");
+                    Poto::Close.bytes()
+                }
+                Ok(Outbound::Ping) => Poto::Ping.bytes(),
+                Err(e) => return Ok(State::Error(e)),
+            };
+
+            if let Err(e) = writer.send_packet(&packet).await {
+                log::error!("failed to send packet to server err={}", e);
+                mux.close();
+                return Ok(State::Error(e));
+            }
+        }
+    }
+
+    async fn accept_mux_stream(mux: Session) -> crate::Result<State> {
+        match mux.accept().await {
+            Ok(stream) => Ok(State::Accept(stream)),
+            Err(e) => Ok(State::Error(e)),
         }
     }
 
     async fn guard_server_heartbeat(
         mut writer: WriteHalf<S>,
         timeout: Duration,
+        shutdown: Shutdown,
     ) -> crate::Result<State> {
-        let ping = Poto::Ping.bytes();
+        let mut keepalive = Keepalive::new(shutdown, timeout);
+        let mut packet = Poto::Ping.bytes();
 
         loop {
-            if let Err(e) = writer.send_packet(&ping).await {
-                log::error!("failed to send heartbeat to server err={}", e);
+            if let Err(e) = writer.send_packet(&packet).await {
+                log::error!("failed to send packet to server err={}", e);
                 return Ok(State::Error(e));
             }
 
-            time::sleep(timeout).await;
+            // 没有数据需要发送, 只有心跳与关闭通知
+            let idle = std::future::pending::<crate::Result<()>>();
+
+            packet = match keepalive.next(idle).await {
+                // 通知服务端不再发送新的访问者, 已建立的连接继续转发
+                Ok(Outbound::Close) => {
+                    log::info!("notify the server that the client is shutting down");
+                    Poto::Close.bytes()
+                }
+                _ => Poto::Ping.bytes(),
+            };
         }
     }
 
-    async fn register_server_handle(mut reader: ReadHalf<S>) -> crate::Result<State> {
+    async fn register_server_handle(
+        mut reader: ReadHalf<S>,
+        mux: Option<Session>,
+    ) -> crate::Result<State> {
         loop {
             let message = match reader.recv_packet().await {
                 Ok(packet) => packet.try_poto(),
@@ -269,8 +813,19 @@ where
             let message = unsafe { message.unwrap_unchecked() };
 
             match message {
-                Poto::Map(id, socket) => {
-                    break Ok(State::Map(id, socket));
+                Poto::Map(id, mapping, socket) => {
+                    break Ok(State::Map(id, mapping, socket));
+                }
+                Poto::Mux(frame) => match mux.as_ref() {
+                    None => log::warn!("multiplexing is not enabled, ignore {:?}", frame),
+                    Some(mux) => {
+                        if let Err(e) = mux.dispatch(frame) {
+                            return Ok(State::Error(e));
+                        }
+                    }
+                },
+                Poto::Close => {
+                    log::info!("the server is shutting down");
                 }
                 message => {
                     log::trace!("received server message {:?}", message);
@@ -281,18 +836,27 @@ where
 
     fn start_async_forward(
         &self,
-        id: u32,
+        (id, mapping): (u32, u32),
         server_socket: Socket,
-        target_socket: Socket,
+        (upstream, target_socket): (Upstream, Socket),
+        proxy_protocol: Option<ProxyProtocol>,
     ) -> BoxedFuture<State> {
-        let s1_connector = self.processor.clone();
-        let s2_connector = self.connector_provider.clone();
         let maximum_wait = self.config.maximum_wait.clone();
+        let linger = self.config.linger;
 
-        let server_fut = async_connect!(self.writer, s1_connector, id, server_socket);
-        let client_fut = async_connect!(self.writer, s2_connector, id, target_socket);
+        log::debug!("try connect to {}", server_socket);
+
+        let server_fut = async_connect!(self.writer, id, self.processor.call(server_socket));
+        let client_fut = async_connect!(
+            self.writer,
+            id,
+            upstream.connect(self.connector_provider.clone(), target_socket, maximum_wait)
+        );
         let server_writer = self.writer.clone();
         let processor = self.processor.clone();
+        let traffic = self.traffic.clone();
+        let limiters = self.limits.limiters(&self.config.whoami(mapping));
+        let start = Instant::now();
 
         let future = async move {
             let mut server_writer = server_writer;
@@ -304,11 +868,13 @@ where
                 Ok(r) => r,
             };
 
-            let (s1, s2) = result?;
+            let (s1, (mut s2, target_socket, slot)) = result?;
+
+            write_proxy_header(proxy_protocol, &mut s2, &target_socket).await?;
 
             let mut s1 = processor.decorate(s1).await?;
 
-            let poto = Poto::Map(id, target_socket).bytes();
+            let poto = Poto::Map(id, mapping, target_socket).bytes();
 
             if let Err(e) = s1.send_packet(&poto).await {
                 let message = Poto::MapError(id, e.to_string()).bytes();
@@ -318,9 +884,17 @@ where
                     Err(e)
                 }
             } else {
+                traffic.mapped(start.elapsed());
                 Ok(State::Ready({
                     match s2 {
-                        Route::Forward(s2) => Box::pin(io::forward(s1, s2)),
+                        Route::Forward(s2) => Box::pin(forward(
+                            Limited::new(
+                                Counter::new(s1, traffic),
+                                Lease::new(limiters).hold(slot),
+                            ),
+                            s2,
+                            linger,
+                        )),
                         Route::Provider(s2) => s2.call(s1),
                     }
                 }))
@@ -329,6 +903,94 @@ where
 
         Box::pin(future)
     }
+
+    fn start_mux_forward(&self, stream: MuxStream) -> BoxedFuture<State> {
+        let (server, local) = self.forward.clone();
+        let config = self.config.clone();
+        let connector = self.connector_provider.clone();
+        let processor = self.processor.clone();
+        let maximum_wait = self.config.maximum_wait;
+        let traffic = self.traffic.clone();
+        let limits = self.limits.clone();
+        let upstreams = self.upstreams.clone();
+        let start = Instant::now();
+
+        Box::pin(async move {
+            let mut stream = stream;
+
+            let (id, mapping, target_socket) = match stream.recv_packet().await?.try_poto()? {
+                Poto::Map(id, mapping, socket) => (id, mapping, socket),
+                poto => {
+                    log::warn!("bad message {}", poto);
+                    return Err(Kind::Unexpected(format!("{}", poto)).into());
+                }
+            };
+
+            let (upstream, proxy_protocol) = match config.mapping(mapping, &local) {
+                Some((local, proxy_protocol)) => (upstreams.get(mapping, local), proxy_protocol),
+                None => {
+                    log::warn!("unknown mapping {}", mapping);
+                    return Err(Kind::Unexpected(format!("mapping {}", mapping)).into());
+                }
+            };
+
+            // 所有目标都连接失败时直接丢弃流, 对端会收到重置
+            let (mut route, target_socket, slot) = upstream
+                .connect(connector, target_socket, maximum_wait)
+                .await?;
+
+            log::debug!("mux stream {} -> {}", stream.id(), target_socket);
+
+            write_proxy_header(proxy_protocol, &mut route, &target_socket).await?;
+
+            traffic.mapped(start.elapsed());
+
+            match route {
+                Route::Forward(target) => Ok(State::Ready(Box::pin(forward(
+                    Limited::new(
+                        Counter::new(stream, traffic),
+                        Lease::new(limits.limiters(&config.whoami(mapping))).hold(slot),
+                    ),
+                    target,
+                    config.linger,
+                )))),
+                Route::Provider(provider) => {
+                    let connect = async move {
+                        let server_socket = server.select(&target_socket)?;
+                        let s1 =
+                            time::wait_for(maximum_wait, processor.call(server_socket)).await??;
+                        let mut s1 = processor.decorate(s1).await?;
+                        s1.send_packet(&Poto::Map(id, mapping, target_socket).bytes())
+                            .await?;
+                        Ok::<_, crate::Error>(s1)
+                    };
+
+                    match connect.await {
+                        Ok(s1) => {
+                            let _ = stream.close().await;
+                            Ok(State::Ready(provider.call(s1)))
+                        }
+                        Err(e) => {
+                            let poto = Poto::MapError(id, e.to_string()).bytes();
+                            let _ = stream.send_packet(&poto).await;
+                            Err(e)
+                        }
+                    }
+                }
+            }
+        })
+    }
+}
+
+impl<P, C, S> Drop for PenetrateClient<P, C, S> {
+    fn drop(&mut self) {
+        if let Some(mux) = self.mux.take() {
+            mux.close();
+        }
+
+        #[cfg(feature = "fuso-metrics")]
+        crate::metrics::metrics().clients.dec();
+    }
 }
 
 impl<CF, C, S> Generator for PenetrateClient<CF, C, S>
@@ -354,18 +1016,32 @@ where
                 Poll::Ready(Ok(State::Leave(socket))) => {
                     log::warn!("leave {}", socket);
                 }
-                Poll::Ready(Ok(State::Map(id, target_socket))) => {
-                    log::debug!("{}", target_socket);
+                Poll::Ready(Ok(State::Map(id, mapping, target_socket))) => {
+                    log::debug!("{} {}", mapping, target_socket);
 
                     let (server, local) = self.forward.clone();
-                    let target_socket = target_socket.default_or(local);
                     let server_writer = self.writer.clone();
+                    let traffic = self.traffic.clone();
 
-                    let future = match server.select(&target_socket) {
-                        Ok(server_socket) => {
-                            self.start_async_forward(id, server_socket, target_socket)
+                    let selected = match self.config.mapping(mapping, &local) {
+                        None => Err(Kind::Unexpected(format!("mapping {}", mapping)).into()),
+                        Some((local, proxy_protocol)) => {
+                            let upstream = self.upstreams.get(mapping, local.clone());
+                            server
+                                .select(&target_socket.clone().default_or(local))
+                                .map(|server_socket| (server_socket, upstream, proxy_protocol))
                         }
+                    };
+
+                    let future = match selected {
+                        Ok((server_socket, upstream, proxy_protocol)) => self.start_async_forward(
+                            (id, mapping),
+                            server_socket,
+                            (upstream, target_socket.clone()),
+                            proxy_protocol,
+                        ),
                         Err(e) => Box::pin(async move {
+                            traffic.map_failed();
                             let mut server_writer = server_writer;
                             let poto = Poto::MapError(id, e.to_string()).bytes();
                             match server_writer.send_packet(&poto).await {
@@ -375,17 +1051,37 @@ where
                         }),
                     };
 
-                    let fut2 = Box::pin(Self::register_server_handle(self.reader.clone()));
+                    let fut2 = Box::pin(Self::register_server_handle(
+                        self.reader.clone(),
+                        self.mux.clone(),
+                    ));
 
                     futures.push(future);
                     futures.push(fut2);
                 }
+                Poll::Ready(Ok(State::Accept(stream))) => {
+                    log::debug!("accept mux stream {}", stream.id());
+
+                    let future = self.start_mux_forward(stream);
+
+                    // 只有开启了多路复用才会收到流, 继续接收下一个
+                    if let Some(mux) = self.mux.clone() {
+                        futures.push(Box::pin(Self::accept_mux_stream(mux)));
+                    }
+
+                    futures.push(future);
+                }
                 Poll::Ready(Ok(State::Ready(fut))) => {
+                    let traffic = self.traffic.clone();
                     self.futures.extend(futures);
-                    return Poll::Ready(Ok(Some(fut)));
+                    return Poll::Ready(Ok(Some(Box::pin(async move {
+                        let _conn = traffic.connect();
+                        fut.await
+                    }))));
                 }
                 Poll::Ready(Err(e)) => {
                     log::trace!("{:?}", e);
+                    self.traffic.map_failed();
                 }
             }
         }
@@ -395,3 +1091,40 @@ where
         Poll::Pending
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Mapping;
+    use crate::penetrate::{Balance, ProxyProtocol};
+
+    #[test]
+    fn test_parse_mapping() {
+        let mapping: Mapping = "name=ssh,visit=2222,target=127.0.0.1:22,proxy-protocol=v2,socks"
+            .parse()
+            .unwrap();
+
+        assert_eq!(mapping.name(), "ssh");
+        assert_eq!(mapping.visit.addr().port(), 2222);
+        assert_eq!(mapping.target.addr().port(), 22);
+        assert_eq!(mapping.proxy_protocol, Some(ProxyProtocol::V2));
+        assert!(mapping.enable_socks5);
+        assert!(!mapping.enable_socks5_udp);
+
+        let mapping: Mapping =
+            "name=web,target=127.0.0.1:80,target=127.0.0.1:81,balance=ip-hash,idle-timeout=60"
+                .parse()
+                .unwrap();
+
+        assert_eq!(mapping.target.addr().port(), 80);
+        assert_eq!(mapping.idle_timeout, Some(Duration::from_secs(60)));
+        assert_eq!(mapping.replicas.len(), 1);
+        assert_eq!(mapping.balance, Balance::IpHash);
+
+        assert!("visit=2222,target=127.0.0.1:22".parse::<Mapping>().is_err());
+        assert!("name=ssh,target=127.0.0.1:22,unknown"
+            .parse::<Mapping>()
+            .is_err());
+    }
+}
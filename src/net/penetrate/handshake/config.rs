@@ -0,0 +1,119 @@
+use crate::{
+    encryption::CipherSuite,
+    error::{EncryptionErr, Kind},
+    ext::{AsyncReadExt, AsyncWriteExt},
+    Stream,
+};
+
+use super::{ServerIdentity, ServerPinning};
+
+/// 服务端握手配置
+pub struct ServerHandshakeConfig {
+    pub(super) identity: Option<ServerIdentity>,
+    /// 允许使用的加密套件
+    pub(super) ciphers: Vec<CipherSuite>,
+}
+
+/// 客户端握手配置
+pub struct ClientHandshakeConfig {
+    pub(super) pinning: Option<ServerPinning>,
+    /// 按优先级排列的加密套件
+    pub(super) ciphers: Vec<CipherSuite>,
+}
+
+impl Default for ServerHandshakeConfig {
+    fn default() -> Self {
+        Self {
+            identity: None,
+            ciphers: CipherSuite::defaults(),
+        }
+    }
+}
+
+impl Default for ClientHandshakeConfig {
+    fn default() -> Self {
+        Self {
+            pinning: None,
+            ciphers: CipherSuite::defaults(),
+        }
+    }
+}
+
+impl ServerHandshakeConfig {
+    pub fn identity(mut self, identity: Option<ServerIdentity>) -> Self {
+        self.identity = identity;
+        self
+    }
+
+    pub fn ciphers(mut self, ciphers: Vec<CipherSuite>) -> Self {
+        if !ciphers.is_empty() {
+            self.ciphers = ciphers;
+        }
+        self
+    }
+
+    /// 从客户端提供的加密套件中选出第一个服务端允许的
+    pub(super) async fn negotiate<S>(&self, stream: &mut S) -> crate::Result<CipherSuite>
+    where
+        S: Stream + Unpin,
+    {
+        let mut len = [0u8; 1];
+        stream.read_exact(&mut len).await?;
+
+        let mut offered = vec![0u8; len[0] as usize];
+        stream.read_exact(&mut offered).await?;
+
+        let selected = offered
+            .iter()
+            .filter_map(|id| CipherSuite::from_id(*id))
+            .find(|suite| self.ciphers.contains(suite));
+
+        match selected {
+            Some(suite) => {
+                stream.write_all(&[suite.id()]).await?;
+                Ok(suite)
+            }
+            None => {
+                stream.write_all(&[0]).await?;
+                Err(Kind::Encryption(EncryptionErr::Cipher(String::from(
+                    "no cipher suite in common with the client",
+                )))
+                .into())
+            }
+        }
+    }
+}
+
+impl ClientHandshakeConfig {
+    pub fn pinning(mut self, pinning: Option<ServerPinning>) -> Self {
+        self.pinning = pinning;
+        self
+    }
+
+    pub fn ciphers(mut self, ciphers: Vec<CipherSuite>) -> Self {
+        if !ciphers.is_empty() {
+            self.ciphers = ciphers;
+        }
+        self
+    }
+
+    pub(super) async fn negotiate<S>(&self, stream: &mut S) -> crate::Result<CipherSuite>
+    where
+        S: Stream + Unpin,
+    {
+        let mut offered = vec![self.ciphers.len() as u8];
+        offered.extend(self.ciphers.iter().map(CipherSuite::id));
+        stream.write_all(&offered).await?;
+
+        let mut selected = [0u8; 1];
+        stream.read_exact(&mut selected).await?;
+
+        match CipherSuite::from_id(selected[0]) {
+            Some(suite) if self.ciphers.contains(&suite) => Ok(suite),
+            _ => Err(Kind::Encryption(EncryptionErr::Cipher(String::from(
+                "no cipher suite in common with the server",
+            )))
+            .into()),
+        }
+    }
+}
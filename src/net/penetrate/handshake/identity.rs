@@ -0,0 +1,324 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use rsa::{
+    pkcs8::{DecodePrivateKey, DecodePublicKey, EncodePrivateKey, EncodePublicKey, LineEnding},
+    Hash, PaddingScheme, PublicKey, RsaPrivateKey, RsaPublicKey,
+};
+use sha2::{Digest, Sha256};
+
+use crate::Kind;
+
+/// 服务端长期身份密钥
+pub struct ServerIdentity {
+    priv_key: RsaPrivateKey,
+}
+
+/// 客户端对服务端身份的校验方式
+pub struct ServerPinning {
+    /// 服务端标识, 用于在 known_hosts 中查找
+    host: String,
+    /// 显式指定的指纹, 优先于 known_hosts
+    fingerprint: Option<String>,
+    /// 首次信任时记录指纹的文件
+    known_hosts: Option<PathBuf>,
+    lock: Mutex<()>,
+}
+
+pub fn fingerprint(der: &[u8]) -> String {
+    Sha256::digest(der)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn normalize(fingerprint: &str) -> String {
+    fingerprint
+        .chars()
+        .filter(|c| *c != ':')
+        .collect::<String>()
+        .to_lowercase()
+}
+
+fn signed_digest(server_key: &[u8], client_key: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(server_key);
+    hasher.update(client_key);
+    hasher.finalize().to_vec()
+}
+
+impl ServerIdentity {
+    pub fn new(priv_key: RsaPrivateKey) -> Self {
+        Self { priv_key }
+    }
+
+    /// 从文件中加载身份密钥, 文件不存在时生成并保存
+    pub fn load_or_generate<P: Into<PathBuf>>(path: P) -> crate::Result<Self> {
+        let path = path.into();
+
+        let identity = if path.exists() {
+            let pem = std::fs::read_to_string(&path)?;
+            let priv_key = RsaPrivateKey::from_pkcs8_pem(&pem).map_err(|e| {
+                Kind::Identity(format!("failed to load {}: {}", path.display(), e))
+            })?;
+
+            Self::new(priv_key)
+        } else {
+            log::info!("generate server identity {}", path.display());
+
+            let priv_key = RsaPrivateKey::new(&mut rand::thread_rng(), 2048)?;
+            let pem = priv_key
+                .to_pkcs8_pem(LineEnding::LF)
+                .map_err(|e| Kind::Identity(e.to_string()))?;
+
+            write_private(&path, pem.as_bytes())?;
+
+            Self::new(priv_key)
+        };
+
+        log::info!("server fingerprint {}", identity.fingerprint()?);
+
+        Ok(identity)
+    }
+
+    pub fn public_key_der(&self) -> crate::Result<Vec<u8>> {
+        Ok(RsaPublicKey::from(&self.priv_key)
+            .to_public_key_der()?
+            .as_ref()
+            .to_vec())
+    }
+
+    pub fn fingerprint(&self) -> crate::Result<String> {
+        Ok(fingerprint(&self.public_key_der()?))
+    }
+
+    /// 对双方的临时公钥进行签名
+    pub(super) fn sign(&self, server_key: &[u8], client_key: &[u8]) -> crate::Result<Vec<u8>> {
+        let digest = signed_digest(server_key, client_key);
+        let ps = PaddingScheme::new_pkcs1v15_sign(Some(Hash::SHA2_256));
+        Ok(self.priv_key.sign(ps, &digest)?)
+    }
+}
+
+/// 私钥文件只允许所有者读写
+fn write_private(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    options.open(path)?.write_all(data)
+}
+
+impl ServerPinning {
+    pub fn new<H: Into<String>>(host: H) -> Self {
+        Self {
+            host: host.into(),
+            fingerprint: None,
+            known_hosts: None,
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn fingerprint(mut self, fingerprint: Option<String>) -> Self {
+        self.fingerprint = fingerprint.map(|f| normalize(&f));
+        self
+    }
+
+    pub fn known_hosts<P: Into<PathBuf>>(mut self, path: Option<P>) -> Self {
+        self.known_hosts = path.map(Into::into);
+        self
+    }
+
+    /// 查找已记录的指纹, 显式指定的指纹优先
+    fn expect(&self) -> crate::Result<Option<String>> {
+        if let Some(expect) = self.fingerprint.as_ref() {
+            return Ok(Some(expect.clone()));
+        }
+
+        let path = match self.known_hosts.as_ref() {
+            Some(path) if path.exists() => path,
+            _ => return Ok(None),
+        };
+
+        let known_hosts = std::fs::read_to_string(path)?;
+
+        Ok(known_hosts.lines().find_map(|line| {
+            let mut fields = line.split_whitespace();
+            match (fields.next(), fields.next()) {
+                (Some(host), Some(fingerprint)) if host.eq(&self.host) => {
+                    Some(normalize(fingerprint))
+                }
+                _ => None,
+            }
+        }))
+    }
+
+    fn mismatch(&self, expect: &str, current: &str) -> crate::Error {
+        let mut message = format!(
+            "fingerprint mismatch for {}! expect: {}, current: {}",
+            self.host, expect, current
+        );
+
+        if let (None, Some(path)) = (self.fingerprint.as_ref(), self.known_hosts.as_ref()) {
+            message.push_str(&format!(
+                ", if the server identity was changed, remove it from {}",
+                path.display()
+            ));
+        }
+
+        Kind::Identity(message).into()
+    }
+
+    /// 校验服务端签名以及身份指纹, 首次连接时记录到 known_hosts
+    pub(super) fn verify(
+        &self,
+        identity: &[u8],
+        signature: &[u8],
+        server_key: &[u8],
+        client_key: &[u8],
+    ) -> crate::Result<()> {
+        let _guard = self.lock.lock()?;
+
+        let expect = self.expect()?;
+
+        if identity.is_empty() {
+            return match expect {
+                None => {
+                    log::warn!("the server {} did not present an identity", self.host);
+                    Ok(())
+                }
+                Some(expect) => Err(self.mismatch(&expect, "none")),
+            };
+        }
+
+        let publ_key = RsaPublicKey::from_public_key_der(identity)
+            .map_err(|_| Kind::Identity(String::from("invalid server identity key")))?;
+
+        let digest = signed_digest(server_key, client_key);
+        let ps = PaddingScheme::new_pkcs1v15_sign(Some(Hash::SHA2_256));
+
+        if publ_key.verify(ps, &digest, signature).is_err() {
+            return Err(Kind::Identity(String::from(
+                "the server signature is invalid, the connection may be hijacked",
+            ))
+            .into());
+        }
+
+        let current = fingerprint(identity);
+
+        match (expect, self.known_hosts.as_ref()) {
+            (Some(expect), _) if expect.eq(&current) => Ok(()),
+            (Some(expect), _) => Err(self.mismatch(&expect, &current)),
+            (None, None) => Ok(()),
+            (None, Some(path)) => {
+                log::warn!(
+                    "trust the server {} for the first time, fingerprint: {}",
+                    self.host,
+                    current
+                );
+
+                let mut file = std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)?;
+
+                writeln!(file, "{} {}", self.host, current)?;
+
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rsa::RsaPrivateKey;
+
+    use super::{fingerprint, ServerIdentity, ServerPinning};
+
+    const SERVER_KEY: &[u8] = b"server ephemeral key";
+    const CLIENT_KEY: &[u8] = b"client ephemeral key";
+
+    fn identity() -> ServerIdentity {
+        ServerIdentity::new(RsaPrivateKey::new(&mut rand::thread_rng(), 1024).unwrap())
+    }
+
+    fn verify(pinning: &ServerPinning, identity: &ServerIdentity) -> crate::Result<()> {
+        let signature = identity.sign(SERVER_KEY, CLIENT_KEY)?;
+        let identity = identity.public_key_der()?;
+        pinning.verify(&identity, &signature, SERVER_KEY, CLIENT_KEY)
+    }
+
+    #[test]
+    fn test_trust_on_first_use() {
+        let path = std::env::temp_dir().join(format!("fuso_known_hosts_{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let pinning = ServerPinning::new("example.com:6722").known_hosts(Some(&path));
+        let trusted = identity();
+
+        // 首次连接记录指纹
+        verify(&pinning, &trusted).unwrap();
+        let known_hosts = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            known_hosts,
+            format!(
+                "example.com:6722 {}\n",
+                fingerprint(&trusted.public_key_der().unwrap())
+            )
+        );
+
+        // 相同的身份通过, 不会重复记录
+        verify(&pinning, &trusted).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), known_hosts);
+
+        // 身份变化或者不提供身份时拒绝
+        let err = verify(&pinning, &identity()).unwrap_err();
+        assert!(err.to_string().contains("fingerprint mismatch"));
+        assert!(pinning.verify(&[], &[], SERVER_KEY, CLIENT_KEY).is_err());
+
+        // 其他服务端不受影响
+        let other = ServerPinning::new("example.org:6722").known_hosts(Some(&path));
+        verify(&other, &identity()).unwrap();
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_pinned_fingerprint() {
+        let trusted = identity();
+        let expect = fingerprint(&trusted.public_key_der().unwrap()).to_uppercase();
+
+        let pinning = ServerPinning::new("example.com:6722").fingerprint(Some(expect));
+
+        verify(&pinning, &trusted).unwrap();
+        assert!(verify(&pinning, &identity()).is_err());
+    }
+
+    #[test]
+    fn test_bad_signature() {
+        let pinning = ServerPinning::new("example.com:6722");
+        let trusted = identity();
+        let identity = trusted.public_key_der().unwrap();
+
+        // 签名的临时公钥与实际的不一致, 说明连接被劫持
+        let signature = trusted.sign(b"attacker key", CLIENT_KEY).unwrap();
+        let err = pinning
+            .verify(&identity, &signature, SERVER_KEY, CLIENT_KEY)
+            .unwrap_err();
+        assert!(err.to_string().contains("signature is invalid"));
+
+        assert!(pinning
+            .verify(&identity, &[0u8; 128], SERVER_KEY, CLIENT_KEY)
+            .is_err());
+    }
+}
@@ -1,14 +1,25 @@
 pub mod real_ip;
 
-use std::pin::Pin;
+mod config;
+mod identity;
+#[cfg(feature = "fuso-crypt-x25519")]
+mod x25519;
+
+pub use config::*;
+pub use real_ip::ProxyProtocol;
+pub use identity::*;
+#[cfg(feature = "fuso-crypt-x25519")]
+pub use x25519::*;
+
+use std::{pin::Pin, sync::Arc};
 
 use rsa::pkcs8::{DecodePublicKey, EncodePublicKey};
 
 use crate::{
     compress::Lz4Compress,
-    encryption::{AESEncryptor, RSAEncryptor},
+    encryption::{AESEncryptor, CipherSuite, RSAEncryptor},
     ext::{AsyncReadExt, AsyncWriteExt},
-    DecorateProvider, FusoStream, Provider, Stream, ToBoxStream,
+    DecorateProvider, FusoStream, Kind, Provider, Stream, ToBoxStream,
 };
 
 type BoxedFuture<T> = Pin<Box<dyn std::future::Future<Output = crate::Result<T>> + Send + 'static>>;
@@ -16,31 +27,29 @@ type BoxedFuture<T> = Pin<Box<dyn std::future::Future<Output = crate::Result<T>>
 pub enum PenetrateRsaAndAesHandshake {
     Server,
     Client,
+    /// 指定身份密钥以及允许的加密套件
+    ConfiguredServer(Arc<ServerHandshakeConfig>),
+    /// 指定服务端身份校验以及加密套件
+    ConfiguredClient(Arc<ClientHandshakeConfig>),
 }
 
 pub struct PenetrateAesAndLz4Decorator {
+    suite: CipherSuite,
     iv: [u8; 16],
-    key: [u8; 16],
+    key: [u8; 32],
 }
 
 impl PenetrateRsaAndAesHandshake {
     pub fn server_handshake<S>(
         client: S,
+        config: Arc<ServerHandshakeConfig>,
     ) -> BoxedFuture<(FusoStream, Option<DecorateProvider<FusoStream>>)>
     where
         S: Stream + Unpin + Send + 'static,
     {
         Box::pin(async move {
             let mut client = Lz4Compress::new(client);
-            let mut buf = [0u8; 4];
-            client.read_exact(&mut buf).await?;
-            let len = u32::from_be_bytes(buf) as usize;
-
-            let mut buf = Vec::with_capacity(len);
-
-            unsafe { buf.set_len(len) }
-
-            client.read_exact(&mut buf).await?;
+            let buf = read_sized(&mut client).await?;
 
             let priv_key = rsa::RsaPrivateKey::new(&mut rand::thread_rng(), 1024)?;
             let publ_key = rsa::RsaPublicKey::from(&priv_key);
@@ -53,20 +62,40 @@ impl PenetrateRsaAndAesHandshake {
             client.write_all(&len.to_be_bytes()).await?;
             client.write_all(pem).await?;
 
+            // 未配置身份密钥时只发送长度0
+            match config.identity.as_ref() {
+                None => client.write_all(&0u32.to_be_bytes()).await?,
+                Some(identity) => {
+                    let identity_key = identity.public_key_der()?;
+                    let signature = identity.sign(pem, &buf)?;
+                    let identity_len = identity_key.len() as u32;
+                    let signature_len = signature.len() as u32;
+
+                    client.write_all(&identity_len.to_be_bytes()).await?;
+                    client.write_all(&identity_key).await?;
+                    client.write_all(&signature_len.to_be_bytes()).await?;
+                    client.write_all(&signature).await?;
+                }
+            }
+
             let mut fuso_stream = RSAEncryptor::new(client, client_publ_key, priv_key);
 
+            let suite = config.negotiate(&mut fuso_stream).await?;
+
             let mut iv = [0u8; 16];
-            let mut key = [0u8; 16];
+            let mut key = [0u8; 32];
 
             fuso_stream.read_exact(&mut iv).await?;
 
             fuso_stream.read_exact(&mut key).await?;
 
+            log::debug!("use cipher suite {}", suite);
             log::trace!("iv: {:?}, key: {:?}", iv, key);
 
             Ok((
                 fuso_stream.into_boxed_stream(),
                 Some(DecorateProvider::wrap(PenetrateAesAndLz4Decorator {
+                    suite,
                     iv,
                     key,
                 })),
@@ -76,6 +105,7 @@ impl PenetrateRsaAndAesHandshake {
 
     pub fn client_handshake<S>(
         stream: S,
+        config: Arc<ClientHandshakeConfig>,
     ) -> BoxedFuture<(FusoStream, Option<DecorateProvider<FusoStream>>)>
     where
         S: Stream + Unpin + Send + 'static,
@@ -93,28 +123,33 @@ impl PenetrateRsaAndAesHandshake {
             stream.write_all(&len.to_be_bytes()).await?;
             stream.write_all(pem).await?;
 
-            let mut buf = [0u8; 4];
-            stream.read_exact(&mut buf).await?;
-            let len = u32::from_be_bytes(buf) as usize;
+            let buf = read_sized(&mut stream).await?;
 
-            let mut buf = Vec::with_capacity(len);
+            let server_publ_key = rsa::RsaPublicKey::from_public_key_der(&buf)?;
 
-            unsafe {
-                buf.set_len(len);
-            }
+            let identity = read_sized(&mut stream).await?;
 
-            stream.read_exact(&mut buf).await?;
+            let signature = if identity.is_empty() {
+                Vec::new()
+            } else {
+                read_sized(&mut stream).await?
+            };
 
-            let server_publ_key = rsa::RsaPublicKey::from_public_key_der(&buf)?;
+            if let Some(pinning) = config.pinning.as_ref() {
+                pinning.verify(&identity, &signature, &buf, pem)?;
+            }
 
             let mut fuso_stream = RSAEncryptor::new(stream, server_publ_key, priv_key);
 
+            let suite = config.negotiate(&mut fuso_stream).await?;
+
             let mut iv = [0u8; 16];
-            let mut key = [0u8; 16];
+            let mut key = [0u8; 32];
 
             iv.fill_with(rand::random);
             key.fill_with(rand::random);
 
+            log::debug!("use cipher suite {}", suite);
             log::trace!("iv: {:?}, key: {:?}", iv, key);
 
             fuso_stream.write_all(&iv).await?;
@@ -123,6 +158,7 @@ impl PenetrateRsaAndAesHandshake {
             Ok((
                 fuso_stream.into_boxed_stream(),
                 Some(DecorateProvider::wrap(PenetrateAesAndLz4Decorator {
+                    suite,
                     iv,
                     key,
                 })),
@@ -131,6 +167,28 @@ impl PenetrateRsaAndAesHandshake {
     }
 }
 
+/// 握手时对端发送的公钥与签名的最大长度
+const MAX_SIZED_LEN: usize = 64 * 1024;
+
+/// 读取带长度前缀的数据, 长度超过 MAX_SIZED_LEN 时返回错误
+async fn read_sized<S>(stream: &mut S) -> crate::Result<Vec<u8>>
+where
+    S: Stream + Unpin,
+{
+    let mut buf = [0u8; 4];
+    stream.read_exact(&mut buf).await?;
+    let len = u32::from_be_bytes(buf) as usize;
+
+    if len > MAX_SIZED_LEN {
+        return Err(Kind::Unexpected(format!("handshake data too large {}bytes", len)).into());
+    }
+
+    let mut buf = vec![0u8; len];
+    stream.read_exact(&mut buf).await?;
+
+    Ok(buf)
+}
+
 impl<S> Provider<S> for PenetrateRsaAndAesHandshake
 where
     S: Stream + Unpin + Send + 'static,
@@ -139,8 +197,18 @@ where
 
     fn call(&self, client: S) -> Self::Output {
         match self {
-            PenetrateRsaAndAesHandshake::Server => Self::server_handshake(client),
-            PenetrateRsaAndAesHandshake::Client => Self::client_handshake(client),
+            PenetrateRsaAndAesHandshake::Server => {
+                Self::server_handshake(client, Default::default())
+            }
+            PenetrateRsaAndAesHandshake::Client => {
+                Self::client_handshake(client, Default::default())
+            }
+            PenetrateRsaAndAesHandshake::ConfiguredServer(config) => {
+                Self::server_handshake(client, config.clone())
+            }
+            PenetrateRsaAndAesHandshake::ConfiguredClient(config) => {
+                Self::client_handshake(client, config.clone())
+            }
         }
     }
 }
@@ -151,12 +219,29 @@ where
 {
     type Output = BoxedFuture<FusoStream>;
     fn call(&self, stream: S) -> Self::Output {
-        let iv = self.iv.clone();
-        let key = self.key.clone();
+        let suite = self.suite;
+        let iv = self.iv;
+        let key = self.key;
         Box::pin(async move {
             let lz4 = Lz4Compress::new(stream);
-            let aes = AESEncryptor::new(lz4, iv, key);
-            Ok(aes.into_boxed_stream())
+            match suite {
+                CipherSuite::Aes128Cbc => {
+                    let mut legacy_key = [0u8; 16];
+                    legacy_key.copy_from_slice(&key[..16]);
+                    let aes = AESEncryptor::new(lz4, iv, legacy_key);
+                    Ok(aes.into_boxed_stream())
+                }
+                #[cfg(feature = "fuso-crypt-aead")]
+                suite => {
+                    let aead = crate::encryption::AEADEncryptor::new(lz4, suite, key);
+                    Ok(aead.into_boxed_stream())
+                }
+                #[cfg(not(feature = "fuso-crypt-aead"))]
+                suite => {
+                    let message = format!("not support {}", suite);
+                    Err(Kind::Encryption(crate::error::EncryptionErr::Cipher(message)).into())
+                }
+            }
         })
     }
 }
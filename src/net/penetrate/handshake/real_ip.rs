@@ -1,20 +1,179 @@
-use std::pin::Pin;
+use std::{
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+};
 
-use crate::{FusoStream, Provider, ext::AsyncWriteExt};
+use serde::{Deserialize, Serialize};
 
-type BoxedFuture<T> = Pin<Box<dyn std::future::Future<Output = crate::Result<T>> + Send + 'static>>;
+use crate::{ext::AsyncWriteExt, InnerAddr, Kind, Origin, Stream};
 
-pub struct ProxyProtocol;
+/// PROXY protocol v2 签名
+const SIGNATURE: &[u8] = b"\x0D\x0A\x0D\x0A\x00\x0D\x0A\x51\x55\x49\x54\x0A";
 
+/// 在转发前向服务写入PROXY protocol头, 使服务获取访问者的真实ip
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProxyProtocol {
+    V1,
+    V2,
+}
+
+impl ProxyProtocol {
+    pub fn encode(&self, origin: Option<&Origin>) -> Vec<u8> {
+        let addrs = origin.and_then(|origin| {
+            let source = socket_addr(origin.source().inner())?;
+            let destination = socket_addr(origin.destination().inner())?;
+            Some(same_family(source, destination))
+        });
+
+        match self {
+            ProxyProtocol::V1 => encode_v1(addrs),
+            ProxyProtocol::V2 => encode_v2(addrs),
+        }
+    }
+
+    pub async fn write<S>(&self, stream: &mut S, origin: Option<&Origin>) -> crate::Result<()>
+    where
+        S: Stream,
+    {
+        let header = self.encode(origin);
+        stream.write_all(&header).await?;
+        Ok(())
+    }
+}
+
+impl FromStr for ProxyProtocol {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "v1" => Ok(ProxyProtocol::V1),
+            "v2" => Ok(ProxyProtocol::V2),
+            _ => Err(Kind::Message(format!("unsupported proxy protocol {}", s)).into()),
+        }
+    }
+}
+
+fn socket_addr(addr: &InnerAddr) -> Option<SocketAddr> {
+    match addr {
+        InnerAddr::Socket(addr) => Some(*addr),
+        InnerAddr::Domain(_, _) => None,
+    }
+}
+
+/// 来源与目标的地址族不同时统一转为ipv6
+fn same_family(source: SocketAddr, destination: SocketAddr) -> (SocketAddr, SocketAddr) {
+    let to_v6 = |addr: SocketAddr| match addr.ip() {
+        IpAddr::V4(ip) => SocketAddr::new(IpAddr::V6(ip.to_ipv6_mapped()), addr.port()),
+        IpAddr::V6(_) => addr,
+    };
+
+    if source.is_ipv4() == destination.is_ipv4() {
+        (source, destination)
+    } else {
+        (to_v6(source), to_v6(destination))
+    }
+}
+
+fn encode_v1(addrs: Option<(SocketAddr, SocketAddr)>) -> Vec<u8> {
+    match addrs {
+        None => b"PROXY UNKNOWN\r\n".to_vec(),
+        Some((source, destination)) => format!(
+            "PROXY {} {} {} {} {}\r\n",
+            if source.is_ipv4() { "TCP4" } else { "TCP6" },
+            source.ip(),
+            destination.ip(),
+            source.port(),
+            destination.port()
+        )
+        .into_bytes(),
+    }
+}
+
+fn encode_v2(addrs: Option<(SocketAddr, SocketAddr)>) -> Vec<u8> {
+    let mut header = SIGNATURE.to_vec();
+
+    let (family, body) = match addrs {
+        // LOCAL, 服务应当使用连接本身的地址
+        None => {
+            header.extend_from_slice(&[0x20, 0x00, 0x00, 0x00]);
+            return header;
+        }
+        Some((source, destination)) => {
+            let mut body = Vec::with_capacity(36);
+
+            let family = match (source.ip(), destination.ip()) {
+                (IpAddr::V4(src), IpAddr::V4(dst)) => {
+                    body.extend_from_slice(&src.octets());
+                    body.extend_from_slice(&dst.octets());
+                    0x11
+                }
+                (src, dst) => {
+                    body.extend_from_slice(&to_octets(src));
+                    body.extend_from_slice(&to_octets(dst));
+                    0x21
+                }
+            };
+
+            body.extend_from_slice(&source.port().to_be_bytes());
+            body.extend_from_slice(&destination.port().to_be_bytes());
+
+            (family, body)
+        }
+    };
+
+    // PROXY, TCP over IPv4 / IPv6
+    header.push(0x21);
+    header.push(family);
+    header.extend_from_slice(&(body.len() as u16).to_be_bytes());
+    header.extend_from_slice(&body);
+    header
+}
+
+fn to_octets(ip: IpAddr) -> [u8; 16] {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped().octets(),
+        IpAddr::V6(ip) => ip.octets(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ProxyProtocol;
+    use crate::{Addr, Origin};
+    use std::net::SocketAddr;
+
+    fn origin(source: &str, destination: &str) -> Origin {
+        let source: SocketAddr = source.parse().unwrap();
+        let destination: SocketAddr = destination.parse().unwrap();
+        Origin::new(Addr::from(source), Addr::from(destination))
+    }
+
+    #[test]
+    fn test_proxy_protocol() {
+        let v4 = origin("192.168.1.2:5000", "10.0.0.1:80");
+
+        assert_eq!(
+            ProxyProtocol::V1.encode(Some(&v4)),
+            b"PROXY TCP4 192.168.1.2 10.0.0.1 5000 80\r\n"
+        );
+
+        assert_eq!(ProxyProtocol::V1.encode(None), b"PROXY UNKNOWN\r\n");
+
+        let header = ProxyProtocol::V2.encode(Some(&v4));
+        assert_eq!(&header[12..16], &[0x21, 0x11, 0x00, 0x0c]);
+        assert_eq!(
+            &header[16..],
+            &[192, 168, 1, 2, 10, 0, 0, 1, 0x13, 0x88, 0x00, 0x50]
+        );
 
-impl Provider<FusoStream> for ProxyProtocol {
-    type Output = BoxedFuture<FusoStream>;
+        let mixed = origin("[::1]:5000", "10.0.0.1:80");
+        assert_eq!(
+            ProxyProtocol::V1.encode(Some(&mixed)),
+            b"PROXY TCP6 ::1 ::ffff:10.0.0.1 5000 80\r\n"
+        );
 
-    fn call(&self, mut client: FusoStream) -> Self::Output {
-        Box::pin(async move {
-            // .....
-            client.write_all(b"\x0D\x0A\x0D\x0A\x00\x0D\x0A\x51\x55\x49\x54\x0A\x02").await?;
-            Ok(client)
-        })
+        let header = ProxyProtocol::V2.encode(Some(&mixed));
+        assert_eq!(&header[12..16], &[0x21, 0x21, 0x00, 0x24]);
+        assert_eq!(header.len(), 16 + 36);
     }
 }
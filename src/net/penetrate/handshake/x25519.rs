@@ -0,0 +1,308 @@
+use std::sync::Arc;
+
+use hkdf::Hkdf;
+use sha2::Sha256;
+use x25519_dalek::{EphemeralSecret, PublicKey};
+
+use crate::{
+    compress::Lz4Compress,
+    encryption::{AEADEncryptor, CipherSuite},
+    ext::{AsyncReadExt, AsyncWriteExt},
+    DecorateProvider, FusoStream, Provider, Stream, ToBoxStream,
+};
+
+use super::{
+    read_sized, BoxedFuture, ClientHandshakeConfig, PenetrateAesAndLz4Decorator,
+    ServerHandshakeConfig,
+};
+
+/// 控制通道固定使用的加密套件
+const CONTROL_CIPHER: CipherSuite = CipherSuite::ChaCha20Poly1305;
+
+pub enum PenetrateX25519Handshake {
+    Server,
+    Client,
+    /// 指定身份密钥以及允许的加密套件
+    ConfiguredServer(Arc<ServerHandshakeConfig>),
+    /// 指定服务端身份校验以及加密套件
+    ConfiguredClient(Arc<ClientHandshakeConfig>),
+}
+
+/// 从共享密钥中派生控制通道与数据通道的密钥
+struct SessionKeys {
+    control: [u8; 32],
+    data: [u8; 32],
+    iv: [u8; 16],
+}
+
+impl SessionKeys {
+    fn derive(shared: &[u8], client_key: &[u8], server_key: &[u8]) -> crate::Result<Self> {
+        let mut salt = Vec::with_capacity(client_key.len() + server_key.len());
+        salt.extend_from_slice(client_key);
+        salt.extend_from_slice(server_key);
+
+        let hkdf = Hkdf::<Sha256>::new(Some(&salt), shared);
+
+        let mut keys = Self {
+            control: [0u8; 32],
+            data: [0u8; 32],
+            iv: [0u8; 16],
+        };
+
+        let invalid_length = |_| crate::Error::from(String::from("hkdf invalid length"));
+
+        hkdf.expand(b"fuso-control", &mut keys.control)
+            .map_err(invalid_length)?;
+        hkdf.expand(b"fuso-data", &mut keys.data)
+            .map_err(invalid_length)?;
+        hkdf.expand(b"fuso-data-iv", &mut keys.iv)
+            .map_err(invalid_length)?;
+
+        Ok(keys)
+    }
+}
+
+impl PenetrateX25519Handshake {
+    pub fn server_handshake<S>(
+        client: S,
+        config: Arc<ServerHandshakeConfig>,
+    ) -> BoxedFuture<(FusoStream, Option<DecorateProvider<FusoStream>>)>
+    where
+        S: Stream + Unpin + Send + 'static,
+    {
+        Box::pin(async move {
+            let mut client = Lz4Compress::new(client);
+
+            let mut client_key = [0u8; 32];
+            client.read_exact(&mut client_key).await?;
+
+            let secret = EphemeralSecret::random_from_rng(rand::thread_rng());
+            let server_key = PublicKey::from(&secret);
+
+            client.write_all(server_key.as_bytes()).await?;
+
+            // 未配置身份密钥时只发送长度0
+            match config.identity.as_ref() {
+                None => client.write_all(&0u32.to_be_bytes()).await?,
+                Some(identity) => {
+                    let identity_key = identity.public_key_der()?;
+                    let signature = identity.sign(server_key.as_bytes(), &client_key)?;
+
+                    client
+                        .write_all(&(identity_key.len() as u32).to_be_bytes())
+                        .await?;
+                    client.write_all(&identity_key).await?;
+                    client
+                        .write_all(&(signature.len() as u32).to_be_bytes())
+                        .await?;
+                    client.write_all(&signature).await?;
+                }
+            }
+
+            let shared = secret.diffie_hellman(&PublicKey::from(client_key));
+            let keys = SessionKeys::derive(shared.as_bytes(), &client_key, server_key.as_bytes())?;
+
+            let mut fuso_stream = AEADEncryptor::new(client, CONTROL_CIPHER, keys.control);
+
+            let suite = config.negotiate(&mut fuso_stream).await?;
+
+            log::debug!("use cipher suite {}", suite);
+
+            Ok((
+                fuso_stream.into_boxed_stream(),
+                Some(DecorateProvider::wrap(PenetrateAesAndLz4Decorator {
+                    suite,
+                    iv: keys.iv,
+                    key: keys.data,
+                })),
+            ))
+        })
+    }
+
+    pub fn client_handshake<S>(
+        stream: S,
+        config: Arc<ClientHandshakeConfig>,
+    ) -> BoxedFuture<(FusoStream, Option<DecorateProvider<FusoStream>>)>
+    where
+        S: Stream + Unpin + Send + 'static,
+    {
+        Box::pin(async move {
+            let mut stream = Lz4Compress::new(stream);
+
+            let secret = EphemeralSecret::random_from_rng(rand::thread_rng());
+            let client_key = PublicKey::from(&secret);
+
+            stream.write_all(client_key.as_bytes()).await?;
+
+            let mut server_key = [0u8; 32];
+            stream.read_exact(&mut server_key).await?;
+
+            let identity = read_sized(&mut stream).await?;
+
+            let signature = if identity.is_empty() {
+                Vec::new()
+            } else {
+                read_sized(&mut stream).await?
+            };
+
+            if let Some(pinning) = config.pinning.as_ref() {
+                pinning.verify(&identity, &signature, &server_key, client_key.as_bytes())?;
+            }
+
+            let shared = secret.diffie_hellman(&PublicKey::from(server_key));
+            let keys = SessionKeys::derive(shared.as_bytes(), client_key.as_bytes(), &server_key)?;
+
+            let mut fuso_stream = AEADEncryptor::new(stream, CONTROL_CIPHER, keys.control);
+
+            let suite = config.negotiate(&mut fuso_stream).await?;
+
+            log::debug!("use cipher suite {}", suite);
+
+            Ok((
+                fuso_stream.into_boxed_stream(),
+                Some(DecorateProvider::wrap(PenetrateAesAndLz4Decorator {
+                    suite,
+                    iv: keys.iv,
+                    key: keys.data,
+                })),
+            ))
+        })
+    }
+}
+
+impl<S> Provider<S> for PenetrateX25519Handshake
+where
+    S: Stream + Unpin + Send + 'static,
+{
+    type Output = BoxedFuture<(FusoStream, Option<DecorateProvider<FusoStream>>)>;
+
+    fn call(&self, client: S) -> Self::Output {
+        match self {
+            PenetrateX25519Handshake::Server => Self::server_handshake(client, Default::default()),
+            PenetrateX25519Handshake::Client => Self::client_handshake(client, Default::default()),
+            PenetrateX25519Handshake::ConfiguredServer(config) => {
+                Self::server_handshake(client, config.clone())
+            }
+            PenetrateX25519Handshake::ConfiguredClient(config) => {
+                Self::client_handshake(client, config.clone())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "fuso-rt-tokio")]
+mod tests {
+    use std::sync::Arc;
+
+    use rsa::RsaPrivateKey;
+    use tokio::net::{TcpListener, TcpStream};
+
+    use crate::{
+        ext::{AsyncReadExt, AsyncWriteExt},
+        penetrate::handshake::{
+            ClientHandshakeConfig, ServerHandshakeConfig, ServerIdentity, ServerPinning,
+        },
+        FusoStream, Provider, Stream, ToBoxStream,
+    };
+
+    use super::PenetrateX25519Handshake;
+
+    fn identity() -> ServerIdentity {
+        ServerIdentity::new(RsaPrivateKey::new(&mut rand::thread_rng(), 1024).unwrap())
+    }
+
+    async fn pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (client, server) = tokio::join!(TcpStream::connect(addr), listener.accept());
+        (client.unwrap(), server.unwrap().0)
+    }
+
+    async fn exchange<A, B>(a: &mut A, b: &mut B, data: &[u8])
+    where
+        A: Stream + Unpin,
+        B: Stream + Unpin,
+    {
+        a.write_all(data).await.unwrap();
+        a.flush().await.unwrap();
+
+        let mut buf = vec![0u8; data.len()];
+        b.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf, data);
+    }
+
+    fn configs(
+        identity: ServerIdentity,
+        pinned: String,
+    ) -> (Arc<ServerHandshakeConfig>, Arc<ClientHandshakeConfig>) {
+        let server = ServerHandshakeConfig::default().identity(Some(identity));
+        let pinning = ServerPinning::new("127.0.0.1").fingerprint(Some(pinned));
+        let client = ClientHandshakeConfig::default().pinning(Some(pinning));
+        (Arc::new(server), Arc::new(client))
+    }
+
+    #[test]
+    fn test_x25519_loopback() {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let identity = identity();
+                let pinned = identity.fingerprint().unwrap();
+                let (server_config, client_config) = configs(identity, pinned);
+
+                let (client, server) = pair().await;
+
+                let (client, server) = tokio::join!(
+                    PenetrateX25519Handshake::client_handshake(client, client_config),
+                    PenetrateX25519Handshake::server_handshake(server, server_config)
+                );
+
+                let (mut client_ctrl, client_decorator) = client.unwrap();
+                let (mut server_ctrl, server_decorator) = server.unwrap();
+
+                exchange(&mut client_ctrl, &mut server_ctrl, b"ping").await;
+                exchange(&mut server_ctrl, &mut client_ctrl, b"pong").await;
+
+                let (client, server) = pair().await;
+
+                let (mut client_data, mut server_data): (FusoStream, FusoStream) = (
+                    client_decorator
+                        .unwrap()
+                        .call(client.into_boxed_stream())
+                        .await
+                        .unwrap(),
+                    server_decorator
+                        .unwrap()
+                        .call(server.into_boxed_stream())
+                        .await
+                        .unwrap(),
+                );
+
+                exchange(&mut client_data, &mut server_data, b"hello server").await;
+                exchange(&mut server_data, &mut client_data, b"hello client").await;
+            });
+    }
+
+    #[test]
+    fn test_x25519_pinned_mismatch() {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let pinned = identity().fingerprint().unwrap();
+                let (server_config, client_config) = configs(identity(), pinned);
+
+                let (client, server) = pair().await;
+
+                let (client, _) = tokio::join!(
+                    PenetrateX25519Handshake::client_handshake(client, client_config),
+                    PenetrateX25519Handshake::server_handshake(server, server_config)
+                );
+
+                match client {
+                    Ok(_) => panic!("the handshake should fail"),
+                    Err(e) => assert!(e.to_string().contains("fingerprint mismatch")),
+                }
+            });
+    }
+}
@@ -6,7 +6,7 @@ use crate::{
         server::{Peer, Visitor},
         Selector,
     },
-    NetSocket, Provider, Socket, Stream,
+    Provider, Stream,
 };
 
 type BoxedFuture<T> = Pin<Box<dyn std::future::Future<Output = crate::Result<T>> + Send + 'static>>;
@@ -24,16 +24,11 @@ where
         (stream, config): (Fallback<S>, Arc<super::super::server::Config>),
     ) -> Self::Output {
         Box::pin(async move {
-            let mut socket = Socket::default();
+            let socket = super::route_socket(&stream, &config)?;
 
-            if config.real_ip {
-                let addr = stream.peer_addr()?;
-                socket.set_origin(addr.first_addr());
-            }
-            
             Ok(Selector::Checked(Peer::Route(
                 Visitor::Route(stream),
-                Socket::default(),
+                socket,
             )))
         })
     }
@@ -2,19 +2,48 @@ mod direct;
 
 mod socks;
 
+mod vhost;
+
+mod sni;
+
 use std::{pin::Pin, sync::Arc};
 
 use self::socks::PenetrateSocksBuilder;
 
+pub use sni::SniMock;
 pub use socks::SocksUdpForwardMock;
+pub use vhost::{VirtualHost, VirtualHostMock, VirtualHosts};
 
 use super::{server::Peer, PenetrateSelectorBuilder};
-use crate::{guard::Fallback, Accepter, Executor, Provider, Socket, Stream, WrappedProvider};
+use crate::{
+    guard::Fallback, Accepter, Executor, NetSocket, Origin, Provider, Socket, Stream,
+    WrappedProvider,
+};
 
 type BoxedFuture<T> = Pin<Box<dyn std::future::Future<Output = crate::Result<T>> + Send + 'static>>;
 
 pub type Mock<S> = WrappedProvider<(Fallback<S>, Arc<super::server::Config>), Peer<Fallback<S>>>;
 
+/// 转发到客户端的默认目标, 需要传递真实ip时记录访问者的地址
+fn route_socket<S>(stream: &Fallback<S>, config: &super::server::Config) -> crate::Result<Socket>
+where
+    S: Stream,
+{
+    let mut socket = Socket::default();
+
+    if config.real_ip {
+        let source = stream.peer_addr()?.first_addr();
+        let destination = stream.local_addr()?.first_addr();
+        socket.set_origin(
+            source
+                .zip(destination)
+                .map(|(source, destination)| Origin::new(source, destination)),
+        );
+    }
+
+    Ok(socket)
+}
+
 impl<E, P, A, S, O> PenetrateSelectorBuilder<E, P, S, O>
 where
     E: Executor + 'static,
@@ -0,0 +1,311 @@
+use std::{future::Future, pin::Pin, sync::Arc, task::Poll, time::Duration};
+
+use crate::{
+    ext::AsyncReadExt,
+    guard::Fallback,
+    penetrate::{
+        server::{Peer, Visitor},
+        PenetrateSelectorBuilder, Selector,
+    },
+    time, Provider, Stream, WrappedProvider,
+};
+
+use super::VirtualHosts;
+
+type BoxedFuture<T> = Pin<Box<dyn std::future::Future<Output = crate::Result<T>> + Send + 'static>>;
+
+pub(crate) type Deadline = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/// 嗅探时等待访问者发送数据的最长时间, 超时后按照无法识别处理
+const SNIFF_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// tls握手记录
+const HANDSHAKE_RECORD: u8 = 0x16;
+
+/// ClientHello 的最大长度
+const MAXIMUM_RECORD_SIZE: usize = 16384 + 5;
+
+/// 按照ClientHello中的SNI转发, 不解密任何数据
+pub struct SniMock<S>(VirtualHosts<S>);
+
+pub(crate) fn deadline() -> Deadline {
+    Box::pin(time::sleep(SNIFF_TIMEOUT))
+}
+
+/// 在deadline之前读取数据, 超时返回None, 例如服务端先发送数据的协议
+pub(crate) async fn read_before<S>(
+    visitor: &mut Fallback<S>,
+    buf: &mut [u8],
+    deadline: &mut Deadline,
+) -> crate::Result<Option<usize>>
+where
+    S: Stream + Send + Unpin,
+{
+    let mut read = visitor.read(buf);
+
+    std::future::poll_fn(|cx| match Pin::new(&mut read).poll(cx) {
+        Poll::Ready(r) => Poll::Ready(r.map(Some)),
+        Poll::Pending => deadline.as_mut().poll(cx).map(|_| Ok(None)),
+    })
+    .await
+}
+
+/// 是否为tls握手, 读取的数据会保留在Fallback中
+pub(crate) async fn is_tls<S>(visitor: &mut Fallback<S>) -> crate::Result<bool>
+where
+    S: Stream + Send + Unpin,
+{
+    let mut buf = [0u8; 1];
+
+    visitor.mark().await?;
+    let n = read_before(visitor, &mut buf, &mut deadline()).await?;
+    visitor.backward().await?;
+
+    Ok(n == Some(1) && buf[0] == HANDSHAKE_RECORD)
+}
+
+/// 读取ClientHello中的SNI, 读取的数据会保留在Fallback中
+pub(crate) async fn sniff_sni<S>(visitor: &mut Fallback<S>) -> crate::Result<Option<String>>
+where
+    S: Stream + Send + Unpin,
+{
+    let mut record = Vec::new();
+    let mut buf = [0u8; 1024];
+    let mut deadline = deadline();
+
+    visitor.mark().await?;
+
+    let server_name = loop {
+        let n = match read_before(visitor, &mut buf, &mut deadline).await? {
+            None | Some(0) => break None,
+            Some(n) => n,
+        };
+
+        record.extend_from_slice(&buf[..n]);
+
+        if record[0] != HANDSHAKE_RECORD {
+            break None;
+        }
+
+        if record.len() < 5 {
+            continue;
+        }
+
+        let len = 5 + u16::from_be_bytes([record[3], record[4]]) as usize;
+
+        if len > MAXIMUM_RECORD_SIZE {
+            break None;
+        }
+
+        if record.len() >= len {
+            break parse_sni(&record[5..len]);
+        }
+    };
+
+    visitor.backward().await?;
+
+    Ok(server_name)
+}
+
+/// 依次读取ClientHello中的字段
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Some(head)
+    }
+
+    fn u8(&mut self) -> Option<usize> {
+        self.take(1).map(|b| b[0] as usize)
+    }
+
+    fn u16(&mut self) -> Option<usize> {
+        self.take(2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]) as usize)
+    }
+
+    fn u24(&mut self) -> Option<usize> {
+        self.take(3)
+            .map(|b| u32::from_be_bytes([0, b[0], b[1], b[2]]) as usize)
+    }
+
+    fn vec8(&mut self) -> Option<Reader<'a>> {
+        let n = self.u8()?;
+        self.take(n).map(Reader)
+    }
+
+    fn vec16(&mut self) -> Option<Reader<'a>> {
+        let n = self.u16()?;
+        self.take(n).map(Reader)
+    }
+}
+
+/// 解析ClientHello中的server_name扩展, 转为小写
+fn parse_sni(handshake: &[u8]) -> Option<String> {
+    let mut reader = Reader(handshake);
+
+    // client_hello
+    if reader.u8()? != 0x01 {
+        return None;
+    }
+
+    let len = reader.u24()?;
+    let mut hello = Reader(reader.take(len)?);
+
+    // legacy_version, random
+    hello.take(2 + 32)?;
+    // session_id, cipher_suites, compression_methods
+    hello.vec8()?;
+    hello.vec16()?;
+    hello.vec8()?;
+
+    let mut extensions = hello.vec16()?;
+
+    while !extensions.0.is_empty() {
+        let kind = extensions.u16()?;
+        let mut data = extensions.vec16()?;
+
+        if kind != 0x0000 {
+            continue;
+        }
+
+        let mut names = data.vec16()?;
+
+        while !names.0.is_empty() {
+            let name_type = names.u8()?;
+            let name = names.vec16()?;
+
+            if name_type == 0x00 {
+                return std::str::from_utf8(name.0)
+                    .ok()
+                    .map(|name| name.to_ascii_lowercase());
+            }
+        }
+    }
+
+    None
+}
+
+impl<S> Provider<(Fallback<S>, Arc<super::super::server::Config>)> for SniMock<S>
+where
+    S: Stream + Send + 'static,
+{
+    type Output = BoxedFuture<Selector<S>>;
+
+    fn call(
+        &self,
+        (stream, config): (Fallback<S>, Arc<super::super::server::Config>),
+    ) -> Self::Output {
+        let hosts = self.0.clone();
+
+        Box::pin(async move {
+            let mut stream = stream;
+
+            let domain = match config.domain.as_ref() {
+                Some(domain) => domain,
+                None => return Ok(Selector::Unselected(stream)),
+            };
+
+            let server_name = match sniff_sni(&mut stream).await? {
+                Some(server_name) => server_name,
+                None => return Ok(Selector::Unselected(stream)),
+            };
+
+            if domain.eq_ignore_ascii_case(&server_name) {
+                let socket = super::route_socket(&stream, &config)?;
+                return Ok(Selector::Checked(Peer::Route(
+                    Visitor::Route(stream),
+                    socket,
+                )));
+            }
+
+            // 其他客户端绑定的域名, 交由对应的客户端处理
+            stream.backward().await?;
+
+            match hosts.transfer(&server_name, stream).await {
+                Ok(()) => Ok(Selector::Checked(Peer::Transferred)),
+                Err(stream) => Ok(Selector::Unselected(stream)),
+            }
+        })
+    }
+}
+
+impl<E, P, S, O> PenetrateSelectorBuilder<E, P, S, O>
+where
+    S: Stream + Send + Sync + 'static,
+{
+    /// 客户端可以通过tls的SNI共享同一个端口
+    pub fn using_sni(mut self, hosts: VirtualHosts<S>) -> Self {
+        self.adapters
+            .insert(0, WrappedProvider::wrap(SniMock(hosts.clone())));
+        self.penetrate_builder.vhosts = Some(hosts);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_sni;
+
+    #[cfg(feature = "fuso-rt-tokio")]
+    #[test]
+    fn test_sniff_timeout() {
+        use crate::guard::Fallback;
+        use tokio::net::{TcpListener, TcpStream};
+
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+                let _visitor = TcpStream::connect(listener.local_addr().unwrap())
+                    .await
+                    .unwrap();
+                let (stream, _) = listener.accept().await.unwrap();
+
+                let mut visitor = Fallback::new(stream, false);
+                assert!(!super::is_tls(&mut visitor).await.unwrap());
+                assert_eq!(super::sniff_sni(&mut visitor).await.unwrap(), None);
+            });
+    }
+
+    fn client_hello(server_name: &[u8]) -> Vec<u8> {
+        let mut sni = Vec::new();
+        sni.extend_from_slice(&((server_name.len() + 3) as u16).to_be_bytes());
+        sni.push(0x00);
+        sni.extend_from_slice(&(server_name.len() as u16).to_be_bytes());
+        sni.extend_from_slice(server_name);
+
+        let mut extensions = Vec::new();
+        // supported_versions
+        extensions.extend_from_slice(&[0x00, 0x2b, 0x00, 0x03, 0x02, 0x03, 0x04]);
+        extensions.extend_from_slice(&[0x00, 0x00]);
+        extensions.extend_from_slice(&(sni.len() as u16).to_be_bytes());
+        extensions.extend_from_slice(&sni);
+
+        let mut hello = vec![0x03, 0x03];
+        hello.extend_from_slice(&[0u8; 32]);
+        hello.extend_from_slice(&[0x00, 0x00, 0x02, 0x13, 0x01, 0x01, 0x00]);
+        hello.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        hello.extend_from_slice(&extensions);
+
+        let mut handshake = vec![0x01];
+        handshake.extend_from_slice(&(hello.len() as u32).to_be_bytes()[1..]);
+        handshake.extend_from_slice(&hello);
+        handshake
+    }
+
+    #[test]
+    fn test_parse_sni() {
+        let hello = client_hello(b"App.Example.com");
+        assert_eq!(parse_sni(&hello).as_deref(), Some("app.example.com"));
+
+        assert_eq!(parse_sni(&hello[..hello.len() - 4]), None);
+        assert_eq!(parse_sni(b"GET / HTTP/1.1\r\n"), None);
+    }
+}
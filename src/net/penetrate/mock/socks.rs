@@ -2,7 +2,7 @@ use std::{net::SocketAddr, pin::Pin, sync::Arc};
 
 use crate::{
     ext::AsyncReadExt,
-    guard::Fallback,
+    guard::{Fallback, Limited},
     io,
     penetrate::{
         server::{Peer, Visitor},
@@ -11,7 +11,8 @@ use crate::{
     protocol::{make_packet, AsyncRecvPacket, AsyncSendPacket, Poto, ToBytes, TryToPoto},
     select::Select,
     socks::{self, S5Authenticate, Socks},
-    Addr, Kind, Provider, Socket, SocketKind, Stream, UdpReceiverExt, UdpSocket, WrappedProvider,
+    Addr, Kind, NetSocket, Provider, Socket, SocketKind, Stream, UdpReceiverExt, UdpSocket,
+    WrappedProvider,
 };
 
 type BoxedFuture<T> = Pin<Box<dyn std::future::Future<Output = crate::Result<T>> + Send + 'static>>;
@@ -176,15 +177,14 @@ where
     }
 }
 
-impl<S, U> Provider<Fallback<S>> for SocksUdpForward<S, U>
+impl<S, U> Provider<io::Watched<Limited<Fallback<S>>>> for SocksUdpForward<S, U>
 where
     S: Stream + Send + 'static,
     U: UdpSocket + Unpin + Send + Sync + 'static,
 {
     type Output = BoxedFuture<()>;
 
-    fn call(&self, s2: Fallback<S>) -> Self::Output {
-        let s2 = s2.into_inner();
+    fn call(&self, s2: io::Watched<Limited<Fallback<S>>>) -> Self::Output {
         let s1 = match self.stream.lock() {
             Err(_) => return Box::pin(async move { Err(Kind::Once.into()) }),
             Ok(mut lock) => match lock.take() {
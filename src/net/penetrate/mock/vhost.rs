@@ -0,0 +1,322 @@
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use super::sni;
+use crate::{
+    ext::AsyncWriteExt,
+    guard::Fallback,
+    penetrate::{
+        server::{Peer, Visitor},
+        PenetrateSelectorBuilder, Selector,
+    },
+    time, Accepter, AccepterExt, Executor, Kind, NetSocket, Provider, Socket, Stream,
+    WrappedProvider,
+};
+
+type BoxedFuture<T> = Pin<Box<dyn std::future::Future<Output = crate::Result<T>> + Send + 'static>>;
+
+/// 请求头的最大长度
+const MAXIMUM_HEADER_SIZE: usize = 8192;
+
+/// 读取请求头的最大等待时间
+const MAXIMUM_WAIT: Duration = Duration::from_secs(10);
+
+/// 域名与客户端的对应关系, 所有客户端共享同一个http端口
+pub struct VirtualHosts<S>(Arc<Mutex<HashMap<String, Registered<S>>>>);
+
+type Registered<S> = (u64, async_channel::Sender<Fallback<S>>);
+
+/// 客户端注册的域名, 释放时自动注销
+pub struct VirtualHost<S> {
+    id: u64,
+    domain: String,
+    hosts: VirtualHosts<S>,
+    receiver: async_channel::Receiver<Fallback<S>>,
+}
+
+/// 请求的域名与客户端一致时直接转发
+pub struct VirtualHostMock;
+
+impl<S> Clone for VirtualHosts<S> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<S> Default for VirtualHosts<S> {
+    fn default() -> Self {
+        Self(Default::default())
+    }
+}
+
+impl<S> VirtualHosts<S>
+where
+    S: Stream + Send + 'static,
+{
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn register(&self, domain: &str) -> crate::Result<VirtualHost<S>> {
+        let domain = domain.to_ascii_lowercase();
+        let mut hosts = self.0.lock()?;
+
+        if hosts.contains_key(&domain) {
+            return Err(Kind::Message(format!("the domain {} is already in use", domain)).into());
+        }
+
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = async_channel::unbounded();
+
+        hosts.insert(domain.clone(), (id, sender));
+
+        Ok(VirtualHost {
+            id,
+            domain,
+            receiver,
+            hosts: self.clone(),
+        })
+    }
+
+    fn lookup(&self, domain: &str) -> Option<async_channel::Sender<Fallback<S>>> {
+        let hosts = self.0.lock().ok()?;
+        hosts.get(domain).map(|(_, sender)| sender.clone())
+    }
+
+    /// 将访问者交给绑定该域名的客户端, 没有对应的客户端时原样返回
+    pub(crate) async fn transfer(
+        &self,
+        domain: &str,
+        visitor: Fallback<S>,
+    ) -> Result<(), Fallback<S>> {
+        match self.lookup(domain) {
+            None => Err(visitor),
+            Some(sender) => sender.send(visitor).await.map_err(|e| e.into_inner()),
+        }
+    }
+
+    /// 在共享端口上接收访问者, 按照请求头中的Host或tls的SNI转发给对应的客户端
+    pub fn serve<E, P, A>(&self, executor: E, provider: P, listen: Socket) -> BoxedFuture<()>
+    where
+        E: Executor + Send + 'static,
+        P: Provider<Socket, Output = BoxedFuture<A>> + Send + 'static,
+        A: Accepter<Stream = S> + Unpin + Send + 'static,
+    {
+        let hosts = self.clone();
+
+        Box::pin(async move {
+            let mut accepter = provider.call(listen).await.map_err(|e| {
+                log::error!("failed to bind the http port {}", e);
+                e
+            })?;
+
+            log::info!("the http listens on {}", accepter.local_addr()?);
+
+            loop {
+                let visitor = accepter.accept().await?;
+                let hosts = hosts.clone();
+
+                executor.spawn(async move {
+                    if let Err(e) = hosts.dispatch(visitor).await {
+                        log::debug!("failed to dispatch http visitor {}", e);
+                    }
+                });
+            }
+        })
+    }
+
+    async fn dispatch(&self, visitor: S) -> crate::Result<()> {
+        let (visitor, host, tls) = time::wait_for(MAXIMUM_WAIT, async move {
+            let mut visitor = Fallback::new(visitor, false);
+
+            let tls = sni::is_tls(&mut visitor).await?;
+
+            let host = if tls {
+                sni::sniff_sni(&mut visitor).await?
+            } else {
+                sniff_host(&mut visitor).await?
+            };
+
+            Ok::<_, crate::Error>((visitor, host, tls))
+        })
+        .await??;
+
+        let mut visitor = match host.as_deref() {
+            None => visitor,
+            Some(host) => match self.transfer(host, visitor).await {
+                Ok(()) => return Ok(()),
+                Err(visitor) => visitor,
+            },
+        };
+
+        log::debug!("unknown host {:?} from {}", host, visitor.peer_addr()?);
+
+        if !tls {
+            let _ = visitor
+                .write_all(
+                    b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                )
+                .await;
+        }
+
+        visitor.close().await
+    }
+}
+
+impl<S> VirtualHost<S> {
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    pub(crate) fn receiver(&self) -> async_channel::Receiver<Fallback<S>> {
+        self.receiver.clone()
+    }
+}
+
+impl<S> Drop for VirtualHost<S> {
+    fn drop(&mut self) {
+        if let Ok(mut hosts) = self.hosts.0.lock() {
+            // 域名可能已经被新的连接注册
+            if hosts
+                .get(&self.domain)
+                .is_some_and(|(id, _)| *id == self.id)
+            {
+                hosts.remove(&self.domain);
+            }
+        }
+    }
+}
+
+/// 读取请求头中的Host, 读取的数据会保留在Fallback中
+async fn sniff_host<S>(visitor: &mut Fallback<S>) -> crate::Result<Option<String>>
+where
+    S: Stream + Send + Unpin,
+{
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    let mut deadline = sni::deadline();
+
+    visitor.mark().await?;
+
+    let host = loop {
+        let n = match sni::read_before(visitor, &mut buf, &mut deadline).await? {
+            None | Some(0) => break None,
+            Some(n) => n,
+        };
+
+        head.extend_from_slice(&buf[..n]);
+
+        if let Some(end) = find(&head, b"\r\n\r\n") {
+            break parse_host(&head[..end]);
+        }
+
+        if head.len() >= MAXIMUM_HEADER_SIZE {
+            break None;
+        }
+    };
+
+    visitor.backward().await?;
+
+    Ok(host)
+}
+
+fn find(buf: &[u8], needle: &[u8]) -> Option<usize> {
+    buf.windows(needle.len()).position(|w| w == needle)
+}
+
+/// 解析请求头中的Host, 去除端口并转为小写
+fn parse_host(head: &[u8]) -> Option<String> {
+    let head = std::str::from_utf8(head).ok()?;
+
+    head.split("\r\n").skip(1).find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+
+        if !name.trim().eq_ignore_ascii_case("host") {
+            return None;
+        }
+
+        let value = value.trim();
+
+        let host = match value.strip_prefix('[') {
+            Some(ipv6) => ipv6.split(']').next()?,
+            None => value.split(':').next()?,
+        };
+
+        Some(host.to_ascii_lowercase())
+    })
+}
+
+impl<S> Provider<(Fallback<S>, Arc<super::super::server::Config>)> for VirtualHostMock
+where
+    S: Stream + Send + 'static,
+{
+    type Output = BoxedFuture<Selector<S>>;
+
+    fn call(
+        &self,
+        (stream, config): (Fallback<S>, Arc<super::super::server::Config>),
+    ) -> Self::Output {
+        Box::pin(async move {
+            let mut stream = stream;
+
+            let domain = match config.domain.as_ref() {
+                Some(domain) => domain,
+                None => return Ok(Selector::Unselected(stream)),
+            };
+
+            if sni::is_tls(&mut stream).await? {
+                return Ok(Selector::Unselected(stream));
+            }
+
+            match sniff_host(&mut stream).await? {
+                Some(host) if host.eq_ignore_ascii_case(domain) => {
+                    let socket = super::route_socket(&stream, &config)?;
+                    Ok(Selector::Checked(Peer::Route(
+                        Visitor::Route(stream),
+                        socket,
+                    )))
+                }
+                _ => Ok(Selector::Unselected(stream)),
+            }
+        })
+    }
+}
+
+impl<E, P, S, O> PenetrateSelectorBuilder<E, P, S, O>
+where
+    S: Stream + Send + Sync + 'static,
+{
+    /// 客户端可以通过域名共享同一个http端口
+    pub fn using_vhost(mut self, hosts: VirtualHosts<S>) -> Self {
+        self.adapters
+            .insert(0, WrappedProvider::wrap(VirtualHostMock));
+        self.penetrate_builder.vhosts = Some(hosts);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_host;
+
+    #[test]
+    fn test_parse_host() {
+        let head = b"GET / HTTP/1.1\r\nUser-Agent: curl\r\nHOST: App.Example.com:8080";
+        assert_eq!(parse_host(head).as_deref(), Some("app.example.com"));
+
+        let head = b"GET / HTTP/1.1\r\nHost: [::1]:80";
+        assert_eq!(parse_host(head).as_deref(), Some("::1"));
+
+        let head = b"GET / HTTP/1.1\r\nAccept: */*";
+        assert_eq!(parse_host(head), None);
+    }
+}
@@ -1,9 +1,14 @@
 mod accepter;
+mod auth;
 mod selector;
 mod builder;
 mod handshake;
 mod observer;
 mod bridge;
+mod settings;
+mod ports;
+mod access;
+mod balance;
 
 pub use handshake::*;
 pub use observer::*;
@@ -17,3 +22,7 @@ pub mod server;
 
 pub use selector::*;
 pub use builder::*;
+pub use settings::*;
+pub use ports::*;
+pub use access::*;
+pub use balance::*;
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use crate::{Address, Error};
 
@@ -58,6 +58,40 @@ pub trait PenetrateObserver {
     {
         log::debug!("on_pen_error {} {}", client, error);
     }
+
+    /// 访问者被黑白名单拒绝
+    fn on_pen_deny(&self, client: &Address, visit: &Address, _: &server::Config, reason: &str)
+    where
+        Self: Sized,
+    {
+        log::debug!(
+            "on_pen_deny client: {}, visit: {}, reason: {}",
+            client,
+            visit,
+            reason
+        );
+    }
+
+    /// 客户端超过数量限制被拒绝
+    fn on_pen_reject(&self, client: &Address, _: &server::Config, reason: &str)
+    where
+        Self: Sized,
+    {
+        log::debug!("on_pen_reject client: {}, reason: {}", client, reason);
+    }
+
+    /// 访问者空闲超时被关闭
+    fn on_pen_idle(&self, client: &Address, visit: &Address, _: &server::Config, timeout: Duration)
+    where
+        Self: Sized,
+    {
+        log::debug!(
+            "on_pen_idle client: {}, visit: {}, timeout: {:?}",
+            client,
+            visit,
+            timeout
+        );
+    }
 }
 
 impl PenetrateObserver for () {}
@@ -103,6 +137,32 @@ where
     {
         (**self).on_pen_stop(client, visit, server, config)
     }
+
+    fn on_pen_deny(&self, client: &Address, visit: &Address, config: &server::Config, reason: &str)
+    where
+        Self: Sized,
+    {
+        (**self).on_pen_deny(client, visit, config, reason)
+    }
+
+    fn on_pen_reject(&self, client: &Address, config: &server::Config, reason: &str)
+    where
+        Self: Sized,
+    {
+        (**self).on_pen_reject(client, config, reason)
+    }
+
+    fn on_pen_idle(
+        &self,
+        client: &Address,
+        visit: &Address,
+        config: &server::Config,
+        timeout: Duration,
+    ) where
+        Self: Sized,
+    {
+        (**self).on_pen_idle(client, visit, config, timeout)
+    }
 }
 
 impl<T> PenetrateObserver for Option<T>
@@ -149,4 +209,36 @@ where
         self.as_ref()
             .map(|obs| obs.on_pen_stop(client, visit, server, config));
     }
+
+    fn on_pen_deny(&self, client: &Address, visit: &Address, config: &server::Config, reason: &str)
+    where
+        Self: Sized,
+    {
+        if let Some(obs) = self.as_ref() {
+            obs.on_pen_deny(client, visit, config, reason);
+        }
+    }
+
+    fn on_pen_reject(&self, client: &Address, config: &server::Config, reason: &str)
+    where
+        Self: Sized,
+    {
+        if let Some(obs) = self.as_ref() {
+            obs.on_pen_reject(client, config, reason);
+        }
+    }
+
+    fn on_pen_idle(
+        &self,
+        client: &Address,
+        visit: &Address,
+        config: &server::Config,
+        timeout: Duration,
+    ) where
+        Self: Sized,
+    {
+        if let Some(obs) = self.as_ref() {
+            obs.on_pen_idle(client, visit, config, timeout);
+        }
+    }
 }
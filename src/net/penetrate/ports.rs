@@ -0,0 +1,319 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, VecDeque},
+    fmt::Display,
+    hash::{Hash, Hasher},
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use crate::Kind;
+
+/// 端口范围, 例如 10000-20000 或者 8080
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortRange {
+    start: u16,
+    end: u16,
+}
+
+/// 固定分配给客户端的端口, 格式为 name=port, name可以是客户端名称或者密钥,
+/// 客户端名称由客户端自己指定, 只有通过密钥认证的客户端才能使用为名称预留的端口
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reservation {
+    key: String,
+    port: u16,
+}
+
+/// 访问端口的分配策略
+#[derive(Debug, Clone, Default)]
+pub struct PortPolicy {
+    /// 允许使用的端口, 为空时不限制
+    allow: Option<PortRange>,
+    /// 禁止使用的端口
+    deny: Vec<PortRange>,
+    reservations: HashMap<String, u16>,
+}
+
+/// 最多记录的端口数量, 超出时丢弃最早的记录
+const MAXIMUM_ASSIGNMENTS: usize = 1024;
+
+/// 记录客户端上一次使用的端口, 按认证密钥与名称记录, 重连时优先分配相同的端口
+#[derive(Clone, Default)]
+pub struct Assignments(Arc<Mutex<AssignmentTable>>);
+
+#[derive(Default)]
+struct AssignmentTable {
+    ports: HashMap<(String, String), u16>,
+    /// 记录的先后顺序
+    order: VecDeque<(String, String)>,
+}
+
+impl PortRange {
+    pub fn new(start: u16, end: u16) -> crate::Result<Self> {
+        if start == 0 || start > end {
+            return Err(Kind::Message(format!("invalid port range {}-{}", start, end)).into());
+        }
+
+        Ok(Self { start, end })
+    }
+
+    pub fn contains(&self, port: u16) -> bool {
+        self.start <= port && port <= self.end
+    }
+
+    /// 从offset开始依次返回范围内的端口
+    fn iter_from(&self, offset: u64) -> impl Iterator<Item = u16> {
+        let len = (self.end - self.start) as u64 + 1;
+        let start = self.start as u64;
+        (0..len).map(move |i| (start + (offset + i) % len) as u16)
+    }
+}
+
+impl FromStr for PortRange {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid =
+            || -> crate::Error { Kind::Message(format!("invalid port range {}", s)).into() };
+        let port = |port: &str| port.trim().parse::<u16>().map_err(|_| invalid());
+
+        match s.split_once('-') {
+            None => port(s).and_then(|port| Self::new(port, port)),
+            Some((start, end)) => Self::new(port(start)?, port(end)?),
+        }
+    }
+}
+
+impl Display for PortRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.start == self.end {
+            write!(f, "{}", self.start)
+        } else {
+            write!(f, "{}-{}", self.start, self.end)
+        }
+    }
+}
+
+impl Reservation {
+    pub fn new(key: &str, port: u16) -> Self {
+        Self {
+            key: key.to_owned(),
+            port,
+        }
+    }
+}
+
+impl FromStr for Reservation {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid =
+            || -> crate::Error { Kind::Message(format!("invalid reservation {}", s)).into() };
+
+        match s.rsplit_once('=') {
+            Some((key, port)) if !key.trim().is_empty() => {
+                let port = port.trim().parse::<u16>().map_err(|_| invalid())?;
+                Ok(Self::new(key.trim(), port))
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl PortPolicy {
+    pub fn allow(mut self, range: Option<PortRange>) -> Self {
+        self.allow = range;
+        self
+    }
+
+    pub fn deny(mut self, ranges: Vec<PortRange>) -> Self {
+        self.deny = ranges;
+        self
+    }
+
+    pub fn reserve(mut self, reservations: Vec<Reservation>) -> Self {
+        self.reservations = reservations
+            .into_iter()
+            .map(|reservation| (reservation.key, reservation.port))
+            .collect();
+        self
+    }
+
+    /// 端口是否在允许的范围内, 并且没有被禁止或者预留给其他客户端,
+    /// 返回的原因会发送给客户端, 不能包含预留的名称或者密钥
+    pub(crate) fn permits(&self, keys: &[&str], port: u16) -> Result<(), String> {
+        if let Some(allow) = self.allow.as_ref() {
+            if !allow.contains(port) {
+                return Err(format!(
+                    "port {} is not in the allowed range {}",
+                    port, allow
+                ));
+            }
+        }
+
+        if let Some(deny) = self.deny.iter().find(|deny| deny.contains(port)) {
+            return Err(format!("port {} is denied by {}", port, deny));
+        }
+
+        let reserved = self
+            .reservations
+            .iter()
+            .any(|(key, reserved)| *reserved == port && !keys.contains(&key.as_str()));
+
+        if reserved {
+            Err(format!("port {} is reserved", port))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// 需要依次尝试绑定的端口, 0表示由系统分配,
+    /// keys为客户端可以使用的预留名称或者密钥, name决定在范围内查找的起始位置
+    pub(crate) fn candidates(
+        &self,
+        name: &str,
+        keys: &[&str],
+        requested: u16,
+        last: Option<u16>,
+    ) -> Result<Vec<u16>, String> {
+        let reserved = keys
+            .iter()
+            .find_map(|key| self.reservations.get(*key).copied());
+
+        if let Some(port) = reserved {
+            return if requested == 0 || requested == port {
+                Ok(vec![port])
+            } else {
+                Err(format!(
+                    "port {} is not allowed, the client must use its reserved port {}",
+                    requested, port
+                ))
+            };
+        }
+
+        if requested != 0 {
+            return self.permits(keys, requested).map(|_| vec![requested]);
+        }
+
+        let mut candidates = last
+            .filter(|port| self.permits(keys, *port).is_ok())
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        match self.allow.as_ref() {
+            None => candidates.push(0),
+            Some(allow) => {
+                // 同一个客户端总是从相同的位置开始查找
+                let mut hasher = DefaultHasher::new();
+                name.hash(&mut hasher);
+
+                candidates.extend(
+                    allow
+                        .iter_from(hasher.finish())
+                        .filter(|port| Some(*port) != last && self.permits(keys, *port).is_ok()),
+                );
+
+                if candidates.is_empty() {
+                    return Err(format!("no port available in the range {}", allow));
+                }
+            }
+        }
+
+        Ok(candidates)
+    }
+}
+
+impl Assignments {
+    /// 未认证的客户端没有记录
+    pub(crate) fn last(&self, identity: Option<&str>, name: &str) -> Option<u16> {
+        let key = (identity?.to_owned(), name.to_owned());
+        self.0.lock().ok()?.ports.get(&key).copied()
+    }
+
+    pub(crate) fn record(&self, identity: Option<&str>, name: &str, port: u16) {
+        let (identity, mut table) = match (identity, self.0.lock()) {
+            (Some(identity), Ok(table)) => (identity, table),
+            _ => return,
+        };
+
+        let key = (identity.to_owned(), name.to_owned());
+
+        if table.ports.insert(key.clone(), port).is_some() {
+            return;
+        }
+
+        table.order.push_back(key);
+
+        while table.order.len() > MAXIMUM_ASSIGNMENTS {
+            if let Some(oldest) = table.order.pop_front() {
+                table.ports.remove(&oldest);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Assignments, PortPolicy, PortRange, Reservation, MAXIMUM_ASSIGNMENTS};
+
+    #[test]
+    fn test_port_policy() {
+        let policy = PortPolicy::default()
+            .allow(Some("10000-10009".parse().unwrap()))
+            .deny(vec!["10005".parse().unwrap()])
+            .reserve(vec!["alice=10001".parse::<Reservation>().unwrap()]);
+
+        assert_eq!(
+            policy.candidates("alice", &["alice"], 0, None),
+            Ok(vec![10001])
+        );
+        assert!(policy.candidates("alice", &["alice"], 10002, None).is_err());
+        assert_eq!(
+            policy.candidates("bob", &["bob"], 10001, None),
+            Err(String::from("port 10001 is reserved"))
+        );
+        assert!(policy.candidates("bob", &["bob"], 10005, None).is_err());
+        assert!(policy.candidates("bob", &["bob"], 8080, None).is_err());
+        assert_eq!(
+            policy.candidates("bob", &["bob"], 10002, None),
+            Ok(vec![10002])
+        );
+
+        // 未认证的客户端不能使用为名称预留的端口
+        assert!(policy.candidates("alice", &[], 10001, None).is_err());
+
+        let candidates = policy.candidates("bob", &["bob"], 0, Some(10003)).unwrap();
+        assert_eq!(candidates[0], 10003);
+        assert_eq!(candidates.len(), 8);
+        assert!(!candidates.contains(&10001) && !candidates.contains(&10005));
+
+        assert_eq!(
+            PortPolicy::default().candidates("bob", &["bob"], 0, None),
+            Ok(vec![0])
+        );
+
+        assert!("20-10".parse::<PortRange>().is_err());
+        assert!("=10".parse::<Reservation>().is_err());
+    }
+
+    #[test]
+    fn test_assignments() {
+        let assignments = Assignments::default();
+
+        assignments.record(None, "alice", 10001);
+        assert_eq!(assignments.last(None, "alice"), None);
+
+        assignments.record(Some("secret"), "alice", 10001);
+        assert_eq!(assignments.last(Some("secret"), "alice"), Some(10001));
+        assert_eq!(assignments.last(Some("other"), "alice"), None);
+        assert_eq!(assignments.last(None, "alice"), None);
+
+        for i in 0..MAXIMUM_ASSIGNMENTS {
+            assignments.record(Some("secret"), &format!("client-{}", i), 10002);
+        }
+
+        assert_eq!(assignments.last(Some("secret"), "alice"), None);
+        assert_eq!(assignments.last(Some("secret"), "client-0"), Some(10002));
+        let table = assignments.0.lock().unwrap();
+        assert_eq!(table.ports.len(), MAXIMUM_ASSIGNMENTS);
+    }
+}
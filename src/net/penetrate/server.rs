@@ -1,7 +1,18 @@
-use std::{collections::HashMap, fmt::Display, pin::Pin, sync::Arc, task::Poll, time::Duration};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, PoisonError,
+    },
+    task::Poll,
+    time::{Duration, Instant},
+};
 
 use serde::Serialize;
 
+use crate::mux::Session;
 use crate::penetrate::accepter::PenetrateAccepter;
 use crate::penetrate::client;
 use crate::protocol::IntoPacket;
@@ -12,17 +23,20 @@ use std::future::Future;
 use crate::io::{ReadHalf, WriteHalf};
 
 use crate::{
-    ext::AsyncWriteExt,
+    ext::{AsyncReadExt, AsyncWriteExt},
     generator::Generator,
-    guard::Fallback,
+    guard::{Counter, Fallback, Lease, Limited, Limits, Slot, Slots, Traffic},
     io,
-    protocol::{AsyncRecvPacket, AsyncSendPacket, Bind, Poto, ToBytes, TryToPoto},
-    ready, Accepter, Provider, Socket, Stream, WrappedProvider,
+    protocol::{AsyncRecvPacket, AsyncSendPacket, Bind, Poto, ToBytes, Token, TryToPoto},
+    ready,
+    select::Select,
+    Accepter, Keepalive, Outbound, Provider, Shutdown, Socket, SocketKind, Stream, WrappedProvider,
 };
 
 use super::accepter::Pen;
-use super::mock::Mock;
-use super::PenetrateObserver;
+use super::auth;
+use super::mock::{Mock, VirtualHost, VirtualHosts};
+use super::{AccessList, Assignments, PenetrateObserver, Reloader, Settings, Throttle};
 use crate::{join, time, Address, Error, Kind, NetSocket, Platform, Processor};
 
 type BoxedFuture<T> = Pin<Box<dyn std::future::Future<Output = crate::Result<T>> + Send + 'static>>;
@@ -75,22 +89,25 @@ macro_rules! read_client_config {
 }
 
 pub enum Outcome<T> {
-    Route(T, T),
+    /// 访问者, 客户端, 作用于访问者的限速器以及占用的名额, 空闲超时
+    Route(T, T, Lease, Idle),
     Future(BoxedFuture<()>),
 }
 
 pub enum State<T> {
     Stop,
+    Visit(Fallback<T>),
     Close(T),
     Finish,
-    Route(T, T),
+    Route(T, T, Lease, Idle),
     Provider(BoxedFuture<()>),
     Error(crate::Error),
 }
 
 pub enum Visitor<T> {
     Route(T),
-    Provider(WrappedProvider<T, ()>),
+    /// 客户端回连的流, 受访问者的限速器与空闲超时限制
+    Provider(WrappedProvider<io::Watched<Limited<T>>, ()>),
 }
 
 pub struct PenetrateGenerator<P, T, A, O>(Penetrate<P, T, PenetrateAccepter<A, A>, O>);
@@ -99,6 +116,8 @@ pub enum Peer<T> {
     Route(Visitor<T>, Socket),
     Finished(T),
     Unknown(T),
+    /// 访问者已交给其他客户端处理
+    Transferred,
 }
 
 #[derive(Default, Clone)]
@@ -113,8 +132,6 @@ pub struct Config {
     pub(super) is_mixed: bool,
     pub(super) maximum_wait: Duration,
     pub(super) heartbeat_delay: Duration,
-    pub(super) read_timeout: Option<Duration>,
-    pub(super) write_timeout: Option<Duration>,
     pub(super) fallback_strict_mode: bool,
     pub(super) enable_socks: bool,
     pub(super) enable_socks_udp: bool,
@@ -122,31 +139,75 @@ pub struct Config {
     pub(super) socks5_username: Option<String>,
     pub(super) platform: Platform,
     pub(super) real_ip: bool,
+    pub(super) enable_mux: bool,
+    pub(super) domain: Option<String>,
+    /// 访问者的黑白名单
+    pub(super) access: AccessList,
+    /// 同时访问的数量, 0表示不限制
+    pub(super) maximum_visitors: usize,
+    /// 客户端设置的空闲超时
+    pub(super) idle_timeout: Option<Duration>,
 }
 
 pub struct PenetrateProvider<T> {
     pub(crate) mock: Arc<Mock<T>>,
     pub(crate) config: Config,
+    pub(crate) settings: Reloader,
+    /// 客户端上一次使用的访问端口
+    pub(crate) assignments: Assignments,
+    /// 带宽限制, 通过管理接口修改后立即生效
+    pub(crate) limits: Limits,
+    /// 在线的客户端数量
+    pub(crate) clients: Slots,
+    pub(crate) throttle: Throttle,
+    pub(crate) vhosts: Option<VirtualHosts<T>>,
+}
+
+/// 转发的空闲超时, 超时后关闭两端并通知观察者
+pub struct Idle {
+    timeout: Option<Duration>,
+    /// 没有空闲超时时, 半关闭后另一个方向没有数据超过linger则结束
+    linger: Duration,
+    on_idle: Box<dyn FnOnce(Duration) + Send + Sync>,
 }
 
+/// 客户端通知暂停的映射, 以及暂停时返回给http访问者的内容
+#[derive(Clone, Default)]
+struct Suspended(Arc<std::sync::Mutex<HashMap<u32, Option<String>>>>);
+
 #[derive(Debug, Clone, Serialize)]
 pub struct PenetrateEnviron {
     conn: Address,
     client: Address,
     config: Config,
     visitor: Address,
+    traffic: Arc<Traffic>,
 }
 
 pub struct Penetrate<P, S, A, O> {
     mock: Arc<Mock<S>>,
     config: Arc<Config>,
-    accepter: A,
+    /// 每个映射的accepter, 对应的配置以及正在访问的数量, 按照映射id排列
+    accepters: Vec<(A, Arc<Config>, Slots)>,
     address: Address,
     writer: WriteHalf<S>,
     processor: Processor<P, S, O>,
     futures: Vec<BoxedFuture<State<S>>>,
     mqueue: MQueue<async_channel::Sender<S>>,
     client_addr: Address,
+    mux: Option<Session>,
+    traffic: Arc<Traffic>,
+    vhost: Option<VirtualHost<S>>,
+    /// 服务端可以热更新的配置, 每个访问者使用最新的值
+    settings: Reloader,
+    limits: Limits,
+    throttle: Throttle,
+    /// 客户端占用的名额, 断开后归还
+    slot: Option<Slot>,
+    /// 客户端即将关闭, 不再接受新的访问者
+    draining: Arc<AtomicBool>,
+    suspended: Suspended,
+    on_stop: Option<Box<dyn FnOnce() + Send + Sync>>,
 }
 
 impl<T> MQueue<T> {
@@ -174,7 +235,93 @@ impl<T> MQueue<T> {
     }
 }
 
+impl Idle {
+    async fn forward<S1, S2>(self, s1: S1, s2: S2) -> crate::Result<()>
+    where
+        S1: Stream + Unpin + Send + 'static,
+        S2: Stream + Unpin + Send + 'static,
+    {
+        match io::forward(s1, s2)
+            .linger(self.linger)
+            .idle_timeout(self.timeout)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => match e.kind() {
+                Kind::Idle(timeout) => {
+                    (self.on_idle)(*timeout);
+                    Ok(())
+                }
+                _ => Err(e),
+            },
+        }
+    }
+
+    /// 由provider转发, 例如socks5的udp转发, 流两个方向都没有数据超过空闲超时时结束
+    fn provide<S>(self, provider: WrappedProvider<io::Watched<S>, ()>, stream: S) -> BoxedFuture<()>
+    where
+        S: Send + 'static,
+    {
+        let (stream, watchdog) = io::watch(stream, self.timeout);
+        let fut = provider.call(stream);
+
+        match watchdog {
+            None => fut,
+            Some(watchdog) => Box::pin(async move {
+                match Select::select(fut, watchdog).await {
+                    Err(e) => match e.kind() {
+                        Kind::Idle(timeout) => {
+                            (self.on_idle)(*timeout);
+                            Ok(())
+                        }
+                        _ => Err(e),
+                    },
+                    Ok(()) => Ok(()),
+                }
+            }),
+        }
+    }
+}
+
+impl Suspended {
+    fn suspend(&self, mapping: u32, page: Option<String>) {
+        self.lock().insert(mapping, page);
+    }
+
+    fn resume(&self, mapping: u32) {
+        self.lock().remove(&mapping);
+    }
+
+    /// 映射暂停时返回Some, 内部为暂停时的http响应内容
+    fn get(&self, mapping: u32) -> Option<Option<String>> {
+        self.lock().get(&mapping).cloned()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<u32, Option<String>>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
 impl Config {
+    pub fn name(&self) -> &str {
+        &self.whoami
+    }
+
+    /// 空闲超时, 服务端与客户端的设置都存在时取较小的值
+    fn idle_timeout(&self, server: Option<Duration>) -> Option<Duration> {
+        match (server, self.idle_timeout) {
+            (None, timeout) | (timeout, None) => timeout,
+            (Some(server), Some(client)) => Some(server.min(client)),
+        }
+    }
+
+    /// 使用最新的服务端配置, 客户端连接时调用
+    fn reload(&mut self, settings: &Settings) {
+        self.maximum_wait = settings.max_wait_time;
+        self.fallback_strict_mode = settings.fallback_strict_mode;
+        self.heartbeat_delay = settings.heartbeat_timeout;
+    }
+
     fn update(&mut self, config: client::Config) {
         self.whoami = config.name;
         self.enable_socks = config.enable_socks5 || config.enable_socks5_udp;
@@ -185,6 +332,27 @@ impl Config {
         self.maximum_wait = config.maximum_wait;
         self.is_mixed = config.enable_kcp;
         self.platform = config.platform;
+        self.enable_mux = config.enable_mux;
+        self.domain = config.domain;
+        self.real_ip = config.proxy_protocol.is_some();
+        self.access = config.access;
+        self.maximum_visitors = config.maximum_visitors;
+        self.idle_timeout = config.idle_timeout;
+    }
+
+    /// 额外映射的配置, 除socks, 真实ip, 黑白名单, 访问数量与空闲超时外与默认映射相同
+    fn mapping(&self, mapping: &client::Mapping) -> Self {
+        let mut config = self.clone();
+        config.whoami = format!("{}/{}", self.whoami, mapping.name);
+        config.enable_socks = mapping.enable_socks5 || mapping.enable_socks5_udp;
+        config.enable_socks_udp = mapping.enable_socks5_udp;
+        config.socks5_username = mapping.socks_username.clone();
+        config.socks5_password = mapping.socks_password.clone();
+        config.real_ip = mapping.proxy_protocol.is_some();
+        config.access = mapping.access.clone();
+        config.maximum_visitors = mapping.maximum_visitors;
+        config.idle_timeout = mapping.idle_timeout;
+        config
     }
 }
 
@@ -202,8 +370,31 @@ where
         address: Address,
         client: T,
         accepter: A,
+        environ: Arc<PenetrateEnviron>,
     ) -> Self {
         let client_addr = unsafe { client.peer_addr().unwrap_unchecked() };
+        let traffic = environ.traffic.clone();
+
+        // 结束时通知观察者, 连接被中断(如管理接口断开连接)时同样会通知
+        let on_stop = {
+            let observer = processor.observer().clone();
+            move || {
+                observer.on_pen_stop(
+                    &environ.conn,
+                    &environ.visitor,
+                    &environ.client,
+                    &environ.config,
+                )
+            }
+        };
+
+        let mux = if config.enable_mux {
+            let local_addr = client.local_addr().unwrap_or_else(|_| address.clone());
+            Some(Session::new(true, local_addr, client_addr.clone()))
+        } else {
+            None
+        };
+
         let (reader, writer) = crate::io::split(client);
 
         let mqueue = MQueue {
@@ -211,25 +402,104 @@ where
             wait_list: Default::default(),
         };
 
-        let recv_fut = Self::poll_handle_recv(mqueue.clone(), reader.clone());
-        let write_fut = Self::poll_heartbeat_future(writer.clone(), config.heartbeat_delay);
+        let draining = Arc::new(AtomicBool::new(false));
+        let suspended = Suspended::default();
+
+        let recv_fut = Self::poll_handle_recv(
+            mqueue.clone(),
+            reader.clone(),
+            mux.clone(),
+            (traffic.clone(), draining.clone(), suspended.clone()),
+        );
+
+        // 多路复用时所有的写入都由同一个future完成, 避免与心跳包交错写入
+        let write_fut: BoxedFuture<State<T>> = match mux.clone() {
+            None => Box::pin(Self::poll_heartbeat_future(
+                writer.clone(),
+                config.heartbeat_delay,
+                processor.shutdown().clone(),
+            )),
+            Some(mux) => Box::pin(Self::poll_mux_outbound(
+                mux,
+                writer.clone(),
+                config.heartbeat_delay,
+                processor.shutdown().clone(),
+            )),
+        };
+
+        let config = Arc::new(config);
 
         Self {
             writer,
-            config: Arc::new(config),
+            accepters: vec![(accepter, config.clone(), Slots::default())],
+            config,
             mock: converter,
-            accepter,
             mqueue,
             client_addr,
             processor,
             address,
-            futures: vec![Box::pin(recv_fut), Box::pin(write_fut)],
+            mux,
+            traffic,
+            vhost: None,
+            settings: Reloader::default(),
+            limits: Limits::default(),
+            throttle: Throttle::default(),
+            slot: None,
+            draining,
+            suspended,
+            on_stop: Some(Box::new(on_stop)),
+            futures: vec![Box::pin(recv_fut), write_fut],
+        }
+    }
+
+    /// 额外的端口映射, 映射id按照添加的顺序从1开始
+    pub fn with_mapping(mut self, accepter: A, config: Config) -> Self {
+        self.accepters
+            .push((accepter, Arc::new(config), Slots::default()));
+        self
+    }
+
+    pub fn with_settings(mut self, settings: Reloader) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn with_throttle(mut self, throttle: Throttle) -> Self {
+        self.throttle = throttle;
+        self
+    }
+
+    /// 持有客户端占用的名额直到断开
+    pub fn with_slot(mut self, slot: Slot) -> Self {
+        self.slot = Some(slot);
+        self
+    }
+
+    fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst) || self.processor.shutdown().is_triggered()
+    }
+
+    /// 接收共享http端口上访问该客户端域名的访问者
+    pub fn with_vhost(mut self, vhost: Option<VirtualHost<T>>) -> Self {
+        if let Some(vhost) = vhost.as_ref() {
+            self.futures
+                .push(Box::pin(Self::poll_shared_visitor(vhost.receiver())));
         }
+
+        self.vhost = vhost;
+        self
     }
 
     async fn poll_handle_recv(
         mqueue: MQueue<async_channel::Sender<T>>,
         mut stream: ReadHalf<T>,
+        mux: Option<Session>,
+        (traffic, draining, suspended): (Arc<Traffic>, Arc<AtomicBool>, Suspended),
     ) -> crate::Result<State<T>> {
         loop {
             let packet = stream.recv_packet().await;
@@ -253,10 +523,37 @@ where
                 Poto::Ping => {
                     log::trace!("client ping received");
                 }
+                Poto::Close => {
+                    log::info!("the client is shutting down, stop accepting visitors");
+                    draining.store(true, Ordering::SeqCst);
+                }
+                Poto::Suspend(mapping, page) => {
+                    log::warn!(
+                        "the local service of mapping {} is down, suspend it",
+                        mapping
+                    );
+                    suspended.suspend(mapping, page);
+                }
+                Poto::Resume(mapping) => {
+                    log::info!(
+                        "the local service of mapping {} is back, resume it",
+                        mapping
+                    );
+                    suspended.resume(mapping);
+                }
                 Poto::MapError(id, err) => {
                     log::warn!("client mapping failed, msg = {}", err);
+                    traffic.map_failed();
                     mqueue.remove(id).await.map(|r| r.close());
                 }
+                Poto::Mux(frame) => match mux.as_ref() {
+                    None => log::warn!("multiplexing is not enabled, ignore {:?}", frame),
+                    Some(mux) => {
+                        if let Err(e) = mux.dispatch(frame) {
+                            return Ok(State::Error(e));
+                        }
+                    }
+                },
                 message => {
                     log::warn!("ignore client message {:?}", message);
                 }
@@ -264,50 +561,290 @@ where
         }
     }
 
+    async fn poll_shared_visitor(
+        receiver: async_channel::Receiver<Fallback<T>>,
+    ) -> crate::Result<State<T>> {
+        match receiver.recv().await {
+            Ok(visitor) => Ok(State::Visit(visitor)),
+            Err(e) => Ok(State::Error(e.into())),
+        }
+    }
+
     async fn poll_heartbeat_future(
         mut stream: WriteHalf<T>,
         timeout: Duration,
+        shutdown: Shutdown,
     ) -> crate::Result<State<T>> {
-        let ping = Poto::Ping.bytes();
+        let mut keepalive = Keepalive::new(shutdown, timeout);
+        let mut packet = Poto::Ping.bytes();
 
         loop {
-            log::trace!("send heartbeat packet to client");
+            if let Err(e) = stream.send_packet(&packet).await {
+                log::warn!("failed to send packet to client");
+                break Ok(State::Error(e));
+            }
+
+            // 没有数据需要发送, 只有心跳与关闭通知
+            let idle = std::future::pending::<crate::Result<()>>();
 
-            if let Err(e) = stream.send_packet(&ping).await {
-                log::warn!("failed to send heartbeat packet to client");
+            packet = match keepalive.next(idle).await {
+                // 通知客户端服务端即将关闭, 已建立的连接继续转发
+                Ok(Outbound::Close) => {
+                    log::info!("notify the client that the server is shutting down");
+                    Poto::Close.bytes()
+                }
+                _ => {
+                    log::trace!("send heartbeat packet to client");
+                    Poto::Ping.bytes()
+                }
+            };
+        }
+    }
+
+    async fn poll_mux_outbound(
+        mux: Session,
+        mut stream: WriteHalf<T>,
+        timeout: Duration,
+        shutdown: Shutdown,
+    ) -> crate::Result<State<T>> {
+        let mut keepalive = Keepalive::new(shutdown, timeout);
+
+        loop {
+            let next_frame = {
+                let mux = mux.clone();
+                keepalive.next(async move { mux.next_frame().await }).await
+            };
+
+            let packet = match next_frame {
+                Ok(Outbound::Data(frame)) => Poto::Mux(frame).bytes(),
+                // 通知客户端服务端即将关闭, 已建立的流继续转发
+                Ok(Outbound::Close) => {
+                    log::info!("notify the client that the server is shutting down");
+                    Poto::Close.bytes()
+                }
+                Ok(Outbound::Ping) => {
+                    log::trace!("send heartbeat packet to client");
+                    Poto::Ping.bytes()
+                }
+                Err(e) => break Ok(State::Error(e)),
+            };
+
+            if let Err(e) = stream.send_packet(&packet).await {
+                log::warn!("failed to send packet to client");
+                mux.close();
                 break Ok(State::Error(e));
             }
+        }
+    }
+
+    async fn async_mux_route(
+        mux: Session,
+        mqueue: MQueue<async_channel::Sender<T>>,
+        (visitor, mapping, dst): (Visitor<Fallback<T>>, u32, Socket),
+        (client_addr, visit_addr): (Address, Address),
+        processor: Processor<P, T, O>,
+        fallback_strict_mode: bool,
+        (traffic, lease, idle): (Arc<Traffic>, Lease, Idle),
+    ) -> crate::Result<State<T>> {
+        let mut stream = throw_client_error!(mux.open());
+
+        match visitor {
+            Visitor::Route(mut src) => {
+                let route = Poto::Map(stream.id(), mapping, dst).bytes();
+
+                throw_client_error!(stream.send_packet(&route).await);
+
+                src.backward().await?;
+
+                if let Some(data) = src.back_data() {
+                    log::debug!("copy data to peer {}bytes", data.len());
+                    stream.write_all(&data).await?;
+                }
+
+                processor
+                    .observer()
+                    .on_pen_route(&client_addr, &visit_addr, &stream.peer_addr()?);
+
+                Ok(State::Provider(Box::pin(idle.forward(
+                    Limited::new(Counter::new(src.into_inner(), traffic), lease),
+                    stream,
+                ))))
+            }
+            Visitor::Provider(provider) => {
+                // udp转发依然需要客户端回连, 通过流通知客户端
+                let (accept_tx, accept_ax) = async_channel::bounded(1);
+                let id = mqueue.push(accept_tx).await;
+                let route = Poto::Map(id, mapping, dst).bytes();
+
+                throw_client_error!(stream.send_packet(&route).await);
+
+                let accept_fut = async move { accept_ax.recv().await.map_err(Into::into) };
+
+                let error_fut = async move {
+                    let message = stream.recv_packet().await.and_then(TryToPoto::try_poto);
+
+                    if let Ok(Poto::MapError(_, err)) = message {
+                        log::warn!("client mapping failed, msg = {}", err);
+                        traffic.map_failed();
+                        return Err(Kind::Message(err).into());
+                    }
+
+                    std::future::pending::<crate::Result<T>>().await
+                };
 
-            time::sleep(timeout).await;
+                let result = Select::select(accept_fut, error_fut).await;
+
+                if result.is_err() {
+                    mqueue.remove(id).await;
+                }
+
+                let fallback = Fallback::new(result?, fallback_strict_mode);
+
+                processor.observer().on_pen_route(
+                    &client_addr,
+                    &visit_addr,
+                    &fallback.peer_addr()?,
+                );
+
+                Ok(State::Provider(
+                    idle.provide(provider, Limited::new(fallback, lease)),
+                ))
+            }
         }
     }
 
     fn async_penetrate_handle(self: &mut Pin<&mut Self>, pen: Pen<T>) -> BoxedFuture<State<T>> {
         let mut writer = self.writer.clone();
         let mock = self.mock.clone();
-        let timeout = self.config.maximum_wait;
+        let settings = self.settings.current();
+        let timeout = self.config.maximum_wait.min(settings.max_wait_time);
         let mqueue = self.mqueue.clone();
-        let fallback_strict_mode = self.config.fallback_strict_mode;
+        let fallback_strict_mode = settings.fallback_strict_mode;
         let processor = self.processor.clone();
-        let config = self.config.clone();
+        let mux = self.mux.clone();
+        let traffic = self.traffic.clone();
+        let throttle = self.throttle.clone();
+        let suspended = self.suspended.clone();
+
+        let (mapping, config, visitors) = match &pen {
+            Pen::Visit(mapping, _) => match self.accepters.get(*mapping as usize) {
+                Some((_, config, visitors)) => (*mapping, config.clone(), visitors.clone()),
+                None => (0, self.config.clone(), self.accepters[0].2.clone()),
+            },
+            _ => (0, self.config.clone(), self.accepters[0].2.clone()),
+        };
+
+        // 客户端与服务端都限制了访问数量时取较小的值
+        let maximum = match (config.maximum_visitors, settings.max_visitors) {
+            (0, maximum) | (maximum, 0) => maximum,
+            (client, server) => client.min(server),
+        };
+
+        let limiters = self.limits.limiters(&config.whoami);
 
         let fut = async move {
             match pen {
-                Pen::Visit(visitor) => {
-                    let mut fallback = Fallback::new(visitor, fallback_strict_mode);
+                Pen::Visit(..) | Pen::Shared(_) => {
+                    let start = Instant::now();
+                    let mut fallback = match pen {
+                        Pen::Visit(_, visitor) => Fallback::new(visitor, fallback_strict_mode),
+                        Pen::Shared(visitor) => visitor,
+                        Pen::Client(_) => unreachable!("client is handled below"),
+                    };
                     let visit_addr = fallback.peer_addr()?;
+                    let suspension = suspended.get(mapping);
+
+                    // 依次检查服务端与映射的黑白名单, 访问频率, 映射是否暂停以及同时访问的数量
+                    let permitted = settings
+                        .access
+                        .permits(&visit_addr)
+                        .and_then(|_| config.access.permits(&visit_addr))
+                        .and_then(|_| throttle.permits(&visit_addr, settings.max_visitor_rate))
+                        .and_then(|_| match suspension {
+                            None => Ok(()),
+                            Some(_) => Err(String::from("the local service is unavailable")),
+                        })
+                        .and_then(|_| {
+                            visitors.acquire(maximum).ok_or_else(|| {
+                                format!("reached the maximum of {} visitors", maximum)
+                            })
+                        });
+
+                    let lease = match permitted {
+                        Ok(slot) => Lease::new(limiters).hold(Some(slot)),
+                        Err(reason) => {
+                            log::info!("{} {}", config.whoami, reason);
+                            processor.observer().on_pen_deny(
+                                &writer.peer_addr()?,
+                                &visit_addr,
+                                &config,
+                                &reason,
+                            );
+
+                            if let Some(Some(page)) = suspension {
+                                if let Err(e) = write_suspend_page(fallback, page).await {
+                                    log::debug!("failed to write suspend page {}", e);
+                                }
+
+                                return Ok(State::Finish);
+                            }
+
+                            return Ok(State::Close(fallback.into_inner()));
+                        }
+                    };
+
+                    let idle = {
+                        let observer = processor.observer().clone();
+                        let (client_addr, visit_addr) = (writer.peer_addr()?, visit_addr.clone());
+                        let config = config.clone();
+                        Idle {
+                            timeout: config.idle_timeout(settings.idle_timeout),
+                            linger: settings.linger,
+                            on_idle: Box::new(move |timeout| {
+                                log::info!(
+                                    "{} visitor {} idle for {:?}, closed",
+                                    config.whoami,
+                                    visit_addr,
+                                    timeout
+                                );
+                                observer.on_pen_idle(&client_addr, &visit_addr, &config, timeout);
+                            }),
+                        }
+                    };
+
                     let _ = fallback.mark().await?;
                     let peer = mock.call((fallback, config)).await?;
-                    let (accept_tx, accept_ax) = async_channel::bounded(1);
-                    let id = mqueue.push(accept_tx).await;
 
                     let (visitor, dst) = match peer {
                         Peer::Finished(visitor) => return Ok(State::Close(visitor.into_inner())),
                         Peer::Unknown(visitor) => return Ok(State::Close(visitor.into_inner())),
+                        Peer::Transferred => return Ok(State::Finish),
                         Peer::Route(visitor, dst) => (visitor, dst),
                     };
 
-                    let route = Poto::Map(id, dst).bytes();
+                    if let Some(mux) = mux {
+                        let state = Self::async_mux_route(
+                            mux,
+                            mqueue,
+                            (visitor, mapping, dst),
+                            (writer.peer_addr()?, visit_addr),
+                            processor,
+                            fallback_strict_mode,
+                            (traffic.clone(), lease, idle),
+                        )
+                        .await;
+
+                        if let Ok(State::Provider(_)) = state.as_ref() {
+                            traffic.mapped(start.elapsed());
+                        }
+
+                        return state;
+                    }
+
+                    let (accept_tx, accept_ax) = async_channel::bounded(1);
+                    let id = mqueue.push(accept_tx).await;
+
+                    let route = Poto::Map(id, mapping, dst).bytes();
 
                     throw_client_error!(writer.send_packet(&route).await);
 
@@ -337,7 +874,9 @@ where
                                 &dst.peer_addr()?,
                             );
 
-                            Ok::<_, crate::Error>(State::Route(src.into_inner(), dst))
+                            traffic.mapped(start.elapsed());
+
+                            Ok::<_, crate::Error>(State::Route(src.into_inner(), dst, lease, idle))
                         }
                         Visitor::Provider(provider) => {
                             let fallback =
@@ -349,9 +888,11 @@ where
                                 &fallback.peer_addr()?,
                             );
 
-                            let dst = provider.call(fallback);
+                            traffic.mapped(start.elapsed());
 
-                            Ok(State::Provider(dst))
+                            Ok(State::Provider(
+                                idle.provide(provider, Limited::new(fallback, lease)),
+                            ))
                         }
                     }
                 }
@@ -361,7 +902,7 @@ where
                     let poto = client.recv_packet().await?.try_poto()?;
 
                     match poto {
-                        Poto::Map(id, _) => {
+                        Poto::Map(id, ..) => {
                             if let Some(tx) = mqueue.remove(id).await {
                                 if let Err(_) = tx.send(client).await {
                                     log::warn!("the client established a mapping request, but the peer was closed");
@@ -389,17 +930,37 @@ where
     }
 }
 
+impl<P, S, A, O> Drop for Penetrate<P, S, A, O> {
+    fn drop(&mut self) {
+        if let Some(mux) = self.mux.take() {
+            mux.close();
+        }
+
+        if let Some(on_stop) = self.on_stop.take() {
+            on_stop();
+        }
+    }
+}
+
 impl<P, T, A, O> NetSocket for Penetrate<P, T, A, O>
 where
     T: Stream,
     A: Accepter<Stream = Pen<T>>,
 {
     fn peer_addr(&self) -> crate::Result<Address> {
-        self.accepter.peer_addr()
+        self.accepters
+            .iter()
+            .try_fold(Address::Many(Vec::new()), |addr, (accepter, ..)| {
+                Ok(addr + accepter.peer_addr()?)
+            })
     }
 
     fn local_addr(&self) -> crate::Result<Address> {
-        self.accepter.local_addr()
+        self.accepters
+            .iter()
+            .try_fold(Address::Many(Vec::new()), |addr, (accepter, ..)| {
+                Ok(addr + accepter.local_addr()?)
+            })
     }
 }
 
@@ -423,28 +984,55 @@ where
         let mut poll_accepter = true;
 
         while poll_accepter {
-            poll_accepter = match Pin::new(&mut self.accepter).poll_accept(cx)? {
-                Poll::Pending => false,
-                Poll::Ready(pen) => {
-                    futures.push(self.async_penetrate_handle(pen));
-                    true
+            let mut accepted = Vec::new();
+
+            for (accepter, ..) in self.accepters.iter_mut() {
+                if let Poll::Ready(pen) = Pin::new(accepter).poll_accept(cx)? {
+                    accepted.push(pen);
                 }
-            };
+            }
+
+            poll_accepter = !accepted.is_empty();
+
+            for pen in accepted {
+                match pen {
+                    // 服务端或者客户端即将关闭时拒绝新的访问者, 客户端的回连依然处理
+                    Pen::Visit(_, visitor) if self.is_draining() => {
+                        futures.push(Box::pin(async move { Ok(State::Close(visitor)) }));
+                    }
+                    pen => futures.push(self.async_penetrate_handle(pen)),
+                }
+            }
 
             while let Some(mut future) = futures.pop() {
                 match Pin::new(&mut future).poll(cx) {
                     Poll::Pending => {
                         self.futures.push(future);
                     }
-                    Poll::Ready(Ok(State::Route(s1, s2))) => {
+                    Poll::Ready(Ok(State::Route(s1, s2, lease, idle))) => {
                         self.futures.extend(futures);
 
-                        return Poll::Ready(Ok::<_, crate::Error>(Outcome::Route(s1, s2)));
+                        return Poll::Ready(Ok::<_, crate::Error>(Outcome::Route(
+                            s1, s2, lease, idle,
+                        )));
                     }
                     Poll::Ready(Ok(State::Provider(fut))) => {
                         self.futures.extend(futures);
                         return Poll::Ready(Ok::<_, crate::Error>(Outcome::Future(fut)));
                     }
+                    Poll::Ready(Ok(State::Visit(visitor))) if self.is_draining() => {
+                        let visitor = visitor.into_inner();
+                        futures.push(Box::pin(async move { Ok(State::Close(visitor)) }));
+                    }
+                    Poll::Ready(Ok(State::Visit(visitor))) => {
+                        log::debug!("shared visitor {}", visitor.peer_addr()?);
+
+                        futures.push(self.async_penetrate_handle(Pen::Shared(visitor)));
+
+                        if let Some(vhost) = self.vhost.as_ref() {
+                            futures.push(Box::pin(Self::poll_shared_visitor(vhost.receiver())));
+                        }
+                    }
                     Poll::Ready(Ok(State::Stop)) => {
                         log::warn!("client aborted {}", self.client_addr);
                         return Poll::Ready(Err(crate::error::Kind::Channel.into()));
@@ -487,12 +1075,94 @@ where
     fn call(&self, (mut client, processor): (S, Processor<P, S, O>)) -> Self::Output {
         let peer_provider = self.mock.clone();
         let mut config = self.config.clone();
+        let settings = self.settings.clone();
+        let limits = self.limits.clone();
+        let clients = self.clients.clone();
+        let throttle = self.throttle.clone();
+        let current = settings.current();
+        let assignments = self.assignments.clone();
+        config.reload(&current);
+        let vhosts = self.vhosts.clone();
         Box::pin(async move {
-            let poto = client.recv_packet().await?.try_poto()?;
+            let mut poto = client.recv_packet().await?.try_poto()?;
+            let mut token = None;
+
+            if let Poto::Token(Token::Request) = poto {
+                match auth::server_authenticate(&mut client, &current.secrets).await {
+                    Ok(secret) => token = secret,
+                    Err(e) => {
+                        log::warn!(
+                            "client authentication failed {}, err={}",
+                            client.peer_addr()?,
+                            e
+                        );
+
+                        processor
+                            .observer()
+                            .on_pen_error(&client.peer_addr()?, &config, &e);
+
+                        return Err(e);
+                    }
+                }
+
+                poto = client.recv_packet().await?.try_poto()?;
+            } else if !current.secrets.is_empty() {
+                let err: Error = Kind::Unauthorized(String::from("secret required")).into();
+                let message = Poto::Bind(Bind::Failed(err.to_string())).bytes();
+
+                log::warn!("reject unauthenticated client {}", client.peer_addr()?);
+
+                if let Err(e) = client.send_packet(&message).await {
+                    log::warn!("failed to send failure message to client err={}", e);
+                }
+
+                processor
+                    .observer()
+                    .on_pen_error(&client.peer_addr()?, &config, &err);
+
+                return Err(err);
+            }
+
+            // 名额在客户端断开后归还
+            let slot = match clients.acquire(current.max_clients) {
+                Some(slot) => slot,
+                None => {
+                    let reason = format!("reached the maximum of {} clients", current.max_clients);
+                    let message = Poto::Bind(Bind::Failed(reason.clone())).bytes();
+
+                    log::warn!("reject client {}, {}", client.peer_addr()?, reason);
+
+                    if let Err(e) = client.send_packet(&message).await {
+                        log::warn!("failed to send failure message to client err={}", e);
+                    }
+
+                    processor
+                        .observer()
+                        .on_pen_reject(&client.peer_addr()?, &config, &reason);
+
+                    return Err(Kind::Forbidden(reason).into());
+                }
+            };
+
             let penetrate = match poto {
-                Poto::Bind(Bind::Setup(client_addr, visit_addr)) => {
+                Poto::Bind(Bind::Setup(client_addr, visit_addr, name)) => {
                     log::debug!("try to bind the server to {}", visit_addr);
-                    let visit_fut = processor.bind(visit_addr);
+
+                    // 通过tls连接的客户端, 数据通道同样使用tls
+                    let client_addr = match client.local_addr()? {
+                        Address::One(local) if local.is_tls() => {
+                            client_addr.with_kind(SocketKind::Tls)
+                        }
+                        _ => client_addr,
+                    };
+
+                    let visit_fut = bind_visitor(
+                        processor.clone(),
+                        (current.clone(), assignments.clone()),
+                        (name, token.clone()),
+                        visit_addr,
+                    );
+
                     let client_fut = processor.bind(client_addr);
                     join::join_output(client_fut, visit_fut).await
                 }
@@ -535,13 +1205,94 @@ where
                         e
                     })?;
 
-                    let client_config = read_client_config!(client);
+                    let mut client_config = read_client_config!(client);
+                    let mappings = std::mem::take(&mut client_config.mappings);
 
                     config.update(client_config);
 
+                    let vhost = match (vhosts, config.domain.as_ref()) {
+                        (Some(vhosts), Some(domain)) => match vhosts.register(domain) {
+                            Ok(vhost) => Some(vhost),
+                            Err(e) => {
+                                log::warn!("failed to register domain err={}", e);
+                                processor.observer().on_pen_error(
+                                    &client.peer_addr()?,
+                                    &config,
+                                    &e,
+                                );
+                                return Err(e);
+                            }
+                        },
+                        (None, Some(domain)) => {
+                            log::warn!("ignore domain {}, the http port is not enabled", domain);
+                            None
+                        }
+                        _ => None,
+                    };
+
+                    if let Some(vhost) = vhost.as_ref() {
+                        log::info!("please visit http://{}", vhost.domain());
+                    }
+
+                    let mut mapped = Vec::with_capacity(mappings.len());
+
+                    for mapping in mappings.iter() {
+                        let name = format!("{}/{}", config.whoami, mapping.name);
+                        let bind_fut = bind_visitor(
+                            processor.clone(),
+                            (current.clone(), assignments.clone()),
+                            (name, token.clone()),
+                            mapping.visit.clone(),
+                        );
+
+                        match bind_fut.await {
+                            Ok(accepter) => mapped.push(accepter),
+                            Err(e) => {
+                                log::warn!("failed to create mapping {} err={}", mapping.name, e);
+
+                                let message = Poto::Bind(Bind::Failed(e.to_string())).bytes();
+
+                                if let Err(e) = client.send_packet(&message).await {
+                                    log::warn!(
+                                        "failed to send failure message to client err={}",
+                                        e
+                                    );
+                                }
+
+                                processor.observer().on_pen_error(
+                                    &client.peer_addr()?,
+                                    &config,
+                                    &e,
+                                );
+
+                                return Err(e);
+                            }
+                        }
+                    }
+
+                    if !mapped.is_empty() {
+                        let addrs = mapped
+                            .iter()
+                            .map(NetSocket::local_addr)
+                            .collect::<crate::Result<Vec<_>>>()?;
+
+                        let poto = Poto::Bind(Bind::Mapped(addrs));
+
+                        client.send_packet(&poto.bytes()).await.map_err(|e| {
+                            log::warn!("failed to send message to client err={}", e);
+                            e
+                        })?;
+                    }
+
+                    let visitor = mapped
+                        .iter()
+                        .try_fold(avisit.local_addr()?, |addr, accepter| {
+                            Ok::<_, Error>(addr + accepter.local_addr()?)
+                        })?;
+
                     processor.observer().on_pen_start(
                         &client.peer_addr()?,
-                        &avisit.local_addr()?,
+                        &visitor,
                         &aclient.local_addr()?,
                         &config,
                     );
@@ -554,29 +1305,179 @@ where
 
                     log::info!("please visit {} for port mapping", avisit.local_addr()?);
 
-                    let environ: Environ = Arc::new(PenetrateEnviron {
+                    for (mapping, accepter) in mappings.iter().zip(mapped.iter()) {
+                        log::info!(
+                            "please visit {} for {}",
+                            accepter.local_addr()?,
+                            mapping.name
+                        );
+                    }
+
+                    let environ = Arc::new(PenetrateEnviron {
                         conn: client.peer_addr()?,
                         config: config.clone(),
                         client: aclient.local_addr()?,
-                        visitor: avisit.local_addr()?,
+                        visitor,
+                        traffic: Arc::new(Traffic::new(&config.whoami)),
                     });
 
-                    let generator = PenetrateGenerator(Penetrate::new(
+                    let configs = mappings
+                        .iter()
+                        .map(|mapping| config.mapping(mapping))
+                        .collect::<Vec<_>>();
+
+                    let mut penetrate = Penetrate::new(
                         config,
                         peer_provider,
                         processor,
                         client.peer_addr()?,
                         client,
                         PenetrateAccepter::new(avisit, aclient),
-                    ));
+                        environ.clone(),
+                    )
+                    .with_vhost(vhost)
+                    .with_settings(settings)
+                    .with_throttle(throttle)
+                    .with_slot(slot)
+                    .with_limits(limits);
+
+                    for (id, (accepter, config)) in mapped.into_iter().zip(configs).enumerate() {
+                        penetrate = penetrate.with_mapping(
+                            PenetrateAccepter::mapping(id as u32 + 1, accepter),
+                            config,
+                        );
+                    }
 
-                    Ok((generator, environ))
+                    let generator = PenetrateGenerator(penetrate);
+
+                    Ok((generator, environ as Environ))
                 }
             }
         })
     }
 }
 
+/// 是否以http请求行开始, 即方法名后跟一个空格
+fn is_http_request(buf: &[u8]) -> bool {
+    const METHODS: [&[u8]; 9] = [
+        b"GET ",
+        b"POST ",
+        b"HEAD ",
+        b"PUT ",
+        b"DELETE ",
+        b"OPTIONS ",
+        b"PATCH ",
+        b"CONNECT ",
+        b"TRACE ",
+    ];
+
+    METHODS.iter().any(|method| buf.starts_with(method))
+}
+
+/// 只有访问者发送了http请求时才返回page, 其他协议直接关闭
+async fn write_suspend_page<T>(visitor: Fallback<T>, page: String) -> crate::Result<()>
+where
+    T: Stream + Send + 'static,
+{
+    let (mut visitor, is_http) = time::wait_for(Duration::from_secs(1), async move {
+        let mut visitor = visitor;
+        let mut buf = [0u8; 1024];
+        let n = visitor.read(&mut buf).await?;
+        let is_http = is_http_request(&buf[..n]);
+        Ok::<_, crate::Error>((visitor, is_http))
+    })
+    .await??;
+
+    if !is_http {
+        return visitor.close().await;
+    }
+
+    let response = format!(
+        "HTTP/1.1 503 Service Unavailable\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        page.len(),
+        page
+    );
+
+    visitor.write_all(response.as_bytes()).await?;
+    visitor.close().await
+}
+
+/// 客户端可以使用的预留端口的名称, 客户端名称由客户端自己指定,
+/// 只有通过密钥认证后才能使用为名称预留的端口
+fn reservation_keys(token: &Option<String>, name: &str) -> Vec<String> {
+    match token {
+        Some(token) => vec![token.clone(), name.to_owned()],
+        None => Vec::new(),
+    }
+}
+
+/// 按照端口策略绑定访问端口, 违反策略时返回 Kind::Forbidden
+async fn bind_visitor<P, S, O, A>(
+    processor: Processor<P, S, O>,
+    (settings, assignments): (Arc<Settings>, Assignments),
+    (name, token): (String, Option<String>),
+    mut visit: Socket,
+) -> crate::Result<A>
+where
+    A: NetSocket,
+    P: Provider<Socket, Output = BoxedFuture<A>>,
+{
+    // 由系统分配的端口同样需要符合策略, 不符合时重新分配
+    const MAXIMUM_ATTEMPTS: usize = 16;
+
+    let keys = reservation_keys(&token, &name);
+    let keys = keys.iter().map(String::as_str).collect::<Vec<_>>();
+
+    // 只为通过密钥认证的客户端记录端口, 避免冒用名称获取他人的端口
+    let identity = token.as_deref();
+
+    let last = assignments.last(identity, &name);
+
+    let candidates = settings
+        .ports
+        .candidates(&name, &keys, visit.port(), last)
+        .map_err(Kind::Forbidden)?;
+
+    let single = candidates.len() == 1 && candidates[0] != 0;
+    let mut last_err = None;
+
+    for port in candidates {
+        let attempts = if port == 0 { MAXIMUM_ATTEMPTS } else { 1 };
+
+        for _ in 0..attempts {
+            visit.set_port(port);
+
+            let accepter = match processor.bind(visit.clone()).await {
+                Ok(accepter) => accepter,
+                Err(e) if single => return Err(e),
+                Err(e) => {
+                    log::debug!("failed to bind port {} err={}", port, e);
+                    last_err = Some(e);
+                    break;
+                }
+            };
+
+            let bound = match accepter.local_addr()? {
+                Address::One(socket) => socket.port(),
+                Address::Many(_) => port,
+            };
+
+            match settings.ports.permits(&keys, bound) {
+                Ok(()) => {
+                    assignments.record(identity, &name, bound);
+                    return Ok(accepter);
+                }
+                Err(reason) => {
+                    log::debug!("drop the port assigned by the system, {}", reason);
+                    last_err = Some(Kind::Forbidden(reason).into());
+                }
+            }
+        }
+    }
+
+    Err(last_err.unwrap_or_else(|| Kind::Forbidden(String::from("no port available")).into()))
+}
+
 impl<P, T, A, O> Generator for PenetrateGenerator<P, T, A, O>
 where
     A: Accepter<Stream = T> + Send + Unpin + 'static,
@@ -590,14 +1491,21 @@ where
         mut self: Pin<&mut Self>,
         cx: &mut std::task::Context,
     ) -> Poll<crate::Result<Self::Output>> {
+        let traffic = self.0.traffic.clone();
+
         match ready!(Pin::new(&mut self.0).poll_accept(cx)?) {
             Outcome::Future(fut) => {
                 log::debug!("start a future");
-                Poll::Ready(Ok(Some(fut)))
+                Poll::Ready(Ok(Some(Box::pin(async move {
+                    let _conn = traffic.connect();
+                    fut.await
+                }))))
             }
-            Outcome::Route(s1, s2) => Poll::Ready(Ok(Some(Box::pin(async move {
+            Outcome::Route(s1, s2, lease, idle) => Poll::Ready(Ok(Some(Box::pin(async move {
                 log::debug!("start forwarding");
-                if let Err(e) = io::forward(s1, s2).await {
+                let _conn = traffic.connect();
+                let s1 = Limited::new(Counter::new(s1, traffic.clone()), lease);
+                if let Err(e) = idle.forward(s1, s2).await {
                     log::trace!("forward error {}", e);
                 };
                 Ok(())
@@ -614,14 +1522,97 @@ impl crate::Environ for PenetrateEnviron {
     fn info(&self) -> crate::Result<serde_json::Value> {
         Ok(serde_json::to_value(self).unwrap())
     }
+
+    fn visitor(&self) -> Option<Address> {
+        Some(self.visitor.clone())
+    }
+
+    fn client(&self) -> Option<Address> {
+        Some(self.client.clone())
+    }
 }
 
 impl Display for Config {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "read_timeout = {:?}, write_timeout = {:?}, max_wait_time={:?}, heartbeat_time={:?}",
-            self.read_timeout, self.write_timeout, self.maximum_wait, self.heartbeat_delay
+            "max_wait_time={:?}, heartbeat_time={:?}",
+            self.maximum_wait, self.heartbeat_delay
         )
     }
 }
+
+#[cfg(test)]
+#[cfg(feature = "fuso-rt-tokio")]
+mod tests {
+    use tokio::net::{TcpListener, TcpStream};
+
+    use super::{is_http_request, write_suspend_page, Suspended};
+    use crate::{
+        ext::{AsyncReadExt, AsyncWriteExt},
+        guard::Fallback,
+    };
+
+    #[test]
+    fn test_suspended() {
+        let suspended = Suspended::default();
+        assert_eq!(suspended.get(1), None);
+
+        suspended.suspend(1, Some(String::from("page")));
+        suspended.suspend(2, None);
+        assert_eq!(suspended.get(1), Some(Some(String::from("page"))));
+        assert_eq!(suspended.clone().get(2), Some(None));
+
+        suspended.resume(1);
+        assert_eq!(suspended.get(1), None);
+        assert_eq!(suspended.get(2), Some(None));
+
+        assert!(is_http_request(b"GET / HTTP/1.1\r\n"));
+        assert!(is_http_request(b"OPTIONS * HTTP/1.1\r\n"));
+        assert!(!is_http_request(b"GETX / HTTP/1.1\r\n"));
+        assert!(!is_http_request(b"SSH-2.0-OpenSSH_8.9\r\n"));
+    }
+
+    async fn read_all(stream: &mut TcpStream) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut buf = [0u8; 1024];
+        loop {
+            match stream.read(&mut buf).await.unwrap() {
+                0 => return data,
+                n => data.extend_from_slice(&buf[..n]),
+            }
+        }
+    }
+
+    #[test]
+    fn test_write_suspend_page() {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+                let addr = listener.local_addr().unwrap();
+
+                let mut visitor = TcpStream::connect(addr).await.unwrap();
+                let (stream, _) = listener.accept().await.unwrap();
+
+                visitor.write_all(b"GET / HTTP/1.1\r\n\r\n").await.unwrap();
+                write_suspend_page(Fallback::new(stream, false), String::from("paused"))
+                    .await
+                    .unwrap();
+
+                let response = String::from_utf8(read_all(&mut visitor).await).unwrap();
+                assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+                assert!(response.ends_with("\r\n\r\npaused"));
+
+                let mut visitor = TcpStream::connect(addr).await.unwrap();
+                let (stream, _) = listener.accept().await.unwrap();
+
+                visitor.write_all(b"SSH-2.0-OpenSSH\r\n").await.unwrap();
+                write_suspend_page(Fallback::new(stream, false), String::from("paused"))
+                    .await
+                    .unwrap();
+
+                assert!(read_all(&mut visitor).await.is_empty());
+            });
+    }
+}
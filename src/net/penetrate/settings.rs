@@ -0,0 +1,158 @@
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use super::{AccessList, PortPolicy};
+
+/// 服务端运行时可以替换的配置
+#[derive(Debug, Clone)]
+pub struct Settings {
+    /// 客户端验证密钥, 为空时不验证
+    pub(crate) secrets: Arc<Vec<String>>,
+    /// 等待客户端建立连接的最大时间, 客户端指定的时间不会超过该值
+    pub(crate) max_wait_time: Duration,
+    pub(crate) fallback_strict_mode: bool,
+    /// 访问端口的分配策略
+    pub(crate) ports: PortPolicy,
+    /// 访问者的黑白名单, 对所有映射生效
+    pub(crate) access: AccessList,
+    /// 同时在线的客户端数量, 0表示不限制
+    pub(crate) max_clients: usize,
+    /// 每个映射同时访问的数量, 0表示不限制
+    pub(crate) max_visitors: usize,
+    /// 每个访问者ip每秒新建的连接数, 0表示不限制
+    pub(crate) max_visitor_rate: u32,
+    /// 访问者的空闲超时, 客户端也指定时取较小的值
+    pub(crate) idle_timeout: Option<Duration>,
+    /// 没有空闲超时时, 访问者半关闭后另一个方向没有数据超过linger则关闭
+    pub(crate) linger: Duration,
+    /// 客户端的心跳超时, 只作用于之后连接的客户端
+    pub(crate) heartbeat_timeout: Duration,
+}
+
+/// 持有最新的配置, 新的连接以及已建立的会话在使用时读取, 替换后立即生效
+#[derive(Clone, Default)]
+pub struct Reloader(Arc<RwLock<Arc<Settings>>>);
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            secrets: Default::default(),
+            max_wait_time: Duration::from_secs(10),
+            fallback_strict_mode: true,
+            ports: PortPolicy::default(),
+            access: AccessList::default(),
+            max_clients: 0,
+            max_visitors: 0,
+            max_visitor_rate: 0,
+            idle_timeout: None,
+            linger: Duration::from_secs(30),
+            heartbeat_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+impl Settings {
+    pub fn max_wait_time(mut self, time: Duration) -> Self {
+        self.max_wait_time = time.min(Duration::from_secs(10));
+        self
+    }
+
+    pub fn fallback_strict_mode(mut self, enable: bool) -> Self {
+        self.fallback_strict_mode = enable;
+        self
+    }
+
+    pub fn set_secrets(mut self, secrets: Vec<String>) -> Self {
+        self.secrets = Arc::new(secrets);
+        self
+    }
+
+    pub fn set_port_policy(mut self, policy: PortPolicy) -> Self {
+        self.ports = policy;
+        self
+    }
+
+    pub fn set_access_list(mut self, access: AccessList) -> Self {
+        self.access = access;
+        self
+    }
+
+    pub fn max_clients(mut self, maximum: usize) -> Self {
+        self.max_clients = maximum;
+        self
+    }
+
+    pub fn max_visitors(mut self, maximum: usize) -> Self {
+        self.max_visitors = maximum;
+        self
+    }
+
+    pub fn max_visitor_rate(mut self, rate: u32) -> Self {
+        self.max_visitor_rate = rate;
+        self
+    }
+
+    pub fn idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    pub fn linger(mut self, linger: Duration) -> Self {
+        self.linger = linger;
+        self
+    }
+
+    pub fn heartbeat_timeout(mut self, time: Duration) -> Self {
+        self.heartbeat_timeout = time.min(Duration::from_secs(60));
+        self
+    }
+}
+
+impl Reloader {
+    pub fn new(settings: Settings) -> Self {
+        Self(Arc::new(RwLock::new(Arc::new(settings))))
+    }
+
+    pub fn current(&self) -> Arc<Settings> {
+        match self.0.read() {
+            Ok(settings) => settings.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    pub fn reload(&self, settings: Settings) {
+        match self.0.write() {
+            Ok(mut current) => *current = Arc::new(settings),
+            Err(poisoned) => *poisoned.into_inner() = Arc::new(settings),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Reloader, Settings};
+
+    #[test]
+    fn test_reload() {
+        let reloader = Reloader::new(Settings::default().max_visitors(1));
+        let shared = reloader.clone();
+        let before = reloader.current();
+
+        reloader.reload(
+            Settings::default()
+                .max_visitors(2)
+                .linger(Duration::from_secs(5))
+                .heartbeat_timeout(Duration::from_secs(120)),
+        );
+
+        // 已经取得的配置不变, 之后读取的都是新的配置
+        assert_eq!(before.max_visitors, 1);
+        assert_eq!(shared.current().max_visitors, 2);
+        assert_eq!(shared.current().linger, Duration::from_secs(5));
+        assert_eq!(shared.current().heartbeat_timeout, Duration::from_secs(60));
+    }
+}
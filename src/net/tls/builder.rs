@@ -0,0 +1,160 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::Poll,
+};
+
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::TlsAcceptor;
+
+use crate::{
+    client::ClientBuilder, mixing::MixListener, server::ServerBuilder, Accepter, Address,
+    ClientProvider, Executor, FusoStream, Kind, NetSocket, Provider, Socket, ToBoxStream,
+};
+
+use super::{TlsClientConfig, TlsServerConfig, TlsStream};
+
+type BoxedFuture<T> = Pin<Box<dyn Future<Output = crate::Result<T>> + Send + 'static>>;
+
+/// tls监听, 与服务端的其他监听共用同一个处理流程
+pub struct TlsAccepterProvider {
+    listen: Socket,
+    acceptor: Option<TlsAcceptor>,
+    /// 服务端的tls监听只启动一次, 之后只处理tls类型的监听(数据通道)
+    started: AtomicBool,
+}
+
+pub struct TlsAccepter {
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+}
+
+/// 通过tls连接服务端, 非tcp连接(如kcp)交给原有的连接器
+pub struct TlsConnector<C> {
+    config: Option<TlsClientConfig>,
+    connector: Arc<C>,
+}
+
+impl NetSocket for TlsAccepter {
+    fn peer_addr(&self) -> crate::Result<Address> {
+        Ok(Address::One(Socket::tls(self.listener.local_addr()?)))
+    }
+
+    fn local_addr(&self) -> crate::Result<Address> {
+        Ok(Address::One(Socket::tls(self.listener.local_addr()?)))
+    }
+}
+
+impl Accepter for TlsAccepter {
+    type Stream = FusoStream;
+
+    fn poll_accept(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<crate::Result<Self::Stream>> {
+        match self.listener.poll_accept(cx) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e.into())),
+            Poll::Ready(Ok((tcp, addr))) => {
+                log::debug!("accept tls connection from {}", addr);
+                Poll::Ready(Ok(
+                    TlsStream::accept(tcp, &self.acceptor)?.into_boxed_stream()
+                ))
+            }
+        }
+    }
+}
+
+impl Provider<Socket> for TlsAccepterProvider {
+    type Output = BoxedFuture<TlsAccepter>;
+
+    fn call(&self, socket: Socket) -> Self::Output {
+        let acceptor = match self.acceptor.clone() {
+            Some(acceptor) => acceptor,
+            None => return Box::pin(async move { Err(Kind::Unsupported(socket).into()) }),
+        };
+
+        let listen = if socket.is_tls() {
+            socket
+        } else if !self.started.swap(true, Ordering::SeqCst) {
+            self.listen.clone()
+        } else {
+            return Box::pin(async move { Err(Kind::Unsupported(socket).into()) });
+        };
+
+        Box::pin(async move {
+            let listener = TcpListener::bind(listen.as_string()).await?;
+            log::debug!("tls listening on {}", listener.local_addr()?);
+            Ok(TlsAccepter { listener, acceptor })
+        })
+    }
+}
+
+impl<C> Provider<Socket> for TlsConnector<C>
+where
+    C: Provider<Socket, Output = BoxedFuture<FusoStream>> + Send + Sync + 'static,
+{
+    type Output = BoxedFuture<FusoStream>;
+
+    fn call(&self, socket: Socket) -> Self::Output {
+        match self.config.clone() {
+            Some(config) if socket.is_tcp() || socket.is_tls() => Box::pin(async move {
+                let tcp = TcpStream::connect(socket.as_string()).await?;
+                Ok(TlsStream::connect(tcp, &socket, &config)
+                    .await?
+                    .into_boxed_stream())
+            }),
+            _ => self.connector.call(socket),
+        }
+    }
+}
+
+impl<E, SP, A1, O> ServerBuilder<E, SP, FusoStream, O>
+where
+    SP: Provider<Socket, Output = BoxedFuture<A1>> + Send + Sync + 'static,
+    A1: Accepter<Stream = FusoStream> + Unpin + Send + 'static,
+{
+    /// 额外监听一个tls端口, config为None时不启用
+    pub fn using_tls<A: Into<Socket>>(
+        self,
+        listen: A,
+        config: Option<TlsServerConfig>,
+    ) -> ServerBuilder<E, MixListener<SP, TlsAccepterProvider, FusoStream>, FusoStream, O> {
+        self.add_accepter(TlsAccepterProvider {
+            listen: listen.into(),
+            acceptor: config.map(|config| TlsAcceptor::from(config.0)),
+            started: AtomicBool::new(false),
+        })
+    }
+}
+
+impl<E, P> ClientBuilder<E, P, FusoStream>
+where
+    E: Executor + 'static,
+    P: Provider<Socket, Output = BoxedFuture<FusoStream>> + Send + Sync + 'static,
+{
+    /// 与服务端之间的连接使用tls, config为None时不启用
+    pub fn using_tls(
+        self,
+        config: Option<TlsClientConfig>,
+    ) -> ClientBuilder<E, TlsConnector<P>, FusoStream> {
+        ClientBuilder {
+            executor: self.executor,
+            reconnect: self.reconnect,
+            backup_servers: self.backup_servers,
+            failover: self.failover,
+            handshake: self.handshake,
+            client_provider: ClientProvider {
+                server_address: self.client_provider.server_address,
+                connect_provider: Arc::new(TlsConnector {
+                    config,
+                    connector: self.client_provider.connect_provider,
+                }),
+            },
+        }
+    }
+}
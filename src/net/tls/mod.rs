@@ -0,0 +1,373 @@
+mod builder;
+
+pub use builder::*;
+
+use std::{
+    future::Future, io::BufReader, path::Path, pin::Pin, sync::Arc, task::Poll, time::Duration,
+};
+
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::{
+    server::AllowAnyAuthenticatedClient, Certificate, ClientConfig, OwnedTrustAnchor, PrivateKey,
+    RootCertStore, ServerConfig, ServerName,
+};
+
+use crate::{time, Address, Kind, NetSocket, Socket, SocketKind};
+
+type AcceptFuture = Pin<
+    Box<dyn Future<Output = std::io::Result<tokio_rustls::server::TlsStream<TcpStream>>> + Send>,
+>;
+
+/// 服务端等待tls握手完成的最长时间
+const ACCEPT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 服务端tls配置
+#[derive(Clone)]
+pub struct TlsServerConfig(Arc<ServerConfig>);
+
+/// 客户端tls配置
+#[derive(Clone)]
+pub struct TlsClientConfig {
+    config: Arc<ClientConfig>,
+    /// 握手时使用的服务名, 不指定时使用连接地址
+    sni: Option<String>,
+}
+
+enum State {
+    /// 服务端正在进行tls握手
+    Accepting(AcceptFuture),
+    Established(Box<tokio_rustls::TlsStream<TcpStream>>),
+}
+
+/// 基于tcp的tls连接
+pub struct TlsStream {
+    local_addr: Socket,
+    peer_addr: Socket,
+    state: State,
+    /// 已交给tls但尚未发送完成的长度
+    wlen: Option<usize>,
+}
+
+fn load_certs(path: &Path) -> crate::Result<Vec<Certificate>> {
+    let mut reader = BufReader::new(std::fs::File::open(path)?);
+
+    let certs = rustls_pemfile::certs(&mut reader)?
+        .into_iter()
+        .map(Certificate)
+        .collect::<Vec<_>>();
+
+    if certs.is_empty() {
+        Err(Kind::Tls(format!("no certificate found in {}", path.display())).into())
+    } else {
+        Ok(certs)
+    }
+}
+
+fn load_private_key(path: &Path) -> crate::Result<PrivateKey> {
+    let mut reader = BufReader::new(std::fs::File::open(path)?);
+
+    loop {
+        match rustls_pemfile::read_one(&mut reader)? {
+            Some(rustls_pemfile::Item::PKCS8Key(key))
+            | Some(rustls_pemfile::Item::RSAKey(key))
+            | Some(rustls_pemfile::Item::ECKey(key)) => break Ok(PrivateKey(key)),
+            Some(_) => continue,
+            None => {
+                break Err(Kind::Tls(format!("no private key found in {}", path.display())).into())
+            }
+        }
+    }
+}
+
+fn load_roots(path: &Path) -> crate::Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+
+    for cert in load_certs(path)? {
+        roots.add(&cert)?;
+    }
+
+    Ok(roots)
+}
+
+impl TlsServerConfig {
+    /// 加载服务端证书与私钥, 指定 client_ca 时要求客户端提供由其签发的证书
+    pub fn load<P: AsRef<Path>>(cert: P, key: P, client_ca: Option<P>) -> crate::Result<Self> {
+        let certs = load_certs(cert.as_ref())?;
+        let key = load_private_key(key.as_ref())?;
+
+        let builder = ServerConfig::builder().with_safe_defaults();
+
+        let config = match client_ca {
+            None => builder.with_no_client_auth(),
+            Some(client_ca) => builder.with_client_cert_verifier(
+                AllowAnyAuthenticatedClient::new(load_roots(client_ca.as_ref())?).boxed(),
+            ),
+        }
+        .with_single_cert(certs, key)?;
+
+        Ok(Self(Arc::new(config)))
+    }
+}
+
+impl TlsClientConfig {
+    /// 未指定 ca 时使用内置的根证书, 指定 identity 时向服务端提供客户端证书
+    pub fn load<P: AsRef<Path>>(
+        ca: Option<P>,
+        identity: Option<(P, P)>,
+        sni: Option<String>,
+    ) -> crate::Result<Self> {
+        let roots = match ca {
+            Some(ca) => load_roots(ca.as_ref())?,
+            None => {
+                let mut roots = RootCertStore::empty();
+                roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+                    OwnedTrustAnchor::from_subject_spki_name_constraints(
+                        ta.subject,
+                        ta.spki,
+                        ta.name_constraints,
+                    )
+                }));
+                roots
+            }
+        };
+
+        let builder = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots);
+
+        let config = match identity {
+            None => builder.with_no_client_auth(),
+            Some((cert, key)) => builder.with_client_auth_cert(
+                load_certs(cert.as_ref())?,
+                load_private_key(key.as_ref())?,
+            )?,
+        };
+
+        Ok(Self {
+            config: Arc::new(config),
+            sni,
+        })
+    }
+
+    /// 优先使用指定的服务名, 否则使用连接的地址
+    fn server_name(&self, socket: &Socket) -> crate::Result<ServerName> {
+        let name = match (self.sni.as_ref(), socket.ip()) {
+            (Some(sni), _) => sni.clone(),
+            (None, Some(ip)) => ip.to_string(),
+            (None, None) => socket.domain().unwrap_or_default().to_string(),
+        };
+
+        ServerName::try_from(name.as_str())
+            .map_err(|_| Kind::Tls(format!("invalid server name {}", name)).into())
+    }
+}
+
+impl TlsStream {
+    pub(crate) fn accept(
+        tcp: TcpStream,
+        acceptor: &tokio_rustls::TlsAcceptor,
+    ) -> crate::Result<Self> {
+        let (local_addr, peer_addr) = (tcp.local_addr()?, tcp.peer_addr()?);
+
+        // 握手超时后关闭连接, 避免不发送数据的连接一直占用
+        let accept = acceptor.accept(tcp);
+        let accept = Box::pin(async move {
+            time::wait_for(ACCEPT_TIMEOUT, accept)
+                .await
+                .unwrap_or_else(|_| {
+                    Err(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        "tls handshake timeout",
+                    ))
+                })
+        });
+
+        Ok(Self {
+            local_addr: Socket::tcp(local_addr),
+            peer_addr: Socket::tcp(peer_addr),
+            state: State::Accepting(accept),
+            wlen: None,
+        })
+    }
+
+    pub(crate) async fn connect(
+        tcp: TcpStream,
+        socket: &Socket,
+        config: &TlsClientConfig,
+    ) -> crate::Result<Self> {
+        let server_name = config.server_name(socket)?;
+        let connector = tokio_rustls::TlsConnector::from(config.config.clone());
+
+        let (local_addr, peer_addr) = (tcp.local_addr()?, tcp.peer_addr()?);
+
+        let tls = connector
+            .connect(server_name, tcp)
+            .await
+            .map_err(|e| Kind::Tls(format!("failed to connect {}: {}", socket, e)))?;
+
+        Ok(Self {
+            local_addr: Socket::tcp(local_addr),
+            peer_addr: Socket::tcp(peer_addr),
+            state: State::Established(Box::new(tls.into())),
+            wlen: None,
+        })
+    }
+
+    /// 服务端在首次读写时完成tls握手, 避免阻塞 accept
+    fn poll_established(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<std::io::Result<(&mut tokio_rustls::TlsStream<TcpStream>, &mut Option<usize>)>> {
+        if let State::Accepting(accept) = &mut self.state {
+            let tls = match accept.as_mut().poll(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(e)) => {
+                    log::warn!("tls handshake failed from {}, err={}", self.peer_addr, e);
+                    return Poll::Ready(Err(e));
+                }
+                Poll::Ready(Ok(tls)) => tls,
+            };
+
+            self.state = State::Established(Box::new(tls.into()));
+        }
+
+        match &mut self.state {
+            State::Established(tls) => Poll::Ready(Ok((tls, &mut self.wlen))),
+            State::Accepting(_) => unreachable!(),
+        }
+    }
+}
+
+impl NetSocket for TlsStream {
+    fn peer_addr(&self) -> crate::Result<Address> {
+        Ok(Address::One(self.peer_addr.clone().with_kind(SocketKind::Tls)))
+    }
+
+    fn local_addr(&self) -> crate::Result<Address> {
+        Ok(Address::One(self.local_addr.clone().with_kind(SocketKind::Tls)))
+    }
+}
+
+impl tokio::io::AsyncRead for TlsStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        match self.poll_established(cx)? {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready((tls, _)) => Pin::new(tls).poll_read(cx, buf),
+        }
+    }
+}
+
+impl tokio::io::AsyncWrite for TlsStream {
+    /// 上层不会主动flush, 数据发送完成后才返回写入的长度
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let (tls, wlen) = match self.poll_established(cx)? {
+            Poll::Pending => return Poll::Pending,
+            Poll::Ready(established) => established,
+        };
+
+        let n = match wlen.take() {
+            Some(n) => n,
+            None => match Pin::new(&mut *tls).poll_write(cx, buf)? {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(n) => n,
+            },
+        };
+
+        match Pin::new(&mut *tls).poll_flush(cx)? {
+            Poll::Ready(()) => Poll::Ready(Ok(n)),
+            Poll::Pending => {
+                wlen.replace(n);
+                Poll::Pending
+            }
+        }
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        match self.poll_established(cx)? {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready((tls, _)) => Pin::new(tls).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        match self.poll_established(cx)? {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready((tls, _)) => Pin::new(tls).poll_shutdown(cx),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_rustls::TlsAcceptor;
+
+    use crate::{
+        ext::{AsyncReadExt, AsyncWriteExt},
+        Socket,
+    };
+
+    use super::{TlsClientConfig, TlsServerConfig, TlsStream};
+
+    fn testdata(name: &str) -> String {
+        format!("{}/src/net/tls/test/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    #[test]
+    fn test_tls_loopback() {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let server_config =
+                    TlsServerConfig::load(testdata("server.pem"), testdata("server.key"), None)
+                        .unwrap();
+
+                let client_config =
+                    TlsClientConfig::load(Some(testdata("ca.pem")), None, Some("localhost".into()))
+                        .unwrap();
+
+                let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+                let addr = listener.local_addr().unwrap();
+                let acceptor = TlsAcceptor::from(server_config.0);
+
+                let server = async move {
+                    let (tcp, _) = listener.accept().await.unwrap();
+                    let mut tls = TlsStream::accept(tcp, &acceptor).unwrap();
+
+                    let mut buf = [0u8; 4];
+                    tls.read_exact(&mut buf).await.unwrap();
+                    assert_eq!(&buf, b"ping");
+                    tls.write_all(b"pong").await.unwrap();
+                };
+
+                let client = async move {
+                    let tcp = TcpStream::connect(addr).await.unwrap();
+                    let mut tls = TlsStream::connect(tcp, &Socket::tls(addr), &client_config)
+                        .await
+                        .unwrap();
+
+                    tls.write_all(b"ping").await.unwrap();
+
+                    let mut buf = [0u8; 4];
+                    tls.read_exact(&mut buf).await.unwrap();
+                    assert_eq!(&buf, b"pong");
+                };
+
+                tokio::join!(server, client);
+            });
+    }
+}
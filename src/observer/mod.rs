@@ -1,4 +1,9 @@
-use std::{future::Future, pin::Pin};
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
 
 use serde_json::json;
 
@@ -6,8 +11,9 @@ use crate::{penetrate::PenetrateObserver, Executor, Observer};
 
 type BoxedFuture = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
 
+#[derive(Clone)]
 pub struct Executable<E> {
-    prog: Option<String>,
+    prog: Arc<RwLock<Option<String>>>,
     executor: E,
 }
 
@@ -16,7 +22,17 @@ where
     E: Executor + 'static,
 {
     pub fn new(prog: Option<String>, executor: E) -> Self {
-        Self { prog, executor }
+        Self {
+            prog: Arc::new(RwLock::new(prog)),
+            executor,
+        }
+    }
+
+    /// 替换通知的程序, 所有的克隆共享同一个程序
+    pub fn reload(&self, prog: Option<String>) {
+        if let Ok(mut current) = self.prog.write() {
+            *current = prog;
+        }
     }
 }
 
@@ -25,7 +41,7 @@ where
     E: Executor,
 {
     fn do_exec<S: ToString + Send + 'static>(&self, data: Vec<S>) -> BoxedFuture {
-        let prog = self.prog.clone();
+        let prog = self.prog.read().ok().and_then(|prog| prog.clone());
         Box::pin(async move {
             match prog {
                 Some(prog) => {
@@ -168,4 +184,62 @@ where
             }
         })]));
     }
+
+    fn on_pen_deny(
+        &self,
+        client: &crate::Address,
+        visit: &crate::Address,
+        config: &crate::penetrate::server::Config,
+        reason: &str,
+    ) where
+        Self: Sized,
+    {
+        self.executor.spawn(self.do_exec(vec![json!({
+            "on": "pen_deny",
+            "data": {
+                "client": client,
+                "visit": visit,
+                "reason": reason,
+                "config": config
+            }
+        })]));
+    }
+
+    fn on_pen_reject(
+        &self,
+        client: &crate::Address,
+        config: &crate::penetrate::server::Config,
+        reason: &str,
+    ) where
+        Self: Sized,
+    {
+        self.executor.spawn(self.do_exec(vec![json!({
+            "on": "pen_reject",
+            "data": {
+                "client": client,
+                "reason": reason,
+                "config": config
+            }
+        })]));
+    }
+
+    fn on_pen_idle(
+        &self,
+        client: &crate::Address,
+        visit: &crate::Address,
+        config: &crate::penetrate::server::Config,
+        timeout: Duration,
+    ) where
+        Self: Sized,
+    {
+        self.executor.spawn(self.do_exec(vec![json!({
+            "on": "pen_idle",
+            "data": {
+                "client": client,
+                "visit": visit,
+                "timeout": timeout.as_secs(),
+                "config": config
+            }
+        })]));
+    }
 }
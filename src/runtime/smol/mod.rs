@@ -226,8 +226,9 @@ pub async fn builder_client(
     Ok(client::ClientBuilder {
         executor: FusoExecutor,
         handshake: None,
-        retry_delay: None,
-        maximum_retries: None,
+        reconnect: Default::default(),
+        backup_servers: Vec::new(),
+        failover: client::Failover::Priority,
         client_provider: ClientProvider {
             server_address: Default::default(),
             connect_provider: Arc::new({
@@ -47,7 +47,7 @@ impl Provider<Socket> for FusoAccepter {
     type Output = BoxedFuture<FusoTcpListener>;
 
     fn call(&self, socket: Socket) -> Self::Output {
-        if socket.is_tcp() || socket.is_mixed() {
+        if !socket.is_tls() && (socket.is_tcp() || socket.is_mixed()) {
             Box::pin(async move {
                 Ok({
                     TcpListener::bind(socket.as_string())
@@ -260,7 +260,8 @@ pub async fn builder_client(
         executor: FusoExecutor,
         handshake: None,
         client_provider: ClientProvider::with_tokio().await?,
-        retry_delay: None,
-        maximum_retries: None,
+        reconnect: Default::default(),
+        backup_servers: Vec::new(),
+        failover: client::Failover::Priority,
     })
 }
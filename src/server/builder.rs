@@ -50,6 +50,7 @@ where
             observer: self.observer,
             handshake: self.handshake.map(Arc::new),
             controller: None,
+            shutdown: Default::default(),
         })
     }
 }
@@ -3,12 +3,18 @@ mod builder;
 pub use builder::*;
 
 use crate::{
-    generator::GeneratorEx, Controller, DecorateProvider, Observer, Processor, Serve, Socket,
-    WrappedProvider,
+    generator::GeneratorEx, Controller, DecorateProvider, Observer, Processor, Serve, Shutdown,
+    Socket, WrappedProvider,
+};
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::Poll,
 };
-use std::{pin::Pin, sync::Arc};
 
-use crate::{generator::Generator, Accepter, AccepterExt, Executor, Fuso, Provider, Stream};
+use crate::{generator::Generator, Accepter, Executor, Fuso, Provider, Stream, Task};
 
 pub type Environ = Arc<dyn crate::core::Environ + Send + Sync + 'static>;
 pub type Handshake<S> = WrappedProvider<S, (S, Option<DecorateProvider<S>>)>;
@@ -23,6 +29,71 @@ pub struct Server<E, H, P, S, O> {
     pub(crate) observer: Option<Arc<O>>,
     pub(crate) handshake: Option<Arc<Handshake<S>>>,
     pub(crate) controller: Option<Arc<dyn Controller + Send + Sync + 'static>>,
+    pub(crate) shutdown: Shutdown,
+}
+
+/// 会话启动的转发, 会话被中断(drop)时一起中断, 正常结束时转发继续运行
+struct Forwards {
+    next_id: u64,
+    running: Arc<Mutex<HashMap<u64, Task<crate::Result<()>>>>>,
+    finished: bool,
+}
+
+impl Forwards {
+    fn new() -> Self {
+        Self {
+            next_id: 0,
+            running: Default::default(),
+            finished: false,
+        }
+    }
+
+    fn spawn<E, F>(&mut self, executor: &E, fut: F)
+    where
+        E: Executor,
+        F: Future<Output = crate::Result<()>> + Send + 'static,
+    {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let running = self.running.clone();
+
+        // 持有锁直到记录完成, 避免转发先结束导致无法移除
+        let mut guard = match self.running.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        let task = executor.spawn(async move {
+            let result = fut.await;
+
+            if let Ok(mut running) = running.lock() {
+                running.remove(&id);
+            }
+
+            result
+        });
+
+        guard.insert(id, task);
+    }
+
+    fn finish(mut self) {
+        self.finished = true;
+    }
+}
+
+impl Drop for Forwards {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+
+        if let Ok(mut running) = self.running.lock() {
+            for (_, mut task) in running.drain() {
+                task.abort();
+            }
+        }
+    }
 }
 
 impl<E, H, A, G, P, S, O> Server<E, H, P, S, O>
@@ -44,14 +115,27 @@ where
 
         log::info!("the server listens on {}", accepter.local_addr()?);
 
+        let mut stopped = Box::pin(self.shutdown.wait());
+
         loop {
-            let client = accepter.accept().await?;
+            // 关闭后不再接受新的客户端
+            let client = std::future::poll_fn(|cx| match stopped.as_mut().poll(cx) {
+                Poll::Ready(()) => Poll::Ready(Ok(None)),
+                Poll::Pending => Pin::new(&mut accepter).poll_accept(cx).map_ok(Some),
+            })
+            .await?;
+
+            let client = match client {
+                Some(client) => client,
+                None => break,
+            };
 
             let executor = self.executor.clone();
             let handshake = self.handshake.clone();
             let provider = self.provider.clone();
             let handler = self.handler.clone();
             let observer = self.observer.clone();
+            let shutdown = self.shutdown.clone();
 
             let client_addr = match client.peer_addr() {
                 Ok(addr) => addr,
@@ -76,19 +160,20 @@ where
                     }
                 };
 
-                observer.on_handshake(&client_addr);
-
                 let generator = match client {
                     Err(e) => {
                         log::warn!("handshake failed {}", e);
+                        observer.on_handshake_error(&e, &client_addr);
                         Err(e)
                     }
                     Ok((client, decorator)) => {
+                        observer.on_handshake(&client_addr);
                         log::debug!("start processing the connection");
                         handler
                             .call((
                                 client,
-                                Processor::new(provider, observer.clone(), decorator),
+                                Processor::new(provider, observer.clone(), decorator)
+                                    .with_shutdown(shutdown.clone()),
                             ))
                             .await
                     }
@@ -105,7 +190,8 @@ where
 
                 let run_fut = async move {
                     let mut generator = generator;
-                    loop {
+                    let mut forwards = Forwards::new();
+                    let result = loop {
                         match generator.next().await {
                             Ok(None) => {
                                 log::warn!("stop processing");
@@ -118,10 +204,13 @@ where
                                 break Err(e);
                             }
                             Ok(Some(fut)) => {
-                                executor.spawn(fut);
+                                forwards.spawn(&executor, shutdown.track(fut));
                             }
                         }
-                    }
+                    };
+
+                    forwards.finish();
+                    result
                 };
 
                 let err = match controller {
@@ -134,6 +223,20 @@ where
                 }
             });
         }
+
+        drop(accepter);
+
+        log::info!("stop accepting new clients, waiting for forwards to finish");
+
+        let remaining = self.shutdown.drain().await;
+
+        if remaining > 0 {
+            log::warn!("{} forwards are still running, force exit", remaining);
+        }
+
+        log::info!("the server has been shut down");
+
+        Ok(())
     }
 }
 
@@ -159,12 +262,16 @@ where
             handler: self.0.handler,
             observer: self.0.observer,
             controller: self.0.controller,
+            shutdown: self.0.shutdown,
         })
     }
 
     pub fn run(self) -> Fuso<Serve> {
+        let shutdown = self.0.shutdown.clone();
+
         Fuso(Serve {
             fut: Box::pin(self.0.run()),
+            shutdown,
         })
     }
 }